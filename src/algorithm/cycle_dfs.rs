@@ -0,0 +1,274 @@
+use std::collections::HashSet;
+use std::sync::Arc;
+use std::cmp::min;
+use crate::structure::{Graph, GraphPath};
+use super::dfs_parallel::{for_each_parallel, CONTINUE_THREADS};
+
+/// 把环旋转成以最小id开头的形式，同一个环只有一种表示
+pub fn canonical_cycle(cycle: &[i64]) -> GraphPath {
+    let mut ret = Vec::with_capacity(cycle.len());
+    if let Some((idx, _)) = cycle.iter().enumerate().min_by_key(|(_, v)| **v) {
+        ret.extend_from_slice(&cycle[idx..]);
+        ret.extend_from_slice(&cycle[..idx]);
+    }
+    ret
+}
+
+/// 从path的末尾继续往外探，找回到path[0]的简单环，环长（边数）最多再走k步
+/// 停止条件：
+///     1. 遇到起点，且环长不小于min_len，把环（规范旋转后）加入result
+///     2. 剩余步数不够再绕回起点，直接返回
+/// 参数说明：
+///     path：当前路径，path[0]是起点，环就是path本身（首尾相接）
+///     visit：path上的点，以及不允许经过的点
+///     result：找到的环，每个环只包含一次起点，不重复首尾
+/// 平行边只走一次，同一个点序列的环只报告一次
+pub fn cycle_dfs(
+    k: u32,
+    min_len: u32,
+    graph: &Graph,
+    result: &mut Vec<GraphPath>,
+    path: &mut GraphPath,
+    visit: &mut HashSet<i64>) {
+    let start = path[0];
+    let now = path[path.len() - 1];
+    if k == 0 {
+        return;
+    }
+    if let Some(nodes) = graph.out_v(now) {
+        let mut tried = HashSet::new();
+        for v in nodes.iter() {
+            if !tried.insert(*v) {
+                continue;
+            }
+            if *v == start {
+                if path.len() as u32 >= min_len {
+                    result.push(canonical_cycle(path));
+                }
+            } else if k >= 2 && !visit.contains(v) {
+                visit.insert(*v);
+                path.push(*v);
+                cycle_dfs(k - 1, min_len, graph, result, path, visit);
+                path.pop();
+                visit.remove(v);
+            }
+        }
+    }
+}
+
+/// 和cycle_dfs一样，但只探depth步，探满depth步的前缀放进temp_result，留给cycle_dfs继续
+/// 长度不超过depth的环直接放进result
+pub fn cycle_dfs_for_temp(
+    depth: u32,
+    min_len: u32,
+    graph: &Graph,
+    result: &mut Vec<GraphPath>,
+    temp_result: &mut Vec<GraphPath>,
+    path: &mut GraphPath,
+    visit: &mut HashSet<i64>) {
+    if depth == 0 {
+        temp_result.push(path.clone());
+        return;
+    }
+    let start = path[0];
+    let now = path[path.len() - 1];
+    if let Some(nodes) = graph.out_v(now) {
+        let mut tried = HashSet::new();
+        for v in nodes.iter() {
+            if !tried.insert(*v) {
+                continue;
+            }
+            if *v == start {
+                if path.len() as u32 >= min_len {
+                    result.push(canonical_cycle(path));
+                }
+            } else if !visit.contains(v) {
+                visit.insert(*v);
+                path.push(*v);
+                cycle_dfs_for_temp(depth - 1, min_len, graph, result, temp_result, path, visit);
+                path.pop();
+                visit.remove(v);
+            }
+        }
+    }
+}
+
+/// 起点start之前（id更小）的源点不允许经过，这样经过多个源点的环只会从最小的源点出发找到一次
+fn banned_visit(start: i64, sources: &[i64]) -> HashSet<i64> {
+    let mut visit: HashSet<i64> = sources.iter().filter(|s| **s < start).cloned().collect();
+    visit.insert(start);
+    visit
+}
+
+fn dedup_sources(sources: &[i64]) -> Vec<i64> {
+    let mut ret = sources.to_vec();
+    ret.sort_unstable();
+    ret.dedup();
+    ret
+}
+
+/// 所有经过x、长度在[min_len, max_len]之间的简单环
+pub fn cycles_through(
+    x: i64,
+    min_len: u32,
+    max_len: u32,
+    graph: &Graph,
+    result: &mut Vec<GraphPath>) {
+    cycles_through_set(&[x], min_len, max_len, graph, result);
+}
+
+/// 所有至少经过sources中一个点、长度在[min_len, max_len]之间的简单环，每个环只报告一次
+pub fn cycles_through_set(
+    sources: &[i64],
+    min_len: u32,
+    max_len: u32,
+    graph: &Graph,
+    result: &mut Vec<GraphPath>) {
+    let sources = dedup_sources(sources);
+    for s in sources.iter() {
+        let mut visit = banned_visit(*s, &sources);
+        let mut path = vec![*s];
+        cycle_dfs(max_len, min_len, graph, result, &mut path, &mut visit);
+    }
+}
+
+/// cycles_through_set的并行版本，和dfs_parallel一样先探min(3, max_len / 2)步切出前缀，
+/// 再用for_each_parallel把前缀分给线程继续探
+pub fn cycles_through_set_parallel(
+    sources: &[i64],
+    min_len: u32,
+    max_len: u32,
+    graph: Arc<Graph>,
+    result: &mut Vec<GraphPath>) {
+    let sources = dedup_sources(sources);
+    let depth = min(3, max_len / 2);
+    let mut temp_result = Vec::new();
+    for s in sources.iter() {
+        let mut visit = banned_visit(*s, &sources);
+        let mut path = vec![*s];
+        cycle_dfs_for_temp(depth, min_len, graph.as_ref(), result, &mut temp_result, &mut path, &mut visit);
+    }
+
+    let parts = for_each_parallel(temp_result, CONTINUE_THREADS, move |mut path: GraphPath, result: &mut Vec<GraphPath>| {
+        let mut visit = banned_visit(path[0], &sources);
+        visit.extend(path.iter().cloned());
+        let len = path.len() as u32 - 1;
+        cycle_dfs(max_len - len, min_len, graph.as_ref(), result, &mut path, &mut visit);
+    });
+    for mut part in parts {
+        result.append(&mut part);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_graph() -> Graph {
+        let mut graph = Graph::empty();
+        // 1 -> 2 -> 3 -> 1
+        graph.add_directed_edge(1, 2);
+        graph.add_directed_edge(2, 3);
+        graph.add_directed_edge(3, 1);
+        // 1 -> 4 -> 5 -> 6 -> 1
+        graph.add_directed_edge(1, 4);
+        graph.add_directed_edge(4, 5);
+        graph.add_directed_edge(5, 6);
+        graph.add_directed_edge(6, 1);
+        // 2 -> 5，组成 1 -> 2 -> 5 -> 6 -> 1
+        graph.add_directed_edge(2, 5);
+        // 3 <-> 7
+        graph.add_undirected_edge(3, 7);
+        // 8 -> 9 -> 8，不经过1
+        graph.add_directed_edge(8, 9);
+        graph.add_directed_edge(9, 8);
+        graph
+    }
+
+    #[test]
+    fn test_canonical_cycle() {
+        assert_eq!(canonical_cycle(&[5, 6, 1, 4]), vec![1, 4, 5, 6]);
+        assert_eq!(canonical_cycle(&[1, 2]), vec![1, 2]);
+        assert!(canonical_cycle(&[]).is_empty());
+    }
+
+    #[test]
+    fn test_cycles_through() {
+        let graph = test_graph();
+        let mut result = Vec::new();
+        cycles_through(1, 1, 4, &graph, &mut result);
+        assert_eq!(result.len(), 3);
+        assert!(result.contains(&vec![1, 2, 3]));
+        assert!(result.contains(&vec![1, 4, 5, 6]));
+        assert!(result.contains(&vec![1, 2, 5, 6]));
+
+        let mut result = Vec::new();
+        cycles_through(1, 4, 4, &graph, &mut result);
+        assert_eq!(result.len(), 2);
+
+        let mut result = Vec::new();
+        cycles_through(1, 1, 3, &graph, &mut result);
+        assert_eq!(result, vec![vec![1, 2, 3]]);
+
+        let mut result = Vec::new();
+        cycles_through(6, 1, 4, &graph, &mut result);
+        assert_eq!(result.len(), 2);
+        assert!(result.contains(&vec![1, 4, 5, 6]));
+        assert!(result.contains(&vec![1, 2, 5, 6]));
+
+        let mut result = Vec::new();
+        cycles_through(7, 2, 2, &graph, &mut result);
+        assert_eq!(result, vec![vec![3, 7]]);
+    }
+
+    #[test]
+    fn test_cycles_through_set() {
+        let graph = test_graph();
+        let mut result = Vec::new();
+        cycles_through_set(&[6, 3, 1, 8, 3], 2, 4, &graph, &mut result);
+        assert_eq!(result.len(), 5);
+        assert!(result.contains(&vec![1, 2, 3]));
+        assert!(result.contains(&vec![1, 4, 5, 6]));
+        assert!(result.contains(&vec![1, 2, 5, 6]));
+        assert!(result.contains(&vec![3, 7]));
+        assert!(result.contains(&vec![8, 9]));
+
+        let mut parallel_result = Vec::new();
+        cycles_through_set_parallel(&[6, 3, 1, 8, 3], 2, 4, Arc::new(graph), &mut parallel_result);
+        result.sort();
+        parallel_result.sort();
+        assert_eq!(result, parallel_result);
+    }
+
+    #[test]
+    fn test_cycles_through_parallel_large() {
+        let graph = Graph::test_large();
+        let mut result = Vec::new();
+        cycles_through_set(&[1, 2, 3, 8], 1, 6, &graph, &mut result);
+        let mut parallel_result = Vec::new();
+        cycles_through_set_parallel(&[1, 2, 3, 8], 1, 6, Arc::new(graph), &mut parallel_result);
+        result.sort();
+        parallel_result.sort();
+        assert!(!result.is_empty());
+        assert_eq!(result, parallel_result);
+    }
+
+    #[test]
+    fn test_cycles_parallel_edges() {
+        // 1 -> 2 和 2 -> 3 各有两条平行边
+        let mut graph = test_graph();
+        graph.add_directed_edge(1, 2);
+        graph.add_directed_edge(2, 3);
+        graph.add_directed_edge(2, 1);
+        graph.add_directed_edge(2, 1);
+        let mut result = Vec::new();
+        cycles_through(1, 1, 3, &graph, &mut result);
+        result.sort();
+        assert_eq!(result, vec![vec![1, 2], vec![1, 2, 3]]);
+
+        let mut parallel_result = Vec::new();
+        cycles_through_set_parallel(&[1], 1, 3, Arc::new(graph), &mut parallel_result);
+        parallel_result.sort();
+        assert_eq!(result, parallel_result);
+    }
+}
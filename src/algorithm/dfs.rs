@@ -3,9 +3,7 @@
 use std::collections::HashSet;
 use std::sync::Arc;
 use std::cmp::min;
-use std::time::Instant;
-use std::collections::VecDeque;
-use crate::structure::{Graph, GraphPath};
+use crate::structure::{GraphPath, GraphView, PathCollector};
 
 /// 从now开始往外探，最多探k度
 /// 停止条件：
@@ -18,20 +16,20 @@ use crate::structure::{Graph, GraphPath};
 ///     visit：表示当前已经访问过的点
 ///     rev：true表示反向探，false表示正向探
 ///     result：表示当前已经找到的答案，不包括起点和终点
-pub fn dfs(
+#[allow(clippy::too_many_arguments)]
+pub fn dfs<G: GraphView, R: PathCollector + ?Sized>(
     now: i64, 
     target: i64, 
     k: u32, 
-    graph: &Graph, 
-    result: &mut Vec<GraphPath>,
+    graph: &G, 
+    result: &mut R,
     temp_ans: &mut Option<&mut Vec<GraphPath>>,
     path: &mut GraphPath, 
     rev: bool, 
     visit: &mut HashSet<i64>) {
     if now == target {
         // 停止条件2
-        let ans = Vec::from(&path[0..path.len() - 1]);
-        result.push(ans);
+        result.collect(&path[0..path.len() - 1]);
         return;
     } else if k == 0 {
         // 停止条件3
@@ -41,57 +39,74 @@ pub fn dfs(
         return;
     }
     visit.insert(now);
-    let next = if rev { graph.in_v(now) } else { graph.out_v(now) };
-    if let Some(nodes) = next {
-        for v in nodes.iter() {
-            if !visit.contains(v) {
-                path.push(*v);
-                dfs(*v, target, k - 1, graph, result, temp_ans, path, rev, visit);
-                path.pop();
-            }
+    let nodes = if rev { graph.in_neighbors(now) } else { graph.out_neighbors(now) };
+    for v in nodes.iter() {
+        if !visit.contains(v) {
+            path.push(*v);
+            dfs(*v, target, k - 1, graph, result, temp_ans, path, rev, visit);
+            path.pop();
         }
     }
     visit.remove(&now);
 }
 
 
-fn dfs_for_continue(
+/// 从一条切出的前缀末尾接着探，start是前缀的起点，不能再走回去
+/// 前缀为空（切分深度为0）时从start本身开始探
+pub(crate) fn continue_prefix<G: GraphView, R: PathCollector + ?Sized>(
+    start: i64,
+    path: &mut GraphPath,
+    target: i64,
+    k: u32,
+    graph: &G,
+    result: &mut R,
+    rev: bool,
+) {
+    let mut vesited = HashSet::<i64>::new();
+    vesited.insert(start);
+    for ele in path.iter() {
+        vesited.insert(*ele);
+    }
+    let len = path.len();
+    let now = path.last().copied().unwrap_or(start);
+    dfs(
+        now, 
+        target, 
+        k - len as u32, 
+        graph, 
+        result, 
+        &mut None,
+        path, 
+        rev, 
+        &mut vesited);
+}
+
+
+/// 从dfs_for_temp切出的前缀接着探，start是前缀的起点，不能再走回去
+fn dfs_for_continue<G: GraphView, R: PathCollector + ?Sized>(
+    start: i64,
     temp_result: &mut Vec<GraphPath>, 
     target: i64, 
     k: u32, 
-    graph: &Graph, 
-    result: &mut Vec<GraphPath>,
+    graph: &G, 
+    result: &mut R,
     rev: bool, 
 ) {
     for mut path in temp_result.drain(..) {
-        let mut vesited = HashSet::<i64>::new();
-        for ele in path.iter() {
-            vesited.insert(*ele);
-        }
-        let len = path.len();
-        let now = path[len - 1];
-        dfs(
-            now, 
-            target, 
-            k - len as u32, 
-            graph, 
-            result, 
-            &mut None,
-            &mut path, 
-            rev, 
-            &mut vesited);
+        continue_prefix(start, &mut path, target, k, graph, result, rev);
     }
 }
 
 
-pub fn dfs_for_temp(
+#[allow(clippy::too_many_arguments)]
+pub fn dfs_for_temp<G: GraphView, R: PathCollector + ?Sized, T: PathCollector + ?Sized>(
     path: &mut GraphPath, 
     start: i64,
     target: i64, 
     k: u32, 
-    graph: &Graph, 
-    result: &mut Vec<GraphPath>,
-    temp_result: &mut Vec<GraphPath>,
+    graph: &G, 
+    result: &mut R,
+    temp_result: &mut T,
     rev: bool, 
     visit: &mut HashSet<i64>) {
     let now = if path.is_empty() {
@@ -101,45 +116,42 @@ pub fn dfs_for_temp(
     };
     if k == 0 {
         // 停止条件3
-        let ans = Vec::from(&path[0..path.len()]);
-        temp_result.push(ans);
+        temp_result.collect(path);
         return;
     } else if now == target {
         // 停止条件2
-        let ans = Vec::from(&path[0..path.len() - 1]);
-        result.push(ans);
+        result.collect(&path[0..path.len() - 1]);
         return;
     }
 
-    let next = if rev { graph.in_v(now) } else { graph.out_v(now) };
-    if let Some(nodes) = next {
-        for v in nodes.iter() {
-            if !visit.contains(v) {
-                visit.insert(*v);
-                path.push(*v);
-                dfs_for_temp(
-                    path, 
-                    start,
-                    target, 
-                    k - 1, 
-                    graph, 
-                    result,
-                    temp_result,
-                    rev, 
-                    visit);
-                path.pop();
-                visit.remove(v);
-            }
+    let nodes = if rev { graph.in_neighbors(now) } else { graph.out_neighbors(now) };
+    for v in nodes.iter() {
+        if !visit.contains(v) {
+            visit.insert(*v);
+            path.push(*v);
+            dfs_for_temp(
+                path, 
+                start,
+                target, 
+                k - 1, 
+                graph, 
+                result,
+                temp_result,
+                rev, 
+                visit);
+            path.pop();
+            visit.remove(v);
         }
     }
 }
 
 
-pub fn dfs_step_by_step(
+#[allow(clippy::too_many_arguments)]
+pub fn dfs_step_by_step<G: GraphView>(
     now: i64, 
     target: i64, 
     k: u32, 
-    graph: Arc<Graph>,  
+    graph: Arc<G>,  
     result: &mut Vec<GraphPath>,
     path: &mut GraphPath, 
     rev: bool, 
@@ -160,6 +172,7 @@ pub fn dfs_step_by_step(
     visit.remove(&now);
 
     dfs_for_continue(
+        now,
         &mut temp_result, 
         target, 
         k, 
@@ -172,6 +185,7 @@ pub fn dfs_step_by_step(
 #[cfg(test)] 
 mod tests {
     use super::*;
+    use crate::structure::Graph;
     #[test]
     fn test_dfs() {
         let now = 1;
@@ -216,7 +230,7 @@ mod tests {
         let mut result = Vec::new();
         let mut path = Vec::new();
         let mut visit = HashSet::new();
-        dfs(now, target, k, &graph, &mut result, &mut path, false, &mut visit);
+        dfs(now, target, k, &graph, &mut result, &mut None, &mut path, false, &mut visit);
         assert!(visit.is_empty());
         assert!(path.is_empty());
         assert_eq!(result.len(), 6);
@@ -232,7 +246,7 @@ mod tests {
         let mut result = Vec::new();
         let mut path = Vec::new();
         let mut visit = HashSet::new();
-        dfs(now, target, k, &graph, &mut result, &mut path, true, &mut visit);
+        dfs(now, target, k, &graph, &mut result, &mut None, &mut path, true, &mut visit);
         assert!(visit.is_empty());
         assert!(path.is_empty());
         assert_eq!(result.len(), 6);
@@ -319,6 +333,7 @@ mod tests {
         assert!(temp_result.contains(&vec![2000, 2000000, 2222]));
 
         dfs_for_continue(
+            now,
             &mut temp_result, 
             0, 
             4, 
@@ -334,4 +349,20 @@ mod tests {
         assert!(result.contains(&vec![2000, 2000000, 2222]));
         assert!(result.contains(&vec![1000000]));
     }
+
+    #[test]
+    fn test_continue_skips_start() {
+        // 1->2->3->1->4回到了起点，不是简单路径，只有1->4
+        let mut graph = Graph::empty();
+        for (u, v) in [(1, 2), (2, 3), (3, 1), (1, 4)].iter() {
+            graph.add_directed_edge(*u, *v);
+        }
+        let graph = Arc::new(graph);
+        let mut result = Vec::new();
+        dfs_step_by_step(1, 4, 4, graph.clone(), &mut result, &mut Vec::new(), false, &mut HashSet::new());
+        assert_eq!(result, vec![Vec::<i64>::new()]);
+        let mut result = Vec::new();
+        crate::algorithm::dfs_parallel::dfs_parallel(1, 4, 4, graph, &mut result, None, &mut Vec::new(), false, &mut HashSet::new());
+        assert_eq!(result, vec![Vec::<i64>::new()]);
+    }
 }
\ No newline at end of file
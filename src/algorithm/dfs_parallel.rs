@@ -6,15 +6,49 @@ use std::cmp::min;
 use std::time::Instant;
 use std::sync::mpsc::{Sender, Receiver, channel};
 use std::collections::VecDeque;
-use crate::structure::{Graph, GraphPath};
+use crate::structure::{GraphPath, GraphView, PathCollector, PathTrie};
 use super::dfs::dfs_for_temp;
-use super::dfs::dfs;
+use super::dfs::{dfs, continue_prefix};
 
-pub fn dfs_for_continue_parallel(
+/// 默认的并行线程数：接着探前缀、全图短环等
+pub const CONTINUE_THREADS: usize = 4;
+
+/// 从dfs_for_temp切出的前缀接着探
+/// 前缀里不含起点，这里不知道起点，接着探时可能走回起点；要排除起点用dfs_for_continue_parallel_from
+pub fn dfs_for_continue_parallel<G: GraphView + Send + Sync + 'static>(
+    temp_result: Arc<Vec<GraphPath>>, 
+    target: i64, 
+    k: u32, 
+    graph: Arc<G>, 
+    result: &mut Vec<GraphPath>,
+    rev: bool, 
+    part_ans: Option<&mut Vec<GraphPath>>
+) {
+    continue_parallel(None, temp_result, target, k, graph, result, rev, part_ans);
+}
+
+/// 和dfs_for_continue_parallel一样，start是前缀的起点，不能再走回去
+#[allow(clippy::too_many_arguments)]
+pub fn dfs_for_continue_parallel_from<G: GraphView + Send + Sync + 'static>(
+    start: i64,
     temp_result: Arc<Vec<GraphPath>>, 
     target: i64, 
     k: u32, 
-    graph: Arc<Graph>, 
+    graph: Arc<G>, 
+    result: &mut Vec<GraphPath>,
+    rev: bool, 
+    part_ans: Option<&mut Vec<GraphPath>>
+) {
+    continue_parallel(Some(start), temp_result, target, k, graph, result, rev, part_ans);
+}
+
+#[allow(clippy::too_many_arguments)]
+fn continue_parallel<G: GraphView + Send + Sync + 'static>(
+    start: Option<i64>,
+    temp_result: Arc<Vec<GraphPath>>, 
+    target: i64, 
+    k: u32, 
+    graph: Arc<G>, 
     result: &mut Vec<GraphPath>,
     rev: bool, 
     part_ans: Option<&mut Vec<GraphPath>>
@@ -37,6 +71,7 @@ pub fn dfs_for_continue_parallel(
                 if idx % 4 == i {
                     let mut path = p.clone();
                     let mut vesited = HashSet::<i64>::new();
+                    vesited.extend(start);
                     for ele in path.iter() {
                         vesited.insert(*ele);
                     }
@@ -57,7 +92,7 @@ pub fn dfs_for_continue_parallel(
             }
             let stop = cur.elapsed();
             println!("thread {} {:?}", i, stop);
-            (result)
+            result
         });
 
         handlers.push(handler);
@@ -69,11 +104,12 @@ pub fn dfs_for_continue_parallel(
     }
 }
 
-pub fn dfs_parallel(
+#[allow(clippy::too_many_arguments)]
+pub fn dfs_parallel<G: GraphView + Send + Sync + 'static>(
     now: i64, 
     target: i64, 
     k: u32, 
-    graph: Arc<Graph>,  
+    graph: Arc<G>,  
     result: &mut Vec<GraphPath>,
     part_ans: Option<&mut Vec<GraphPath>>,
     path: &mut GraphPath, 
@@ -94,7 +130,8 @@ pub fn dfs_parallel(
         visit);
     visit.remove(&now);
 
-    dfs_for_continue_parallel(
+    dfs_for_continue_parallel_from(
+        now,
         Arc::new(temp_result), 
         target, 
         k, 
@@ -106,13 +143,125 @@ pub fn dfs_parallel(
 }
 
 
-pub fn dfs_send_path(
+/// 把任务（切出的前缀、起点等）轮流分给threads个线程，每个线程对分到的任务依次调用f，
+/// 结果收进自己的R，按线程顺序返回；任务按值分下去，线程之间不共享也不拷贝
+pub fn for_each_parallel<T, R, F>(items: Vec<T>, threads: usize, f: F) -> Vec<R>
+where
+    T: Send + 'static,
+    R: Default + Send + 'static,
+    F: Fn(T, &mut R) + Send + Sync + 'static {
+    let threads = threads.max(1);
+    let mut groups: Vec<Vec<T>> = (0..threads).map(|_| Vec::new()).collect();
+    for (idx, x) in items.into_iter().enumerate() {
+        groups[idx % threads].push(x);
+    }
+    let f = Arc::new(f);
+    let handlers: Vec<_> = groups.into_iter().map(|group| {
+        let f = f.clone();
+        thread::spawn(move || -> R {
+            let mut result = R::default();
+            for x in group {
+                f(x, &mut result);
+            }
+            result
+        })
+    }).collect();
+    handlers.into_iter().map(|h| h.join().unwrap()).collect()
+}
+
+/// dfs_for_temp切出的前缀收进PathTrie，公共前缀只存一份，比Vec<GraphPath>省内存
+/// 切分时已经到target的结果收进result
+pub fn split_to_trie<G: GraphView>(
+    now: i64,
+    target: i64,
+    k: u32,
+    graph: &G,
+    rev: bool,
+    result: &mut PathTrie) -> PathTrie {
+    let mut frontier = PathTrie::new();
+    let mut path = GraphPath::new();
+    let mut visit = HashSet::new();
+    visit.insert(now);
+    dfs_for_temp(
+        &mut path, 
+        now,
+        target, 
+        min(3, k / 2), 
+        graph, 
+        result, 
+        &mut frontier,
+        rev, 
+        &mut visit);
+    frontier.shrink_to_fit();
+    frontier
+}
+
+/// 并行阶段直接从共享的前缀树里取前缀：第i个线程遍历整棵树，只接着探先序下标模threads等于i的前缀，
+/// 手上每次只复制一条，前缀不会展开成Vec<GraphPath>；start是前缀的起点
+#[allow(clippy::too_many_arguments)]
+pub fn continue_trie<G, R>(
+    start: i64,
+    frontier: Arc<PathTrie>,
+    target: i64,
+    k: u32,
+    graph: Arc<G>,
+    rev: bool,
+    threads: usize) -> Vec<R>
+where
+    G: GraphView + Send + Sync + 'static,
+    R: PathCollector + Default + Send + 'static {
+    let threads = threads.max(1);
+    let handlers: Vec<_> = (0..threads).map(|i| {
+        let frontier = frontier.clone();
+        let graph = graph.clone();
+        thread::spawn(move || -> R {
+            let mut result = R::default();
+            frontier.for_each_part(i, threads, |p| {
+                let mut path = p.to_vec();
+                continue_prefix(start, &mut path, target, k, graph.as_ref(), &mut result, rev);
+            });
+            result
+        })
+    }).collect();
+    handlers.into_iter().map(|h| h.join().unwrap()).collect()
+}
+
+
+/// dfs_parallel的前缀树版本，切出的前缀和结果都存在PathTrie里，公共前缀只存一份
+/// 每个线程先收进自己的树，最后按线程顺序合并；k小于2时切不出前缀，直接顺序dfs
+pub fn dfs_parallel_trie<G: GraphView + Send + Sync + 'static>(
+    now: i64, 
+    target: i64, 
+    k: u32, 
+    graph: Arc<G>,  
+    rev: bool) -> PathTrie {
+    let mut result = PathTrie::new();
+    if now == target || k == 0 {
+        return result;
+    }
+    if k < 2 {
+        let mut path = GraphPath::new();
+        let mut visit = HashSet::new();
+        dfs(now, target, k, graph.as_ref(), &mut result, &mut None, &mut path, rev, &mut visit);
+        return result;
+    }
+
+    let frontier = Arc::new(split_to_trie(now, target, k, graph.as_ref(), rev, &mut result));
+    for part in continue_trie::<_, PathTrie>(now, frontier, target, k, graph, rev, CONTINUE_THREADS) {
+        result.merge(&part);
+    }
+    result
+}
+
+
+#[allow(clippy::too_many_arguments)]
+pub fn dfs_send_path<G: GraphView>(
     temp_result_sender: &Vec<Sender<GraphPath>>,
     empty_group_receive: &Receiver<i64>,
     now: i64, 
     target: i64, 
     k: u32, 
-    graph: &Graph, 
+    graph: &G, 
     result: &mut Vec<GraphPath>,
     path: &mut GraphPath, 
     rev: bool, 
@@ -127,47 +276,45 @@ pub fn dfs_send_path(
         return;
     }
     visit.insert(now);
-    let next = if rev { graph.in_v(now) } else { graph.out_v(now) };
-    if let Some(nodes) = next {
-        for v in nodes.iter() {
-            if !visit.contains(v) {
-                path.push(*v);
-                let received = empty_group_receive.recv();
-                let is_skip = match received {
-                    Ok(thread_index) =>
-                     {
-                         let _ = temp_result_sender[thread_index as usize].send(path.clone());
-                         true
-                     }
-                     _ => false
-                };
-                if is_skip {
-                    path.pop();
-                    continue;
-                }
-                dfs_send_path(
-                    temp_result_sender,
-                    empty_group_receive, 
-                    *v, target, 
-                    k - 1, 
-                    graph, 
-                    result, 
-                    path, 
-                    rev, 
-                    visit);
+    let nodes = if rev { graph.in_neighbors(now) } else { graph.out_neighbors(now) };
+    for v in nodes.iter() {
+        if !visit.contains(v) {
+            path.push(*v);
+            let received = empty_group_receive.recv();
+            let is_skip = match received {
+                Ok(thread_index) =>
+                 {
+                     let _ = temp_result_sender[thread_index as usize].send(path.clone());
+                     true
+                 }
+                 _ => false
+            };
+            if is_skip {
                 path.pop();
+                continue;
             }
+            dfs_send_path(
+                temp_result_sender,
+                empty_group_receive, 
+                *v, target, 
+                k - 1, 
+                graph, 
+                result, 
+                path, 
+                rev, 
+                visit);
+            path.pop();
         }
     }
     visit.remove(&now);
 }
 
 
-pub fn dfs_for_continue_parallel_balance_stealing(
+pub fn dfs_for_continue_parallel_balance_stealing<G: GraphView + Send + Sync + 'static>(
     temp_result: Arc<Vec<GraphPath>>, 
     target: i64, 
     k: u32, 
-    graph: Arc<Graph>, 
+    graph: Arc<G>, 
     result: &mut Vec<GraphPath>,
     rev: bool, 
 ) {
@@ -242,7 +389,7 @@ pub fn dfs_for_continue_parallel_balance_stealing(
                     } else {
                         require_sender_cloner[0].send(i as i64)
                     };
-                    if let Ok(_) = res {
+                    if res.is_ok() {
                         if let Ok(path) = this_path_receiver.recv() {
                             to_process.push_back(path);
                         }
@@ -265,13 +412,14 @@ pub fn dfs_for_continue_parallel_balance_stealing(
 }
 
 
-pub fn dfs_lock(
+#[allow(clippy::too_many_arguments)]
+pub fn dfs_lock<G: GraphView>(
     cnt: Arc<Mutex<i64>>,
     take_path: Arc<Mutex<Vec<GraphPath>>>,
     now: i64, 
     target: i64, 
     k: u32, 
-    graph: &Graph, 
+    graph: &G, 
     result: &mut Vec<GraphPath>,
     path: &mut GraphPath, 
     rev: bool, 
@@ -286,27 +434,25 @@ pub fn dfs_lock(
         return;
     }
     visit.insert(now);
-    let next = if rev { graph.in_v(now) } else { graph.out_v(now) };
-    if let Some(nodes) = next {
-        for v in nodes.iter() {
-            if !visit.contains(v) {
-                path.push(*v);
-                let mut lock = cnt.try_lock();
-                let mut is_skip = false;
-                if let Ok(ref mut mutex) = lock {
-                    **mutex -= 1;
-                    is_skip = true;
-
-                    take_path.lock().unwrap().push(path.clone());
-                }
-                
-                if is_skip {
-                    path.pop();
-                    continue;
-                }
-                dfs_lock(cnt.clone(), take_path.clone(), *v, target, k - 1, graph, result, path, rev, visit);
+    let nodes = if rev { graph.in_neighbors(now) } else { graph.out_neighbors(now) };
+    for v in nodes.iter() {
+        if !visit.contains(v) {
+            path.push(*v);
+            let mut lock = cnt.try_lock();
+            let mut is_skip = false;
+            if let Ok(ref mut mutex) = lock {
+                **mutex -= 1;
+                is_skip = true;
+
+                take_path.lock().unwrap().push(path.clone());
+            }
+            
+            if is_skip {
                 path.pop();
+                continue;
             }
+            dfs_lock(cnt.clone(), take_path.clone(), *v, target, k - 1, graph, result, path, rev, visit);
+            path.pop();
         }
     }
     visit.remove(&now);
@@ -318,11 +464,11 @@ pub fn dfs_lock(
 
 //在loop中如果自己的队列执行完毕了，就往路径中放东西
 //共享内存实现
-pub fn dfs_in_mutex(
+pub fn dfs_in_mutex<G: GraphView + Send + Sync + 'static>(
     temp_result: Arc<Vec<GraphPath>>, 
     target: i64, 
     k: u32, 
-    graph: Arc<Graph>, 
+    graph: Arc<G>, 
     result: &mut Vec<GraphPath>,
     rev: bool, 
 ) {
@@ -398,6 +544,8 @@ pub fn dfs_in_mutex(
 #[cfg(test)]
 mod test {
     use super::*;
+    use std::mem::{size_of, size_of_val};
+    use crate::structure::Graph;
     #[test]
     fn test_dfs_parallel() {
 
@@ -479,7 +627,8 @@ mod test {
             4, 
             Arc::new(graph), 
             &mut result, 
-            false);
+            false,
+            None);
 
         assert_eq!(result.len(), 6);
         assert!(result.contains(&vec![2]));
@@ -489,4 +638,67 @@ mod test {
         assert!(result.contains(&vec![2000, 2000000, 2222]));
         assert!(result.contains(&vec![1000000]));
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_dfs_parallel_trie() {
+        let graph = Arc::new(Graph::test_large());
+        let ids = graph.vertices();
+        let mut found = 0;
+        for (i, s) in ids.iter().enumerate().step_by(41).take(6) {
+            let t = ids[(i * 5 + 1) % ids.len()];
+            if *s == t {
+                continue;
+            }
+            let mut expected = Vec::new();
+            dfs_parallel(*s, t, 5, graph.clone(), &mut expected, None, &mut Vec::new(), false, &mut HashSet::new());
+            expected.sort_unstable();
+            let trie = dfs_parallel_trie(*s, t, 5, graph.clone(), false);
+            let mut paths = trie.to_paths();
+            paths.sort_unstable();
+            assert_eq!(trie.len(), expected.len());
+            assert_eq!(paths, expected);
+            found += paths.len();
+        }
+        assert!(found > 0);
+    }
+
+    #[test]
+    fn test_trie_frontier_size() {
+        let graph = Arc::new(Graph::test_large());
+        let mut result = PathTrie::new();
+        let frontier = split_to_trie(1, 3, 6, graph.as_ref(), false, &mut result);
+        let paths = frontier.to_paths();
+        let vec_bytes: usize = paths.iter().map(|p| size_of::<GraphPath>() + size_of_val(p.as_slice())).sum();
+        assert!(paths.len() > 1000);
+        assert!(frontier.heap_size() * 3 < vec_bytes * 2, "{} {}", frontier.heap_size(), vec_bytes);
+
+        // 并行阶段只拿着这棵树，结束后引用都还回来了
+        let frontier = Arc::new(frontier);
+        for part in continue_trie::<_, PathTrie>(1, frontier.clone(), 3, 6, graph.clone(), false, 3) {
+            result.merge(&part);
+        }
+        assert_eq!(Arc::strong_count(&frontier), 1);
+        let mut expected = Vec::new();
+        dfs_parallel(1, 3, 6, graph.clone(), &mut expected, None, &mut Vec::new(), false, &mut HashSet::new());
+        expected.sort_unstable();
+        let mut paths = result.to_paths();
+        paths.sort_unstable();
+        assert_eq!(paths, expected);
+        assert_eq!(dfs_parallel_trie(1, 3, 6, graph, false).len(), expected.len());
+    }
+
+    #[test]
+    fn test_dfs_parallel_trie_small_k() {
+        let mut graph = Graph::empty();
+        graph.add_directed_edge(1, 2);
+        graph.add_directed_edge(1, 3);
+        graph.add_directed_edge(3, 2);
+        let graph = Arc::new(graph);
+        assert!(dfs_parallel_trie(1, 2, 0, graph.clone(), false).is_empty());
+        assert_eq!(dfs_parallel_trie(1, 2, 1, graph.clone(), false).to_paths(), vec![Vec::<i64>::new()]);
+        assert_eq!(dfs_parallel_trie(2, 1, 1, graph.clone(), true).to_paths(), vec![Vec::<i64>::new()]);
+        let mut paths = dfs_parallel_trie(1, 2, 2, graph, false).to_paths();
+        paths.sort_unstable();
+        assert_eq!(paths, vec![vec![], vec![3]]);
+    }
+}
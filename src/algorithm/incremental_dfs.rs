@@ -1,95 +1,421 @@
-use std::sync::Arc;
-use std::collections::HashSet;
-use std::cmp::min;
+use std::rc::Rc;
+use std::collections::{HashMap, HashSet, VecDeque};
 use crate::structure::Graph;
 use crate::structure::GraphPath;
-use crate::algorithm::dfs_parallel::dfs_parallel;
+use crate::algorithm::dfs::dfs;
+use crate::algorithm::cycle_dfs::canonical_cycle;
 
+/// 从u往v探limit度，total是探到v的路径，part_answer是探满limit度还没到v的前缀
 pub fn cal_part(
     u: i64,
     v: i64,
     limit: u32,
-    graph: Arc<Graph>,
+    graph: &Graph,
     total: &mut Vec<GraphPath>,
     part_answer: &mut Vec<GraphPath>
 ) {
     let mut visited = HashSet::new();
     let mut path = GraphPath::new();
-    dfs_parallel(
+    dfs(
         u, 
         v, 
         limit, 
-        graph.clone(), 
+        graph, 
         total,
-        Some(part_answer), 
+        &mut Some(part_answer), 
         &mut path, 
         false,
         &mut visited
     );
 }
-pub fn join(
-    _left_part_answer: &Vec<GraphPath>, 
-    _right_part_answer: &Vec<GraphPath>) {
-}
 
+/// 往graph里加一条无向边(u, v)，把因此新出现的、长度不超过limit的简单环放进result
+/// 环的表示和cycle_dfs相同；图里已经有的方向照样加平行边，但不算新边，见Graph上平行边的约定
 pub fn incremental_path(
     u: i64,
     v: i64,
     limit: u32,
-    mut graph: Graph,
+    graph: &mut Graph,
     result: &mut Vec<GraphPath>,
 ) {
-    let half_len = (limit + 1) / 2;
-    let mut left_part_answer = vec![];
-    let mut left_answer = vec![];
-    let graph_arc = Arc::new(graph);
-    cal_part(u, v, half_len, graph_arc.clone(), &mut left_part_answer, &mut left_answer);
-    let mut right_part_answer = vec![];
-    let mut right_answer = vec![];
-    cal_part(v, u, half_len, graph_arc.clone(), &mut right_part_answer, &mut right_answer);
-    let mut graph = Arc::try_unwrap(graph_arc).ok().unwrap();
-    
-    let mut lu = vec![];
-    let mut lv = vec![];
-    let mut minlu = usize::max_value();
-    let mut minlv = usize::max_value();
-    for lp in left_part_answer {
-        if lp.len() > 1 {
-            if lp[lp.len() - 1] == u {                
-                minlu = min(minlu, lp.len());
-                lu.push(lp);
-            } else if lp[lp.len() - 1] == v {
-                minlv = min(minlv, lp.len());
-                lv.push(lp); 
+    let batch = new_edges(graph, &[(u, v), (v, u)]);
+    graph.add_undirected_edge(u, v);
+    let mut halves = HalfPaths::new(graph);
+    for (cycle, _) in batch_cycles(limit, &batch, &batch, &mut halves) {
+        result.push(cycle);
+    }
+}
+
+/// 去掉batch里重复的边和图里已经有的边，剩下的才是真正的新边
+/// 要在把batch加进图之前调用；被去掉的边照样加进图成为平行边，只是不带来新结果
+pub fn new_edges(graph: &Graph, batch: &[(i64, i64)]) -> Vec<(i64, i64)> {
+    let mut seen = HashSet::new();
+    batch.iter()
+        .filter(|(u, v)| !graph.has_edge(*u, *v) && seen.insert((*u, *v)))
+        .cloned()
+        .collect()
+}
+
+/// 半路径缓存：同一批里共享起点或终点的新边，只算一次对应的半条路径
+/// 路径都包括两个端点
+pub struct HalfPaths<'a> {
+    graph: &'a Graph,
+    left: HashMap<(i64, i64, u32), Rc<Vec<GraphPath>>>,
+    right: HashMap<(i64, i64, u32), Rc<Vec<GraphPath>>>,
+}
+
+impl<'a> HalfPaths<'a> {
+    pub fn new(graph: &'a Graph) -> Self {
+        HalfPaths {
+            graph,
+            left: HashMap::new(),
+            right: HashMap::new(),
+        }
+    }
+
+    #[inline]
+    pub fn graph(&self) -> &'a Graph {
+        self.graph
+    }
+
+    /// s -> ... -> u，不超过k步
+    pub fn left(&mut self, s: i64, u: i64, k: u32) -> Rc<Vec<GraphPath>> {
+        let graph = self.graph;
+        self.left.entry((s, u, k)).or_insert_with(|| {
+            // 逆向从u找s，得到的路径是u -> ... -> s，翻转成s -> ... -> u
+            let paths = full_paths(u, s, k, graph, true)
+                .into_iter()
+                .map(|mut p| {
+                    p.reverse();
+                    p
+                })
+                .collect();
+            Rc::new(paths)
+        }).clone()
+    }
+
+    /// v -> ... -> t，不超过k步
+    pub fn right(&mut self, v: i64, t: i64, k: u32) -> Rc<Vec<GraphPath>> {
+        let graph = self.graph;
+        self.right.entry((v, t, k))
+            .or_insert_with(|| Rc::new(full_paths(v, t, k, graph, false)))
+            .clone()
+    }
+}
+
+/// 把不相交的左右半边用一条新边连起来，总长不超过k
+/// result和dfs相同，不包括起点和终点
+pub fn join_halves(left: &[GraphPath], right: &[GraphPath], k: u32, result: &mut Vec<GraphPath>) {
+    for l in left.iter() {
+        let left_set: HashSet<i64> = l.iter().cloned().collect();
+        for r in right.iter() {
+            if l.len() + r.len() - 1 > k as usize {
+                continue;
             }
+            if r.iter().any(|x| left_set.contains(x)) {
+                continue;
+            }
+            let mut ans = Vec::with_capacity(l.len() + r.len() - 2);
+            ans.extend_from_slice(&l[1..]);
+            ans.extend_from_slice(&r[..r.len() - 1]);
+            result.push(ans);
         }
     }
+}
 
-    let mut ru = vec![];
-    let mut rv = vec![];
-    let mut minru = usize::max_value();
-    let mut minrv = usize::max_value();
-    for rp in right_part_answer {
-        if rp.len() > 1 {
-            if rp[rp.len() - 1] == u {
-                minru = min(minru, rp.len());
-                ru.push(rp);
-            } else if rp[rp.len() - 1] == v {
-                minrv = min(minrv, rp.len());
-                rv.push(rp);
+/// 路径（首尾相接时是环）上用到的batch里的边
+fn used_edges(vertices: &[i64], ring: bool, batch: &HashSet<(i64, i64)>) -> Vec<(i64, i64)> {
+    let mut ret = Vec::new();
+    let mut pairs: Vec<(i64, i64)> = vertices.windows(2).map(|w| (w[0], w[1])).collect();
+    if ring && !vertices.is_empty() {
+        pairs.push((vertices[vertices.len() - 1], vertices[0]));
+    }
+    for e in pairs {
+        if batch.contains(&e) && !ret.contains(&e) {
+            ret.push(e);
+        }
+    }
+    ret
+}
+
+/// 一批新边（已经加进图里）带来的s到t的新路径，每条路径只出现一次，
+/// 并附上它用到的所有新边（按batch里的顺序）
+/// 路径只在它用到的第一条新边下面枚举出来，其余新边遇到它时跳过
+/// candidates是batch的子集，只从这些边出发枚举（调用方确定其余新边不可能在结果里）
+pub fn batch_paths(
+    s: i64,
+    t: i64,
+    k: u32,
+    batch: &[(i64, i64)],
+    candidates: &[(i64, i64)],
+    halves: &mut HalfPaths,
+) -> Vec<(GraphPath, Vec<(i64, i64)>)> {
+    let mut ret = Vec::new();
+    if s == t || k == 0 {
+        return ret;
+    }
+    let batch_set: HashSet<(i64, i64)> = batch.iter().cloned().collect();
+    let order: HashMap<(i64, i64), usize> = batch.iter().enumerate().map(|(i, e)| (*e, i)).collect();
+    for (u, v) in candidates.iter() {
+        let i = order[&(*u, *v)];
+        let left = halves.left(s, *u, k - 1);
+        if left.is_empty() {
+            continue;
+        }
+        let right = halves.right(*v, t, k - 1);
+        let mut paths = Vec::new();
+        join_halves(&left, &right, k, &mut paths);
+        for p in paths {
+            let mut full = Vec::with_capacity(p.len() + 2);
+            full.push(s);
+            full.extend_from_slice(&p);
+            full.push(t);
+            let mut used = used_edges(&full, false, &batch_set);
+            used.sort_by_key(|e| order[e]);
+            if order[&used[0]] == i {
+                ret.push((p, used));
             }
         }
     }
-    
-    graph.add_undirected_edge(u, v);
-    // 三种情况
-    if lu.len() == 0 && rv.len() == 0 {
-        // there is nothing to do
-    } else if lu.len() != 0 && rv.len() == 0 {
-        //
-        
-    } else {
-        //
+    ret
+}
+
+/// 一批新边（已经加进图里）带来的长度不超过k的新环，去重方式和batch_paths相同
+pub fn batch_cycles(
+    k: u32,
+    batch: &[(i64, i64)],
+    candidates: &[(i64, i64)],
+    halves: &mut HalfPaths,
+) -> Vec<(GraphPath, Vec<(i64, i64)>)> {
+    let mut ret = Vec::new();
+    if k == 0 {
+        return ret;
     }
+    let batch_set: HashSet<(i64, i64)> = batch.iter().cloned().collect();
+    let order: HashMap<(i64, i64), usize> = batch.iter().enumerate().map(|(i, e)| (*e, i)).collect();
+    for (u, v) in candidates.iter() {
+        let i = order[&(*u, *v)];
+        for p in halves.right(*v, *u, k - 1).iter() {
+            // p是v -> ... -> u，环是u -> v -> ... -> u
+            let mut cycle = Vec::with_capacity(p.len());
+            cycle.push(*u);
+            cycle.extend_from_slice(&p[..p.len() - 1]);
+            let mut used = used_edges(&cycle, true, &batch_set);
+            used.sort_by_key(|e| order[e]);
+            if order[&used[0]] == i {
+                ret.push((canonical_cycle(&cycle), used));
+            }
+        }
+    }
+    ret
+}
+
+/// 从start出发（rev为true时逆向）最多走depth步的BFS，返回每个到达点的最短步数
+pub fn bounded_bfs(start: i64, depth: u32, graph: &Graph, rev: bool) -> HashMap<i64, u32> {
+    let mut dist = HashMap::new();
+    let mut queue = VecDeque::new();
+    dist.insert(start, 0);
+    queue.push_back(start);
+    while let Some(now) = queue.pop_front() {
+        let d = dist[&now];
+        if d >= depth {
+            continue;
+        }
+        let next = if rev { graph.in_v(now) } else { graph.out_v(now) };
+        if let Some(nodes) = next {
+            for v in nodes.iter() {
+                if !dist.contains_key(v) {
+                    dist.insert(*v, d + 1);
+                    queue.push_back(*v);
+                }
+            }
+        }
+    }
+    dist
 }
 
+/// 从now出发走不超过k步到target的所有简单路径，包括两个端点
+/// now == target时只有一条长度为0的路径；平行边走出的相同点序列只留一条
+fn full_paths(now: i64, target: i64, k: u32, graph: &Graph, rev: bool) -> Vec<GraphPath> {
+    if now == target {
+        return vec![vec![now]];
+    }
+    let mut part = Vec::new();
+    let mut path = GraphPath::new();
+    let mut visit = HashSet::new();
+    dfs(now, target, k, graph, &mut part, &mut None, &mut path, rev, &mut visit);
+    part.sort_unstable();
+    part.dedup();
+    part.into_iter().map(|mut p| {
+        p.insert(0, now);
+        p.push(target);
+        p
+    }).collect()
+}
+
+/// 插入有向边(u, v)之后新出现的s到t、长度不超过k的简单路径，也就是所有经过(u, v)的路径
+/// 左半边是s到u的路径，右半边是v到t的路径，两边不相交且总长加一不超过k
+/// result和dfs相同，不包括起点和终点
+pub fn paths_through_edge(
+    s: i64,
+    t: i64,
+    k: u32,
+    u: i64,
+    v: i64,
+    graph: &Graph,
+    result: &mut Vec<GraphPath>) {
+    if s == t || k == 0 {
+        return;
+    }
+    let mut halves = HalfPaths::new(graph);
+    let left = halves.left(s, u, k - 1);
+    if left.is_empty() {
+        return;
+    }
+    let right = halves.right(v, t, k - 1);
+    join_halves(&left, &right, k, result);
+}
+
+/// 插入有向边(u, v)之后新出现的、长度不超过k的简单环，也就是v到u的路径再接上(u, v)
+/// 环的表示和cycle_dfs相同：以最小id开头，不重复首尾
+pub fn cycles_through_edge(u: i64, v: i64, k: u32, graph: &Graph, result: &mut Vec<GraphPath>) {
+    if k == 0 {
+        return;
+    }
+    for p in full_paths(v, u, k - 1, graph, false) {
+        // p是v -> ... -> u，环是u -> v -> ... -> u
+        let mut cycle = Vec::with_capacity(p.len());
+        cycle.push(u);
+        cycle.extend_from_slice(&p[..p.len() - 1]);
+        result.push(canonical_cycle(&cycle));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn all_paths(s: i64, t: i64, k: u32, graph: &Graph) -> Vec<GraphPath> {
+        let mut result = Vec::new();
+        let mut path = GraphPath::new();
+        let mut visit = HashSet::new();
+        dfs(s, t, k, graph, &mut result, &mut None, &mut path, false, &mut visit);
+        result.sort();
+        result
+    }
+
+    #[test]
+    fn test_bounded_bfs() {
+        let graph = Graph::test_large();
+        let dist = bounded_bfs(5, 2, &graph, false);
+        assert_eq!(dist[&5], 0);
+        assert_eq!(dist[&2], 1);
+        assert_eq!(dist[&3], 2);
+        assert!(!dist.contains_key(&7));
+        let dist = bounded_bfs(3, 1, &graph, true);
+        assert_eq!(dist[&7], 1);
+        assert!(!dist.contains_key(&6));
+    }
+
+    #[test]
+    fn test_paths_through_edge() {
+        let mut graph = Graph::test_large();
+        let inserts = vec![(4, 6), (7, 9), (1, 8), (8, 2), (3, 2)];
+        for (u, v) in inserts {
+            for k in 1..6 {
+                let before = all_paths(1, 3, k, &graph);
+                let mut added = Vec::new();
+                paths_through_edge(1, 3, k, u, v, &graph, &mut added);
+                graph.add_directed_edge(u, v);
+                let after = all_paths(1, 3, k, &graph);
+                graph.adj.get_mut(&u).unwrap().pop();
+                graph.rev_adj.get_mut(&v).unwrap().pop();
+
+                let mut expected = before.clone();
+                expected.extend(added.iter().cloned());
+                expected.sort();
+                assert_eq!(after, expected, "edge ({}, {}) k {}", u, v, k);
+            }
+            graph.add_directed_edge(u, v);
+        }
+    }
+
+    #[test]
+    fn test_cycles_through_edge() {
+        let mut graph = Graph::empty();
+        graph.add_directed_edge(1, 2);
+        graph.add_directed_edge(2, 3);
+        graph.add_directed_edge(2, 4);
+        graph.add_directed_edge(4, 3);
+        let mut result = Vec::new();
+        cycles_through_edge(3, 1, 4, &graph, &mut result);
+        result.sort();
+        assert_eq!(result, vec![vec![1, 2, 3], vec![1, 2, 4, 3]]);
+
+        let mut result = Vec::new();
+        cycles_through_edge(3, 1, 3, &graph, &mut result);
+        assert_eq!(result, vec![vec![1, 2, 3]]);
+
+        let mut result = Vec::new();
+        cycles_through_edge(5, 5, 1, &graph, &mut result);
+        assert_eq!(result, vec![vec![5]]);
+    }
+
+    #[test]
+    fn test_batch_paths() {
+        let mut graph = Graph::empty();
+        for (u, v) in [(0, 1), (1, 2), (2, 9), (0, 3), (4, 9)].iter() {
+            graph.add_directed_edge(*u, *v);
+        }
+        let before: HashSet<GraphPath> = all_paths(0, 9, 5, &graph).into_iter().collect();
+        let batch = vec![(3, 4), (1, 4), (3, 4), (2, 9), (3, 1), (4, 2)];
+        let new = new_edges(&graph, &batch);
+        assert_eq!(new, vec![(3, 4), (1, 4), (3, 1), (4, 2)]);
+        for (u, v) in new.iter() {
+            graph.add_directed_edge(*u, *v);
+        }
+        let mut halves = HalfPaths::new(&graph);
+        let found = batch_paths(0, 9, 5, &new, &new, &mut halves);
+        let after: HashSet<GraphPath> = all_paths(0, 9, 5, &graph).into_iter().collect();
+
+        let found_paths: HashSet<GraphPath> = found.iter().map(|(p, _)| p.clone()).collect();
+        assert_eq!(found_paths.len(), found.len());
+        let expected: HashSet<GraphPath> = after.difference(&before).cloned().collect();
+        assert_eq!(found_paths, expected);
+        for (p, edges) in found.iter() {
+            if p == &vec![3, 1, 4] {
+                assert_eq!(edges, &vec![(1, 4), (3, 1)]);
+            }
+            if p == &vec![3, 4, 2] {
+                assert_eq!(edges, &vec![(3, 4), (4, 2)]);
+            }
+        }
+    }
+
+    #[test]
+    fn test_incremental_path() {
+        let mut graph = Graph::empty();
+        graph.add_undirected_edge(1, 2);
+        graph.add_undirected_edge(2, 3);
+        let mut result = Vec::new();
+        incremental_path(3, 1, 3, &mut graph, &mut result);
+        result.sort();
+        assert_eq!(result, vec![vec![1, 2, 3], vec![1, 3], vec![1, 3, 2]]);
+        assert!(graph.has_edge(1, 3) && graph.has_edge(3, 1));
+
+        let mut result = Vec::new();
+        incremental_path(3, 1, 3, &mut graph, &mut result);
+        assert!(result.is_empty());
+        assert_eq!(graph.adj[&3].iter().filter(|x| **x == 1).count(), 2);
+    }
+
+    #[test]
+    fn test_cal_part() {
+        let graph = Graph::test_large();
+        let mut total = Vec::new();
+        let mut part = Vec::new();
+        cal_part(1, 3, 2, &graph, &mut total, &mut part);
+        assert_eq!(total, vec![vec![4]]);
+        assert!(part.contains(&vec![5, 2]));
+    }
+}
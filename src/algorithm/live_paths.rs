@@ -0,0 +1,230 @@
+//! 一个(s, t, k)查询的实时结果集
+//!
+//! 结果集是去重后的路径集合，每条路径按它经过的边建倒排索引，
+//! 删边时只需要查这条边的倒排表，不用扫全部结果；加边时用paths_through_edge只算新路径。
+
+use std::collections::{HashMap, HashSet};
+use crate::structure::{Graph, GraphPath, PathResult};
+use super::dfs::dfs;
+use super::incremental_dfs::paths_through_edge;
+
+pub type PathId = usize;
+
+pub struct LivePaths {
+    s: i64,
+    t: i64,
+    k: u32,
+    paths: HashMap<PathId, GraphPath>,
+    ids: HashMap<GraphPath, PathId>,
+    by_edge: HashMap<(i64, i64), HashSet<PathId>>,
+    next_id: PathId,
+}
+
+impl LivePaths {
+    pub fn new(s: i64, t: i64, k: u32) -> Self {
+        LivePaths {
+            s,
+            t,
+            k,
+            paths: HashMap::new(),
+            ids: HashMap::new(),
+            by_edge: HashMap::new(),
+            next_id: 0,
+        }
+    }
+
+    /// 用已经算好的结果（和dfs相同，不包括起点和终点）建结果集
+    pub fn from_paths(s: i64, t: i64, k: u32, paths: Vec<GraphPath>) -> Self {
+        let mut ret = LivePaths::new(s, t, k);
+        for p in paths {
+            ret.add(p);
+        }
+        ret
+    }
+
+    /// 在graph上跑一遍dfs建结果集
+    pub fn build(s: i64, t: i64, k: u32, graph: &Graph) -> Self {
+        let mut result = Vec::new();
+        let mut path = GraphPath::new();
+        let mut visit = HashSet::new();
+        if s != t {
+            dfs(s, t, k, graph, &mut result, &mut None, &mut path, false, &mut visit);
+        }
+        LivePaths::from_paths(s, t, k, result)
+    }
+
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.paths.len()
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.paths.is_empty()
+    }
+
+    #[inline]
+    pub fn contains(&self, path: &[i64]) -> bool {
+        self.ids.contains_key(path)
+    }
+
+    pub fn paths(&self) -> impl Iterator<Item = &GraphPath> {
+        self.paths.values()
+    }
+
+    /// 当前结果补全成含起点终点的PathResult，按点序列排序
+    pub fn results(&self) -> Vec<PathResult> {
+        let mut ret: Vec<PathResult> = self.paths.values()
+            .map(|p| PathResult::from_trimmed(self.s, self.t, p, false))
+            .collect();
+        ret.sort_unstable_by(|a, b| a.vertices.cmp(&b.vertices));
+        ret
+    }
+
+    /// 路径经过的边：s -> path[0] -> ... -> path[n - 1] -> t
+    fn edges(&self, path: &[i64]) -> Vec<(i64, i64)> {
+        let mut ret = Vec::with_capacity(path.len() + 1);
+        let mut prev = self.s;
+        for v in path.iter().chain(Some(&self.t)) {
+            ret.push((prev, *v));
+            prev = *v;
+        }
+        ret
+    }
+
+    /// 加入一条路径，已经存在时返回false
+    fn add(&mut self, path: GraphPath) -> bool {
+        if self.ids.contains_key(&path) {
+            return false;
+        }
+        let id = self.next_id;
+        self.next_id += 1;
+        for e in self.edges(&path) {
+            self.by_edge.entry(e).or_default().insert(id);
+        }
+        self.ids.insert(path.clone(), id);
+        self.paths.insert(id, path);
+        true
+    }
+
+    fn remove(&mut self, id: PathId) -> Option<GraphPath> {
+        let path = self.paths.remove(&id)?;
+        self.ids.remove(&path);
+        for e in self.edges(&path) {
+            if let Some(set) = self.by_edge.get_mut(&e) {
+                set.remove(&id);
+                if set.is_empty() {
+                    self.by_edge.remove(&e);
+                }
+            }
+        }
+        Some(path)
+    }
+
+    /// 结果集里经过(u, v)的路径
+    pub fn paths_with_edge(&self, u: i64, v: i64) -> Vec<&GraphPath> {
+        match self.by_edge.get(&(u, v)) {
+            Some(ids) => ids.iter().map(|id| &self.paths[id]).collect(),
+            None => Vec::new(),
+        }
+    }
+
+    /// (u, v)从图里彻底消失后，把经过它的路径从结果集里删掉并返回
+    pub fn remove_paths_with_edge(&mut self, u: i64, v: i64) -> Vec<GraphPath> {
+        let ids = match self.by_edge.get(&(u, v)) {
+            Some(ids) => ids.iter().cloned().collect::<Vec<_>>(),
+            None => return Vec::new(),
+        };
+        ids.into_iter().filter_map(|id| self.remove(id)).collect()
+    }
+
+    /// 从graph里删掉一条(u, v)并返回消失的路径
+    /// 有重边时只要还剩一条(u, v)，路径就还在，返回空
+    pub fn remove_edge(&mut self, graph: &mut Graph, u: i64, v: i64) -> Vec<GraphPath> {
+        if !graph.remove_directed_edge(u, v) || graph.has_edge(u, v) {
+            return Vec::new();
+        }
+        self.remove_paths_with_edge(u, v)
+    }
+
+    /// 往graph里加一条(u, v)并返回新出现的路径
+    pub fn insert_edge(&mut self, graph: &mut Graph, u: i64, v: i64) -> Vec<GraphPath> {
+        let mut added = Vec::new();
+        if !graph.has_edge(u, v) {
+            paths_through_edge(self.s, self.t, self.k, u, v, graph, &mut added);
+        }
+        graph.add_directed_edge(u, v);
+        added.retain(|p| self.add(p.clone()));
+        added
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sorted(mut paths: Vec<GraphPath>) -> Vec<GraphPath> {
+        paths.sort();
+        paths
+    }
+
+    fn current(live: &LivePaths) -> Vec<GraphPath> {
+        sorted(live.paths().cloned().collect())
+    }
+
+    #[test]
+    fn test_remove_edge() {
+        let mut graph = Graph::test_large();
+        let mut live = LivePaths::build(1, 3, 5, &graph);
+        assert!(live.contains(&[5, 2]));
+        assert!(live.contains(&[4]));
+        let total = live.len();
+        let results = live.results();
+        assert_eq!(results.len(), total);
+        assert!(results.iter().any(|p| p.vertices == vec![1, 5, 2, 3]));
+
+        assert_eq!(live.paths_with_edge(2, 6), vec![&vec![5, 2, 6, 7]]);
+        let removed = live.remove_edge(&mut graph, 2, 6);
+        assert_eq!(removed, vec![vec![5, 2, 6, 7]]);
+        assert_eq!(live.len(), total - 1);
+        assert_eq!(current(&live), current(&LivePaths::build(1, 3, 5, &graph)));
+
+        let removed = sorted(live.remove_edge(&mut graph, 1, 5));
+        assert_eq!(removed, vec![vec![5, 2], vec![5, 2, 8], vec![5, 2, 8, 9]]);
+        assert_eq!(current(&live), current(&LivePaths::build(1, 3, 5, &graph)));
+
+        assert!(live.remove_edge(&mut graph, 1, 5).is_empty());
+        assert!(live.remove_paths_with_edge(100, 200).is_empty());
+    }
+
+    #[test]
+    fn test_parallel_edge() {
+        let mut graph = Graph::empty();
+        graph.add_directed_edge(1, 2);
+        graph.add_directed_edge(2, 3);
+        let mut live = LivePaths::build(1, 3, 2, &graph);
+        assert!(live.insert_edge(&mut graph, 1, 2).is_empty());
+        assert_eq!(live.len(), 1);
+        assert!(live.remove_edge(&mut graph, 1, 2).is_empty());
+        assert_eq!(live.remove_edge(&mut graph, 1, 2), vec![vec![2]]);
+        assert!(live.is_empty());
+    }
+
+    #[test]
+    fn test_insert_and_remove() {
+        let mut graph = Graph::empty();
+        let mut live = LivePaths::new(0, 9, 5);
+        let edges = vec![(0, 1), (1, 9), (0, 2), (2, 3), (3, 9), (1, 3), (2, 1), (3, 1), (0, 9)];
+        for (u, v) in edges.iter() {
+            live.insert_edge(&mut graph, *u, *v);
+            assert_eq!(current(&live), current(&LivePaths::build(0, 9, 5, &graph)));
+        }
+        for (u, v) in edges.iter().rev() {
+            let before = live.len();
+            let removed = live.remove_edge(&mut graph, *u, *v);
+            assert_eq!(live.len() + removed.len(), before);
+            assert_eq!(current(&live), current(&LivePaths::build(0, 9, 5, &graph)));
+        }
+        assert!(live.is_empty());
+    }
+}
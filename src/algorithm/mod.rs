@@ -1,4 +1,11 @@
 pub mod dfs;
 pub mod incremental_dfs;
 pub mod dfs_parallel;
-
+pub mod spill_frontier;
+pub mod path_pattern;
+pub mod pattern_dfs;
+pub mod cycle_dfs;
+pub mod short_cycles;
+pub mod standing_query;
+pub mod live_paths;
+pub mod paths;
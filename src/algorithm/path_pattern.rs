@@ -0,0 +1,478 @@
+//! 边标签序列的小型正则语言
+//!
+//! 语法：
+//!     alt    := seq ('|' seq)*
+//!     seq    := repeat*
+//!     repeat := atom ('*' | '+' | '?')*
+//!     atom   := label | '.' | '(' alt ')'
+//! label由字母、数字、'_'、'-'组成，'.'匹配任意一条边（包括没有标签的边），
+//! 相邻的atom之间用空白分隔，例如 `static* dynamic dynamic+`。
+//! 编译过程：表达式 -> Thompson NFA -> 子集构造DFA，并剪掉所有到不了接受态的死状态。
+
+use std::collections::{BTreeSet, HashMap, VecDeque};
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct PatternError {
+    err_msg: String,
+}
+
+impl PatternError {
+    pub fn new(err_msg: String) -> Self {
+        PatternError {
+            err_msg,
+        }
+    }
+}
+
+impl fmt::Display for PatternError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.err_msg)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Label(String),
+    Any,
+    Star,
+    Plus,
+    Question,
+    Alt,
+    LParen,
+    RParen,
+}
+
+#[derive(Debug, Clone)]
+enum Ast {
+    Label(String),
+    Any,
+    Concat(Vec<Ast>),
+    Alt(Vec<Ast>),
+    Star(Box<Ast>),
+    Plus(Box<Ast>),
+    Question(Box<Ast>),
+}
+
+fn tokenize(src: &str) -> Result<Vec<(usize, Token)>, PatternError> {
+    let mut ret = Vec::new();
+    let chars: Vec<char> = src.chars().collect();
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        let token = match c {
+            '.' => Token::Any,
+            '*' => Token::Star,
+            '+' => Token::Plus,
+            '?' => Token::Question,
+            '|' => Token::Alt,
+            '(' => Token::LParen,
+            ')' => Token::RParen,
+            c if c.is_whitespace() => {
+                i += 1;
+                continue;
+            }
+            c if c.is_alphanumeric() || c == '_' || c == '-' => {
+                let start = i;
+                while i < chars.len() && (chars[i].is_alphanumeric() || chars[i] == '_' || chars[i] == '-') {
+                    i += 1;
+                }
+                let label: String = chars[start..i].iter().collect();
+                ret.push((start, Token::Label(label)));
+                continue;
+            }
+            c => return Err(PatternError::new(format!("unexpected char '{}' at {}", c, i))),
+        };
+        ret.push((i, token));
+        i += 1;
+    }
+    Ok(ret)
+}
+
+struct Parser {
+    tokens: Vec<(usize, Token)>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos).map(|(_, t)| t)
+    }
+
+    fn offset(&self) -> usize {
+        self.tokens.get(self.pos).map(|(o, _)| *o).unwrap_or(usize::MAX)
+    }
+
+    fn parse_alt(&mut self) -> Result<Ast, PatternError> {
+        let mut branches = vec![self.parse_seq()?];
+        while let Some(Token::Alt) = self.peek() {
+            self.pos += 1;
+            branches.push(self.parse_seq()?);
+        }
+        if branches.len() == 1 {
+            Ok(branches.pop().unwrap())
+        } else {
+            Ok(Ast::Alt(branches))
+        }
+    }
+
+    fn parse_seq(&mut self) -> Result<Ast, PatternError> {
+        let mut items = Vec::new();
+        while let Some(t) = self.peek() {
+            match t {
+                Token::Alt | Token::RParen => break,
+                _ => items.push(self.parse_repeat()?),
+            }
+        }
+        if items.is_empty() {
+            let at = self.offset();
+            return Err(PatternError::new(if at == usize::MAX {
+                "empty expression at end of pattern".to_owned()
+            } else {
+                format!("empty expression at {}", at)
+            }));
+        }
+        if items.len() == 1 {
+            Ok(items.pop().unwrap())
+        } else {
+            Ok(Ast::Concat(items))
+        }
+    }
+
+    fn parse_repeat(&mut self) -> Result<Ast, PatternError> {
+        let mut atom = self.parse_atom()?;
+        loop {
+            atom = match self.peek() {
+                Some(Token::Star) => Ast::Star(Box::new(atom)),
+                Some(Token::Plus) => Ast::Plus(Box::new(atom)),
+                Some(Token::Question) => Ast::Question(Box::new(atom)),
+                _ => break,
+            };
+            self.pos += 1;
+        }
+        Ok(atom)
+    }
+
+    fn parse_atom(&mut self) -> Result<Ast, PatternError> {
+        let at = self.offset();
+        let token = self.peek().cloned();
+        self.pos += 1;
+        match token {
+            Some(Token::Label(l)) => Ok(Ast::Label(l)),
+            Some(Token::Any) => Ok(Ast::Any),
+            Some(Token::LParen) => {
+                let inner = self.parse_alt()?;
+                if let Some(Token::RParen) = self.peek() {
+                    self.pos += 1;
+                    Ok(inner)
+                } else {
+                    Err(PatternError::new(format!("unclosed '(' at {}", at)))
+                }
+            }
+            Some(t) => Err(PatternError::new(format!("unexpected {:?} at {}", t, at))),
+            None => Err(PatternError::new("unexpected end of pattern".to_owned())),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Symbol {
+    Column(usize),
+    Any,
+}
+
+#[derive(Default)]
+struct NfaState {
+    eps: Vec<usize>,
+    trans: Vec<(Symbol, usize)>,
+}
+
+struct Nfa {
+    states: Vec<NfaState>,
+    columns: HashMap<String, usize>,
+}
+
+impl Nfa {
+    fn add_state(&mut self) -> usize {
+        self.states.push(NfaState::default());
+        self.states.len() - 1
+    }
+
+    /// 返回片段的(入口, 出口)
+    fn build(&mut self, ast: &Ast) -> (usize, usize) {
+        match ast {
+            Ast::Label(_) | Ast::Any => {
+                let symbol = match ast {
+                    Ast::Label(l) => {
+                        let next = self.columns.len();
+                        Symbol::Column(*self.columns.entry(l.clone()).or_insert(next))
+                    }
+                    _ => Symbol::Any,
+                };
+                let s = self.add_state();
+                let e = self.add_state();
+                self.states[s].trans.push((symbol, e));
+                (s, e)
+            }
+            Ast::Concat(items) => {
+                let (s, mut e) = self.build(&items[0]);
+                for item in items.iter().skip(1) {
+                    let (s1, e1) = self.build(item);
+                    self.states[e].eps.push(s1);
+                    e = e1;
+                }
+                (s, e)
+            }
+            Ast::Alt(branches) => {
+                let s = self.add_state();
+                let e = self.add_state();
+                for branch in branches.iter() {
+                    let (s1, e1) = self.build(branch);
+                    self.states[s].eps.push(s1);
+                    self.states[e1].eps.push(e);
+                }
+                (s, e)
+            }
+            Ast::Star(inner) | Ast::Plus(inner) | Ast::Question(inner) => {
+                let s = self.add_state();
+                let e = self.add_state();
+                let (s1, e1) = self.build(inner);
+                self.states[s].eps.push(s1);
+                self.states[e1].eps.push(e);
+                match ast {
+                    Ast::Star(_) => {
+                        self.states[s].eps.push(e);
+                        self.states[e1].eps.push(s1);
+                    }
+                    Ast::Plus(_) => self.states[e1].eps.push(s1),
+                    _ => self.states[s].eps.push(e),
+                }
+                (s, e)
+            }
+        }
+    }
+
+    fn closure(&self, set: &mut BTreeSet<usize>) {
+        let mut stack: Vec<usize> = set.iter().cloned().collect();
+        while let Some(s) = stack.pop() {
+            for t in self.states[s].eps.iter() {
+                if set.insert(*t) {
+                    stack.push(*t);
+                }
+            }
+        }
+    }
+}
+
+/// 编译好的标签路径模式（一个剪过死状态的DFA）
+///
+/// 每个出现在模式里的标签对应一列，另外还有一列`other`给没出现过的标签和无标签边用，
+/// 它只能被'.'匹配。状态0是初始状态。
+#[derive(Debug, Clone)]
+pub struct PathPattern {
+    columns: HashMap<String, usize>,
+    trans: Vec<Vec<Option<usize>>>,
+    accept: Vec<bool>,
+    min_hops: Vec<u32>,
+}
+
+impl PathPattern {
+    pub fn compile(src: &str) -> Result<Self, PatternError> {
+        let tokens = tokenize(src)?;
+        let mut parser = Parser { tokens, pos: 0 };
+        let ast = parser.parse_alt()?;
+        if parser.pos < parser.tokens.len() {
+            return Err(PatternError::new(format!("unexpected {:?} at {}", parser.tokens[parser.pos].1, parser.offset())));
+        }
+
+        let mut nfa = Nfa { states: Vec::new(), columns: HashMap::new() };
+        let (nfa_start, nfa_accept) = nfa.build(&ast);
+        let other = nfa.columns.len();
+        let width = other + 1;
+
+        // 子集构造
+        let mut start = BTreeSet::new();
+        start.insert(nfa_start);
+        nfa.closure(&mut start);
+        let mut index = HashMap::new();
+        let mut sets = vec![start.clone()];
+        index.insert(start, 0usize);
+        let mut trans: Vec<Vec<Option<usize>>> = Vec::new();
+        let mut i = 0;
+        while i < sets.len() {
+            let mut row = vec![None; width];
+            for (col, cell) in row.iter_mut().enumerate() {
+                let mut next = BTreeSet::new();
+                for s in sets[i].iter() {
+                    for (symbol, t) in nfa.states[*s].trans.iter() {
+                        let hit = match symbol {
+                            Symbol::Any => true,
+                            Symbol::Column(c) => *c == col,
+                        };
+                        if hit {
+                            next.insert(*t);
+                        }
+                    }
+                }
+                if next.is_empty() {
+                    continue;
+                }
+                nfa.closure(&mut next);
+                let id = match index.get(&next) {
+                    Some(id) => *id,
+                    None => {
+                        sets.push(next.clone());
+                        index.insert(next, sets.len() - 1);
+                        sets.len() - 1
+                    }
+                };
+                *cell = Some(id);
+            }
+            trans.push(row);
+            i += 1;
+        }
+        let accept: Vec<bool> = sets.iter().map(|s| s.contains(&nfa_accept)).collect();
+
+        // 反向BFS求每个状态到接受态的最少边数，到不了的就是死状态
+        let n = sets.len();
+        let mut rev = vec![Vec::new(); n];
+        for (s, row) in trans.iter().enumerate() {
+            for t in row.iter().flatten() {
+                rev[*t].push(s);
+            }
+        }
+        let mut min_hops = vec![u32::MAX; n];
+        let mut queue = VecDeque::new();
+        for s in 0..n {
+            if accept[s] {
+                min_hops[s] = 0;
+                queue.push_back(s);
+            }
+        }
+        while let Some(s) = queue.pop_front() {
+            for p in rev[s].iter() {
+                if min_hops[*p] == u32::MAX {
+                    min_hops[*p] = min_hops[s] + 1;
+                    queue.push_back(*p);
+                }
+            }
+        }
+        for row in trans.iter_mut() {
+            for cell in row.iter_mut() {
+                if let Some(t) = *cell {
+                    if min_hops[t] == u32::MAX {
+                        *cell = None;
+                    }
+                }
+            }
+        }
+
+        Ok(PathPattern {
+            columns: nfa.columns,
+            trans,
+            accept,
+            min_hops,
+        })
+    }
+
+    /// 初始状态，模式什么都匹配不了时返回None
+    #[inline]
+    pub fn start(&self) -> Option<usize> {
+        if self.min_hops[0] == u32::MAX {
+            None
+        } else {
+            Some(0)
+        }
+    }
+
+    /// 标签对应的列，None和模式里没出现过的标签都落在`other`列
+    #[inline]
+    pub fn column(&self, label: Option<&str>) -> usize {
+        label.and_then(|l| self.columns.get(l).cloned()).unwrap_or(self.columns.len())
+    }
+
+    #[inline]
+    pub fn step_column(&self, state: usize, column: usize) -> Option<usize> {
+        self.trans[state][column]
+    }
+
+    #[inline]
+    pub fn step(&self, state: usize, label: Option<&str>) -> Option<usize> {
+        self.step_column(state, self.column(label))
+    }
+
+    #[inline]
+    pub fn is_accept(&self, state: usize) -> bool {
+        self.accept[state]
+    }
+
+    /// 从state出发至少还要走几条边才能到接受态
+    #[inline]
+    pub fn min_hops(&self, state: usize) -> u32 {
+        self.min_hops[state]
+    }
+
+    pub fn matches(&self, labels: &[Option<&str>]) -> bool {
+        let mut state = match self.start() {
+            Some(s) => s,
+            None => return false,
+        };
+        for l in labels.iter() {
+            state = match self.step(state, *l) {
+                Some(s) => s,
+                None => return false,
+            };
+        }
+        self.is_accept(state)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn matches(pattern: &PathPattern, labels: &[&str]) -> bool {
+        let labels: Vec<Option<&str>> = labels.iter().map(|l| Some(*l)).collect();
+        pattern.matches(&labels)
+    }
+
+    #[test]
+    fn test_compile_and_match() {
+        let p = PathPattern::compile("static* dynamic dynamic+").unwrap();
+        assert!(matches(&p, &["dynamic", "dynamic"]));
+        assert!(matches(&p, &["static", "static", "dynamic", "dynamic", "dynamic"]));
+        assert!(!matches(&p, &["dynamic"]));
+        assert!(!matches(&p, &["static", "dynamic"]));
+        assert!(!matches(&p, &["dynamic", "static", "dynamic"]));
+        assert_eq!(p.min_hops(p.start().unwrap()), 2);
+
+        let p = PathPattern::compile("(static | dynamic)? . transfer").unwrap();
+        assert!(matches(&p, &["foo", "transfer"]));
+        assert!(matches(&p, &["static", "foo", "transfer"]));
+        assert!(p.matches(&[None, Some("transfer")]));
+        assert!(!p.matches(&[Some("transfer"), None]));
+        assert!(!matches(&p, &["a", "b", "transfer"]));
+    }
+
+    #[test]
+    fn test_dead_state() {
+        let p = PathPattern::compile("static dynamic").unwrap();
+        let s = p.start().unwrap();
+        assert_eq!(p.step(s, Some("dynamic")), None);
+        assert_eq!(p.step(s, None), None);
+        let s = p.step(s, Some("static")).unwrap();
+        assert_eq!(p.min_hops(s), 1);
+        assert!(!p.is_accept(s));
+        assert!(p.is_accept(p.step(s, Some("dynamic")).unwrap()));
+    }
+
+    #[test]
+    fn test_compile_error() {
+        assert!(PathPattern::compile("").is_err());
+        assert!(PathPattern::compile("static (dynamic").is_err());
+        assert!(PathPattern::compile("static)").is_err());
+        assert!(PathPattern::compile("* static").is_err());
+        assert!(PathPattern::compile("static | ").is_err());
+        assert!(PathPattern::compile("static & dynamic").is_err());
+    }
+}
@@ -0,0 +1,227 @@
+//! 各种搜索策略的统一入口，结果都是含起点终点的PathResult
+//! dfs系列函数本身返回不含起点和终点的中间点，只在find_trimmed_paths里保留这种旧表示
+//!
+//! 覆盖范围：
+//!   s到t的路径：Strategy里的各种dfs、dfs_parallel_spill（find_paths_spill）、pattern_dfs
+//!   环：cycle_dfs、short_cycles（find_cycles、find_short_cycles），用PathResult::from_cycle补全
+//!   增量结果：StandingQuery::to_result、LivePaths::results
+//! dfs_for_temp切出的前缀（Vec、PathTrie、SpillFrontier）只是中间状态，不是结果，仍然是GraphPath
+
+use std::collections::HashSet;
+use std::sync::Arc;
+use crate::common::error::Result;
+use crate::structure::{EdgeLabels, Graph, GraphPath, GraphView, PathResult};
+use super::cycle_dfs::cycles_through_set;
+use super::dfs::{dfs, dfs_step_by_step};
+use super::dfs_parallel::{dfs_parallel, dfs_parallel_trie};
+use super::short_cycles::all_short_cycles;
+use super::spill_frontier::dfs_parallel_spill;
+use super::path_pattern::PathPattern;
+use super::pattern_dfs::dfs_with_pattern;
+
+/// 搜索策略
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Strategy {
+    /// dfs
+    Sequential,
+    /// dfs_step_by_step，先探几步切出前缀再逐个接着探
+    StepByStep,
+    /// dfs_parallel，前缀分给4个线程
+    Parallel,
+    /// dfs_parallel_trie，前缀和结果存在前缀树里
+    Trie,
+}
+
+/// 一次查询：从source出发最多走k步到target，rev为true时逆着边走
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PathQuery {
+    pub source: i64,
+    pub target: i64,
+    pub k: u32,
+    pub rev: bool,
+}
+
+impl PathQuery {
+    pub fn new(source: i64, target: i64, k: u32) -> Self {
+        PathQuery { source, target, k, rev: false }
+    }
+
+    pub fn reversed(mut self) -> Self {
+        self.rev = !self.rev;
+        self
+    }
+}
+
+/// 旧的表示：不含起点和终点，按点序列排序
+/// 起点等于终点时没有结果；k小于2时切不出前缀，StepByStep和Parallel退化成Sequential
+pub fn find_trimmed_paths<G: GraphView + Send + Sync + 'static>(graph: Arc<G>, query: &PathQuery, strategy: Strategy) -> Vec<GraphPath> {
+    let mut result = Vec::new();
+    if query.source == query.target || query.k == 0 {
+        return result;
+    }
+    let mut path = GraphPath::new();
+    let mut visit = HashSet::new();
+    let strategy = if query.k < 2 { Strategy::Sequential } else { strategy };
+    match strategy {
+        Strategy::Sequential => {
+            dfs(query.source, query.target, query.k, graph.as_ref(), &mut result, &mut None, &mut path, query.rev, &mut visit);
+        }
+        Strategy::StepByStep => {
+            dfs_step_by_step(query.source, query.target, query.k, graph, &mut result, &mut path, query.rev, &mut visit);
+        }
+        Strategy::Parallel => {
+            dfs_parallel(query.source, query.target, query.k, graph, &mut result, None, &mut path, query.rev, &mut visit);
+        }
+        Strategy::Trie => {
+            result = dfs_parallel_trie(query.source, query.target, query.k, graph, query.rev).to_paths();
+        }
+    }
+    result.sort_unstable();
+    result
+}
+
+/// 按strategy搜索，结果是完整路径，和策略无关地按点序列排序
+pub fn find_paths<G: GraphView + Send + Sync + 'static>(graph: Arc<G>, query: &PathQuery, strategy: Strategy) -> Vec<PathResult> {
+    to_results(query, find_trimmed_paths(graph, query, strategy))
+}
+
+/// find_paths，另外按labels填上每条边的层
+pub fn find_labeled_paths<G: GraphView + Send + Sync + 'static>(
+    graph: Arc<G>,
+    labels: &EdgeLabels,
+    query: &PathQuery,
+    strategy: Strategy) -> Vec<PathResult> {
+    let mut ret = find_paths(graph, query, strategy);
+    ret.iter_mut().for_each(|p| p.set_layers(labels));
+    ret
+}
+
+/// dfs_with_pattern的完整路径版本，边的层已经填好
+pub fn find_pattern_paths(graph: &Graph, labels: &EdgeLabels, pattern: &PathPattern, query: &PathQuery) -> Vec<PathResult> {
+    let mut result = Vec::new();
+    let mut path = GraphPath::new();
+    let mut visit = HashSet::new();
+    dfs_with_pattern(query.source, query.target, query.k, graph, labels, pattern, &mut result, &mut path, query.rev, &mut visit);
+    result.sort_unstable();
+    let mut ret = to_results(query, result);
+    ret.iter_mut().for_each(|p| p.set_layers(labels));
+    ret
+}
+
+/// dfs_parallel_spill的完整路径版本，切出的前缀超过budget字节就写到dir下
+pub fn find_paths_spill<G: GraphView + Send + Sync + 'static>(
+    graph: Arc<G>,
+    query: &PathQuery,
+    dir: &str,
+    budget: usize,
+    threads: usize) -> Result<Vec<PathResult>> {
+    let mut result = dfs_parallel_spill(query.source, query.target, query.k, graph, query.rev, dir, budget, threads)?;
+    result.sort_unstable();
+    Ok(to_results(query, result))
+}
+
+/// 经过sources里的点、长度在[min_len, max_len]之间的简单环，首尾都是环的最小点，按点序列排序
+pub fn find_cycles(graph: &Graph, sources: &[i64], min_len: u32, max_len: u32) -> Vec<PathResult> {
+    let mut result = Vec::new();
+    cycles_through_set(sources, min_len, max_len, graph, &mut result);
+    result.sort_unstable();
+    result.iter().map(|c| PathResult::from_cycle(c)).collect()
+}
+
+/// 全图长度在[min_len, max_len]之间的简单环，表示和find_cycles相同
+pub fn find_short_cycles<G: GraphView>(graph: &G, min_len: u32, max_len: u32) -> Vec<PathResult> {
+    let mut result = Vec::new();
+    all_short_cycles(min_len, max_len, graph, &mut result);
+    result.sort_unstable();
+    result.iter().map(|c| PathResult::from_cycle(c)).collect()
+}
+
+fn to_results(query: &PathQuery, trimmed: Vec<GraphPath>) -> Vec<PathResult> {
+    trimmed.iter()
+        .map(|p| PathResult::from_trimmed(query.source, query.target, p, query.rev))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::structure::{CsrGraph, Direction};
+    use crate::structure::edge_label::{STATIC_LABEL, DYNAMIC_LABEL};
+
+    #[test]
+    fn test_strategies_agree() {
+        let graph = Arc::new(Graph::test_large());
+        let csr = Arc::new(CsrGraph::from_graph(&graph));
+        let ids = graph.vertices();
+        let mut found = 0;
+        for (i, s) in ids.iter().enumerate().step_by(37).take(8) {
+            let t = ids[(i * 7 + 3) % ids.len()];
+            for k in 0..5 {
+                let query = PathQuery::new(*s, t, k);
+                let expected = find_paths(graph.clone(), &query, Strategy::Sequential);
+                for p in expected.iter() {
+                    assert_eq!((p.source(), p.target()), (Some(*s), Some(t)));
+                    assert!(p.len() as u32 <= k);
+                    assert!(p.edges.iter().all(|e| graph.out_neighbors(e.from).contains(&e.to)));
+                }
+                found += expected.len();
+                assert_eq!(find_paths(graph.clone(), &query, Strategy::StepByStep), expected);
+                assert_eq!(find_paths(graph.clone(), &query, Strategy::Parallel), expected);
+                assert_eq!(find_paths(graph.clone(), &query, Strategy::Trie), expected);
+                assert_eq!(find_paths(csr.clone(), &query, Strategy::Parallel), expected);
+                let trimmed: Vec<GraphPath> = expected.iter().map(|p| p.trimmed()).collect();
+                assert_eq!(find_trimmed_paths(graph.clone(), &query, Strategy::StepByStep), trimmed);
+            }
+        }
+        assert!(found > 0);
+
+        let dir = "test_paths_spill";
+        let query = PathQuery::new(1, 3, 5);
+        let spilled = find_paths_spill(graph.clone(), &query, dir, 1 << 10, 3).unwrap();
+        assert_eq!(spilled, find_paths(graph, &query, Strategy::Sequential));
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn test_cycle_results() {
+        let mut graph = Graph::empty();
+        graph.add_directed_edge(1, 2);
+        graph.add_directed_edge(2, 3);
+        graph.add_directed_edge(3, 1);
+        graph.add_undirected_edge(3, 4);
+        let cycles = find_short_cycles(&graph, 2, 3);
+        assert_eq!(cycles.iter().map(|c| c.vertices.clone()).collect::<Vec<_>>(), vec![vec![1, 2, 3, 1], vec![3, 4, 3]]);
+        assert_eq!(find_cycles(&graph, &[4], 2, 3), cycles[1..]);
+        assert!(cycles.iter().all(|c| c.edges.iter().all(|e| graph.has_edge(e.from, e.to))));
+    }
+
+    #[test]
+    fn test_labeled_paths() {
+        let mut graph = Graph::empty();
+        let mut labels = EdgeLabels::new();
+        graph.add_undirected_edge(1, 2);
+        labels.set_undirected_label(1, 2, STATIC_LABEL);
+        for (u, v) in [(2, 3), (1, 3)].iter() {
+            graph.add_directed_edge(*u, *v);
+            labels.set_label(*u, *v, DYNAMIC_LABEL);
+        }
+        let graph = Arc::new(graph);
+
+        let paths = find_labeled_paths(graph.clone(), &labels, &PathQuery::new(1, 3, 3), Strategy::Parallel);
+        assert_eq!(paths.len(), 2);
+        assert_eq!(paths[0].vertices, vec![1, 3]);
+        assert_eq!(paths[1].vertices, vec![1, 2, 3]);
+        assert_eq!(paths[1].layer_names(&labels), vec![Some(STATIC_LABEL), Some(DYNAMIC_LABEL)]);
+
+        let paths = find_labeled_paths(graph.clone(), &labels, &PathQuery::new(3, 1, 3).reversed(), Strategy::Sequential);
+        assert_eq!(paths[0].vertices, vec![3, 1]);
+        assert_eq!(paths[1].edges[0].direction, Direction::Backward);
+        assert_eq!(paths[1].edges[0].graph_edge(), (2, 3));
+
+        let pattern = PathPattern::compile("static dynamic").unwrap();
+        let paths = find_pattern_paths(&graph, &labels, &pattern, &PathQuery::new(1, 3, 3));
+        assert_eq!(paths.len(), 1);
+        assert_eq!(paths[0].layer_names(&labels), vec![Some(STATIC_LABEL), Some(DYNAMIC_LABEL)]);
+        assert!(find_paths(graph, &PathQuery::new(1, 1, 3), Strategy::Sequential).is_empty());
+    }
+}
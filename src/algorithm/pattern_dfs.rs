@@ -0,0 +1,203 @@
+use std::collections::HashSet;
+use crate::structure::{Graph, GraphPath, EdgeLabels};
+use super::path_pattern::PathPattern;
+
+/// 把EdgeLabels里的标签id预先映射到模式的列上，省得每走一步查一次字符串
+struct LabelMatcher<'a> {
+    pattern: &'a PathPattern,
+    labels: &'a EdgeLabels,
+    columns: Vec<usize>,
+    other: usize,
+}
+
+impl<'a> LabelMatcher<'a> {
+    fn new(pattern: &'a PathPattern, labels: &'a EdgeLabels) -> Self {
+        let columns = labels.names().iter().map(|n| pattern.column(Some(n.as_str()))).collect();
+        LabelMatcher {
+            pattern,
+            labels,
+            columns,
+            other: pattern.column(None),
+        }
+    }
+
+    /// 从states里的状态经过边(src, dst)能到的状态，平行边的标签都试一遍，去重后按序返回
+    fn step(&self, states: &[usize], src: i64, dst: i64) -> Vec<usize> {
+        let ids = self.labels.label_ids(src, dst);
+        let mut ret = Vec::new();
+        for state in states.iter() {
+            if ids.is_empty() {
+                ret.extend(self.pattern.step_column(*state, self.other));
+            }
+            for id in ids.iter() {
+                ret.extend(self.pattern.step_column(*state, self.columns[*id as usize]));
+            }
+        }
+        ret.sort_unstable();
+        ret.dedup();
+        ret
+    }
+}
+
+/// 和dfs一样从now开始往外探，最多探k度，但要求路径上边标签的序列被pattern接受
+/// 停止条件：
+///     1. 遇到target，且自动机处于接受态，将当前路径加入result
+///     2. 自动机进入死状态，或剩余步数不够走到接受态，剪枝
+///     3. 探完k度，直接返回
+/// 参数说明：
+///     labels：边标签，rev为true时查的是原图里的边(v, now)
+///     result：和dfs相同，不包括起点和终点
+/// 两点之间有平行边时，只要有一条边的标签能让模式走通就算，同一个点序列只出一次
+#[allow(clippy::too_many_arguments)]
+pub fn dfs_with_pattern(
+    now: i64,
+    target: i64,
+    k: u32,
+    graph: &Graph,
+    labels: &EdgeLabels,
+    pattern: &PathPattern,
+    result: &mut Vec<GraphPath>,
+    path: &mut GraphPath,
+    rev: bool,
+    visit: &mut HashSet<i64>) {
+    let start = match pattern.start() {
+        Some(s) => s,
+        None => return,
+    };
+    let matcher = LabelMatcher::new(pattern, labels);
+    pattern_dfs(now, target, k, &[start], graph, &matcher, result, path, rev, visit);
+}
+
+/// states：沿着当前点序列能到的自动机状态，平行边的标签不同时可能不止一个
+#[allow(clippy::too_many_arguments)]
+fn pattern_dfs(
+    now: i64,
+    target: i64,
+    k: u32,
+    states: &[usize],
+    graph: &Graph,
+    matcher: &LabelMatcher,
+    result: &mut Vec<GraphPath>,
+    path: &mut GraphPath,
+    rev: bool,
+    visit: &mut HashSet<i64>) {
+    if now == target && !path.is_empty() {
+        if states.iter().any(|s| matcher.pattern.is_accept(*s)) {
+            let ans = Vec::from(&path[0..path.len() - 1]);
+            result.push(ans);
+        }
+        return;
+    } else if k == 0 {
+        return;
+    }
+    visit.insert(now);
+    let next = if rev { graph.in_v(now) } else { graph.out_v(now) };
+    if let Some(nodes) = next {
+        // 平行边在邻接表里出现多次，每个邻居只走一次
+        let mut tried = HashSet::new();
+        for v in nodes.iter() {
+            if visit.contains(v) || !tried.insert(*v) {
+                continue;
+            }
+            let mut next_states = if rev { matcher.step(states, *v, now) } else { matcher.step(states, now, *v) };
+            next_states.retain(|s| matcher.pattern.min_hops(*s) < k);
+            if next_states.is_empty() {
+                continue;
+            }
+            path.push(*v);
+            pattern_dfs(*v, target, k - 1, &next_states, graph, matcher, result, path, rev, visit);
+            path.pop();
+        }
+    }
+    visit.remove(&now);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::structure::edge_label::{STATIC_LABEL, DYNAMIC_LABEL};
+
+    fn add_static(graph: &mut Graph, labels: &mut EdgeLabels, u: i64, v: i64) {
+        graph.add_undirected_edge(u, v);
+        labels.set_undirected_label(u, v, STATIC_LABEL);
+    }
+
+    fn add_dynamic(graph: &mut Graph, labels: &mut EdgeLabels, u: i64, v: i64) {
+        graph.add_directed_edge(u, v);
+        labels.set_label(u, v, DYNAMIC_LABEL);
+    }
+
+    #[test]
+    fn test_dfs_with_pattern() {
+        let mut graph = Graph::empty();
+        let mut labels = EdgeLabels::new();
+        add_static(&mut graph, &mut labels, 1, 2);
+        add_dynamic(&mut graph, &mut labels, 2, 3);
+        add_dynamic(&mut graph, &mut labels, 3, 0);
+        add_dynamic(&mut graph, &mut labels, 1, 4);
+        add_dynamic(&mut graph, &mut labels, 4, 0);
+        add_dynamic(&mut graph, &mut labels, 1, 5);
+        add_static(&mut graph, &mut labels, 5, 0);
+        add_dynamic(&mut graph, &mut labels, 1, 0);
+        graph.add_directed_edge(1, 6);
+        graph.add_directed_edge(6, 0);
+
+        let pattern = PathPattern::compile("static* dynamic dynamic+").unwrap();
+        let mut result = Vec::new();
+        let mut path = Vec::new();
+        let mut visit = HashSet::new();
+        dfs_with_pattern(1, 0, 4, &graph, &labels, &pattern, &mut result, &mut path, false, &mut visit);
+        assert!(visit.is_empty());
+        assert!(path.is_empty());
+        assert_eq!(result.len(), 2);
+        assert!(result.contains(&vec![2, 3]));
+        assert!(result.contains(&vec![4]));
+
+        // 步数不够走完 static dynamic dynamic
+        let mut result = Vec::new();
+        dfs_with_pattern(1, 0, 2, &graph, &labels, &pattern, &mut result, &mut path, false, &mut visit);
+        assert_eq!(result, vec![vec![4]]);
+
+        // 无标签边只能被'.'匹配
+        let pattern = PathPattern::compile(". .").unwrap();
+        let mut result = Vec::new();
+        dfs_with_pattern(1, 0, 3, &graph, &labels, &pattern, &mut result, &mut path, false, &mut visit);
+        assert_eq!(result.len(), 3);
+        assert!(result.contains(&vec![4]));
+        assert!(result.contains(&vec![5]));
+        assert!(result.contains(&vec![6]));
+
+        // 反向探，标签按原图方向查，序列按探的顺序匹配
+        let pattern = PathPattern::compile("dynamic dynamic static").unwrap();
+        let mut result = Vec::new();
+        dfs_with_pattern(0, 1, 4, &graph, &labels, &pattern, &mut result, &mut path, true, &mut visit);
+        assert!(visit.is_empty());
+        assert_eq!(result, vec![vec![3, 2]]);
+    }
+
+    #[test]
+    fn test_dfs_with_pattern_parallel_edges() {
+        // 静态边1-2和动态边1->2是两条平行边，各自保留标签
+        let mut graph = Graph::empty();
+        let mut labels = EdgeLabels::new();
+        for (u, v) in [(1, 2), (2, 0)].iter() {
+            graph.add_undirected_edge(*u, *v);
+            labels.add_undirected_label(*u, *v, STATIC_LABEL);
+        }
+        graph.add_directed_edge(1, 2);
+        labels.add_label(1, 2, DYNAMIC_LABEL);
+
+        let mut path = Vec::new();
+        let mut visit = HashSet::new();
+        for (expr, expected) in [("static static", vec![vec![2]]), ("dynamic static", vec![vec![2]]), (". .", vec![vec![2]]), ("dynamic dynamic", vec![])].iter() {
+            let pattern = PathPattern::compile(expr).unwrap();
+            let mut result = Vec::new();
+            dfs_with_pattern(1, 0, 3, &graph, &labels, &pattern, &mut result, &mut path, false, &mut visit);
+            assert_eq!(&result, expected, "{}", expr);
+        }
+        let pattern = PathPattern::compile("static dynamic").unwrap();
+        let mut result = Vec::new();
+        dfs_with_pattern(0, 1, 3, &graph, &labels, &pattern, &mut result, &mut path, true, &mut visit);
+        assert_eq!(result, vec![vec![2]]);
+    }
+}
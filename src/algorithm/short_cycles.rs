@@ -0,0 +1,328 @@
+//! 全图长度受限的简单环枚举
+//!
+//! 按点id从小到大依次当起点s，只在id不小于s的点里找经过s的环，所以每个环恰好从它的最小点出发找到一次，
+//! 输出的环已经是以最小id开头的规范形式。
+//! 单个起点上用带长度上限的Johnson算法（Gupta & Suzumura的lock/blocked-by做法）：
+//! lock(v)表示v只能在深度小于lock(v)的时候被访问，探不到起点的点保持锁定，
+//! 等它的后继被证明能在blen步内回到起点后，再沿blocked-by表把锁放松到k - blen + 1。
+
+use std::collections::{HashMap, HashSet};
+use std::cmp::min;
+use std::io;
+use std::sync::Arc;
+use std::time::Instant;
+use crate::structure::{GraphPath, GraphView, PathSink};
+use super::dfs_parallel::{for_each_parallel, CONTINUE_THREADS};
+
+const UNREACHABLE: u32 = u32::MAX;
+
+struct BoundedJohnson<'a, G: GraphView> {
+    graph: &'a G,
+    start: i64,
+    min_len: u32,
+    max_len: u32,
+    lock: HashMap<i64, u32>,
+    blocked_by: HashMap<i64, HashSet<i64>>,
+    stack: Vec<i64>,
+    on_stack: HashSet<i64>,
+}
+
+impl<'a, G: GraphView> BoundedJohnson<'a, G> {
+    fn new(graph: &'a G, start: i64, min_len: u32, max_len: u32) -> Self {
+        BoundedJohnson {
+            graph,
+            start,
+            min_len,
+            max_len,
+            lock: HashMap::new(),
+            blocked_by: HashMap::new(),
+            stack: Vec::new(),
+            on_stack: HashSet::new(),
+        }
+    }
+
+    #[inline]
+    fn lock(&self, v: i64) -> u32 {
+        self.lock.get(&v).cloned().unwrap_or(UNREACHABLE)
+    }
+
+    /// 返回从v回到起点的最短步数，回不去时返回UNREACHABLE
+    /// 平行边只走一次，同一个点序列的环只报告一次
+    fn length_bounded_dfs(&mut self, v: i64, flen: u32, result: &mut Vec<GraphPath>) -> u32 {
+        let mut blen = UNREACHABLE;
+        self.lock.insert(v, flen);
+        self.stack.push(v);
+        self.on_stack.insert(v);
+        let graph = self.graph;
+        let mut tried = HashSet::new();
+        for w in graph.out_neighbors(v).iter() {
+            if *w < self.start || !tried.insert(*w) {
+                continue;
+            }
+            if *w == self.start {
+                if self.stack.len() as u32 >= self.min_len {
+                    result.push(self.stack.clone());
+                }
+                blen = 1;
+            } else if flen + 1 < self.lock(*w) && flen + 1 < self.max_len {
+                let b = self.length_bounded_dfs(*w, flen + 1, result);
+                if b != UNREACHABLE {
+                    blen = min(blen, b + 1);
+                }
+            }
+        }
+        if blen != UNREACHABLE {
+            self.relax_locks(v, blen);
+        }
+        for w in graph.out_neighbors(v).iter() {
+            if *w > self.start {
+                self.blocked_by.entry(*w).or_default().insert(v);
+            }
+        }
+        self.stack.pop();
+        self.on_stack.remove(&v);
+        blen
+    }
+
+    fn relax_locks(&mut self, u: i64, blen: u32) {
+        if blen > self.max_len {
+            return;
+        }
+        let relaxed = self.max_len - blen + 1;
+        if self.lock(u) < relaxed {
+            self.lock.insert(u, relaxed);
+            let preds: Vec<i64> = match self.blocked_by.get(&u) {
+                Some(x) => x.iter().cloned().collect(),
+                None => return,
+            };
+            for w in preds {
+                if !self.on_stack.contains(&w) {
+                    self.relax_locks(w, blen + 1);
+                }
+            }
+        }
+    }
+}
+
+/// 以start为最小点、长度在[min_len, max_len]之间的所有简单环
+pub fn bounded_cycles_from<G: GraphView>(
+    start: i64,
+    min_len: u32,
+    max_len: u32,
+    graph: &G,
+    result: &mut Vec<GraphPath>) {
+    if max_len == 0 {
+        return;
+    }
+    let mut johnson = BoundedJohnson::new(graph, start, min_len, max_len);
+    johnson.length_bounded_dfs(start, 0, result);
+}
+
+/// 全图所有长度在[min_len, max_len]之间的简单环，单线程
+pub fn all_short_cycles<G: GraphView>(
+    min_len: u32,
+    max_len: u32,
+    graph: &G,
+    result: &mut Vec<GraphPath>) {
+    let mut vertices = graph.vertices();
+    vertices.sort_unstable();
+    for s in vertices {
+        bounded_cycles_from(s, min_len, max_len, graph, result);
+    }
+}
+
+/// 全图短环任务：按起点id分批，每批分给threads个线程，做完一批就把结果写进sink并回调checkpoint，
+/// 所以中断后可以从最后一个checkpoint的下一个点（from）接着跑
+#[derive(Debug, Clone)]
+pub struct CycleJob {
+    pub min_len: u32,
+    pub max_len: u32,
+    /// 起点id下界（包含）
+    pub from: Option<i64>,
+    /// 起点id上界（不包含）
+    pub to: Option<i64>,
+    /// 每批的起点个数
+    pub batch_size: usize,
+    /// 每批分给几个线程
+    pub threads: usize,
+}
+
+impl CycleJob {
+    pub fn new(max_len: u32) -> Self {
+        CycleJob {
+            min_len: 1,
+            max_len,
+            from: None,
+            to: None,
+            batch_size: 4096,
+            threads: CONTINUE_THREADS,
+        }
+    }
+
+    /// 本任务负责的起点，升序
+    pub fn start_vertices<G: GraphView>(&self, graph: &G) -> Vec<i64> {
+        let mut ret: Vec<i64> = graph.vertices()
+            .into_iter()
+            .filter(|v| self.from.is_none_or(|f| *v >= f) && self.to.is_none_or(|t| *v < t))
+            .collect();
+        ret.sort_unstable();
+        ret
+    }
+
+    /// 返回找到的环数，checkpoint的参数是这一批最后一个起点
+    /// checkpoint在这一批写进sink并flush之后才调用，调用方可以记下此时输出的长度，续跑前截掉之后多写的结果
+    pub fn run<G, S>(
+        &self,
+        graph: Arc<G>,
+        sink: &mut S,
+        checkpoint: &mut dyn FnMut(i64) -> io::Result<()>) -> io::Result<usize>
+    where
+        G: GraphView + Send + Sync + 'static,
+        S: PathSink,
+    {
+        let now = Instant::now();
+        let vertices = self.start_vertices(graph.as_ref());
+        let mut total = 0;
+        for batch in vertices.chunks(self.batch_size.max(1)) {
+            let min_len = self.min_len;
+            let max_len = self.max_len;
+            let graph = graph.clone();
+            let parts = for_each_parallel(batch.to_vec(), self.threads, move |s, result: &mut Vec<GraphPath>| {
+                bounded_cycles_from(s, min_len, max_len, graph.as_ref(), result);
+            });
+            for res in parts {
+                total += res.len();
+                for cycle in res.iter() {
+                    sink.push(cycle)?;
+                }
+            }
+            sink.flush()?;
+            checkpoint(batch[batch.len() - 1])?;
+        }
+        println!("find {} cycles from {} vertices, cost {:?}", total, vertices.len(), now.elapsed());
+        Ok(total)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::structure::{Graph, CsrGraph};
+    use crate::algorithm::cycle_dfs::cycles_through_set;
+
+    fn brute_force(graph: &Graph, min_len: u32, max_len: u32) -> Vec<GraphPath> {
+        let mut result = Vec::new();
+        cycles_through_set(&graph.v(), min_len, max_len, graph, &mut result);
+        result.sort();
+        result
+    }
+
+    fn random_graph(seed: u64, n: i64, m: usize) -> Graph {
+        let mut state = seed;
+        let mut next = || {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            state
+        };
+        let mut graph = Graph::empty();
+        let mut edges = HashSet::new();
+        while edges.len() < m {
+            let u = (next() % n as u64) as i64;
+            let v = (next() % n as u64) as i64;
+            if u != v && edges.insert((u, v)) {
+                graph.add_directed_edge(u, v);
+            }
+        }
+        graph
+    }
+
+    #[test]
+    fn test_all_short_cycles() {
+        let mut graph = Graph::empty();
+        graph.add_directed_edge(1, 2);
+        graph.add_directed_edge(2, 3);
+        graph.add_directed_edge(3, 1);
+        graph.add_directed_edge(3, 4);
+        graph.add_directed_edge(4, 2);
+        graph.add_undirected_edge(4, 5);
+
+        let mut result = Vec::new();
+        all_short_cycles(1, 3, &graph, &mut result);
+        result.sort();
+        assert_eq!(result, vec![vec![1, 2, 3], vec![2, 3, 4], vec![4, 5]]);
+
+        let mut result = Vec::new();
+        all_short_cycles(3, 3, &graph, &mut result);
+        assert_eq!(result.len(), 2);
+    }
+
+    #[test]
+    fn test_parallel_edges() {
+        // 静态边1-2加上重复的动态边1->2
+        let mut graph = Graph::empty();
+        graph.add_undirected_edge(1, 2);
+        graph.add_directed_edge(1, 2);
+        graph.add_directed_edge(2, 3);
+        graph.add_directed_edge(3, 1);
+        let expected = vec![vec![1, 2], vec![1, 2, 3]];
+        assert_eq!(brute_force(&graph, 2, 3), expected);
+        let csr = CsrGraph::from_graph(&graph);
+        let mut result = Vec::new();
+        all_short_cycles(2, 3, &csr, &mut result);
+        result.sort();
+        assert_eq!(result, expected);
+
+        let mut result: Vec<GraphPath> = Vec::new();
+        let mut job = CycleJob::new(3);
+        job.min_len = 2;
+        job.run(Arc::new(csr), &mut result, &mut |_| Ok(())).unwrap();
+        result.sort();
+        assert_eq!(result, expected);
+    }
+
+    #[test]
+    fn test_against_brute_force() {
+        for seed in 1..20u64 {
+            let graph = random_graph(seed * 7919, 30, 90);
+            for max_len in 1..7 {
+                let mut result = Vec::new();
+                all_short_cycles(2, max_len, &graph, &mut result);
+                result.sort();
+                assert_eq!(result, brute_force(&graph, 2, max_len), "seed {} k {}", seed, max_len);
+            }
+        }
+    }
+
+    #[test]
+    fn test_cycle_job() {
+        let graph = random_graph(42, 40, 150);
+        let expected = brute_force(&graph, 1, 5);
+        let csr = Arc::new(CsrGraph::from_graph(&graph));
+
+        let mut job = CycleJob::new(5);
+        job.batch_size = 7;
+        job.threads = 3;
+        let mut result: Vec<GraphPath> = Vec::new();
+        let mut checkpoints = Vec::new();
+        let total = job.run(csr.clone(), &mut result, &mut |v| {
+            checkpoints.push(v);
+            Ok(())
+        }).unwrap();
+        assert_eq!(total, expected.len());
+        result.sort();
+        assert_eq!(result, expected);
+        assert!(checkpoints.windows(2).all(|w| w[0] < w[1]));
+
+        // 按点区间拆成两段续跑，结果和一次跑完相同
+        let mut first = CycleJob::new(5);
+        first.to = Some(20);
+        let mut second = CycleJob::new(5);
+        second.from = Some(20);
+        let mut result: Vec<GraphPath> = Vec::new();
+        first.run(csr.clone(), &mut result, &mut |_| Ok(())).unwrap();
+        second.run(csr, &mut result, &mut |_| Ok(())).unwrap();
+        result.sort();
+        assert_eq!(result, expected);
+    }
+}
@@ -0,0 +1,343 @@
+//! 内存有上限的前缀集合：dfs_for_temp在大度数点上切得很深时，前缀可能在并行阶段开始前就撑爆内存
+//! 超过预算时把内存里的前缀写成一个临时文件（二进制结果格式，见path_writer），并行阶段再从文件流式读回来分给线程
+
+use std::collections::{HashSet, VecDeque};
+use std::mem::{size_of, size_of_val, take};
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::cmp::min;
+use std::process;
+use std::thread;
+use crate::common::error::{Error, Result};
+use crate::common::io::fs;
+use crate::structure::{GraphPath, GraphView, PathCollector, PathSink};
+use crate::utils::path_writer::{BinaryPathReader, BinaryPathSink, ResultWriter};
+use super::dfs::{dfs, dfs_for_temp, continue_prefix};
+
+/// 同一进程里的临时文件编号，多个查询共用一个目录时不会重名
+static SPILL_SEQ: AtomicUsize = AtomicUsize::new(0);
+
+/// 线程每次从前缀里取多少条
+const BATCH_SIZE: usize = 256;
+
+/// 读写临时文件的缓冲按budget的1/4算，夹在这两个值之间
+const MIN_IO_BUFFER: usize = 4 << 10;
+const MAX_IO_BUFFER: usize = 4 << 20;
+
+#[inline]
+fn io_buffer_size(budget: usize) -> usize {
+    (budget / 4).clamp(MIN_IO_BUFFER, MAX_IO_BUFFER)
+}
+
+/// 估算一条前缀占的内存
+#[inline]
+fn path_bytes(path: &[i64]) -> usize {
+    size_of::<GraphPath>() + size_of_val(path)
+}
+
+/// 切分阶段的前缀集合，内存里超过budget字节就写到dir下的临时文件
+/// collect不能返回错误，写文件的第一个错误留到into_reader时返回
+pub struct SpillFrontier {
+    dir: String,
+    budget: usize,
+    mem: Vec<GraphPath>,
+    mem_bytes: usize,
+    files: Vec<String>,
+    len: usize,
+    error: Option<Error>,
+}
+
+impl SpillFrontier {
+    pub fn new(dir: &str, budget: usize) -> Result<Self> {
+        fs::mkdir(dir).map_err(|e| Error::io(dir, e))?;
+        Ok(SpillFrontier {
+            dir: dir.to_owned(),
+            budget,
+            mem: Vec::new(),
+            mem_bytes: 0,
+            files: Vec::new(),
+            len: 0,
+            error: None,
+        })
+    }
+
+    /// 前缀条数，含已经写到文件里的
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// 已经写出的临时文件
+    #[inline]
+    pub fn spilled_files(&self) -> &[String] {
+        &self.files
+    }
+
+    /// 把内存里的前缀写成一个新的临时文件
+    fn spill(&mut self) -> Result<()> {
+        let seq = SPILL_SEQ.fetch_add(1, Ordering::SeqCst);
+        let file = fs::create_path(&[self.dir.as_str(), &format!("frontier-{}-{}.bin", process::id(), seq)]);
+        // 先记下文件名，写到一半出错也能在drop时删掉
+        self.files.push(file.clone());
+        let mut sink = BinaryPathSink::with_capacity(&file, io_buffer_size(self.budget))?;
+        for p in self.mem.iter() {
+            sink.write_path(0, p)?;
+        }
+        sink.flush()?;
+        self.mem.clear();
+        self.mem.shrink_to_fit();
+        self.mem_bytes = 0;
+        Ok(())
+    }
+
+    /// 切分结束，交给并行阶段读；先读文件里的，再读内存里剩下的
+    pub fn into_reader(mut self) -> Result<FrontierReader> {
+        if let Some(e) = self.error.take() {
+            return Err(e);
+        }
+        Ok(FrontierReader {
+            buffer_size: io_buffer_size(self.budget),
+            mem: take(&mut self.mem).into_iter().collect(),
+            files: take(&mut self.files).into_iter().collect(),
+            reader: None,
+            current: None,
+        })
+    }
+}
+
+impl PathCollector for SpillFrontier {
+    fn collect(&mut self, path: &[i64]) {
+        if self.error.is_some() {
+            return;
+        }
+        self.len += 1;
+        self.mem_bytes += path_bytes(path);
+        self.mem.push(path.to_vec());
+        if self.mem_bytes > self.budget {
+            if let Err(e) = self.spill() {
+                self.error = Some(e);
+            }
+        }
+    }
+}
+
+impl Drop for SpillFrontier {
+    fn drop(&mut self) {
+        for f in self.files.iter() {
+            let _ = fs::rm(f);
+        }
+    }
+}
+
+/// 按顺序流式读出前缀，读完的文件和drop时剩下的文件都会删掉
+pub struct FrontierReader {
+    buffer_size: usize,
+    mem: VecDeque<GraphPath>,
+    files: VecDeque<String>,
+    reader: Option<BinaryPathReader>,
+    /// 正在读的文件
+    current: Option<String>,
+}
+
+impl FrontierReader {
+    /// 下一条前缀，读完返回None
+    pub fn read_path(&mut self) -> Result<Option<GraphPath>> {
+        loop {
+            if let Some(reader) = self.reader.as_mut() {
+                if let Some((_, p)) = reader.read_path()? {
+                    return Ok(Some(p));
+                }
+                self.reader = None;
+                if let Some(f) = self.current.take() {
+                    let _ = fs::rm(&f);
+                }
+            }
+            match self.files.pop_front() {
+                Some(f) => {
+                    self.current = Some(f);
+                    self.reader = Some(BinaryPathReader::with_capacity(self.current.as_ref().unwrap(), self.buffer_size)?);
+                }
+                None => return Ok(self.mem.pop_front()),
+            }
+        }
+    }
+
+    /// 最多取max条，读完返回空
+    pub fn next_batch(&mut self, max: usize) -> Result<Vec<GraphPath>> {
+        let mut ret = Vec::with_capacity(min(max, BATCH_SIZE));
+        while ret.len() < max {
+            match self.read_path()? {
+                Some(p) => ret.push(p),
+                None => break,
+            }
+        }
+        Ok(ret)
+    }
+}
+
+impl Drop for FrontierReader {
+    fn drop(&mut self) {
+        self.reader = None;
+        for f in self.current.iter().chain(self.files.iter()) {
+            let _ = fs::rm(f);
+        }
+    }
+}
+
+/// dfs_parallel的有界内存版本：切出的前缀超过budget字节就写到dir下，threads个线程每次取一批接着探
+/// k小于2时切不出前缀，直接顺序dfs
+#[allow(clippy::too_many_arguments)]
+pub fn dfs_parallel_spill<G: GraphView + Send + Sync + 'static>(
+    now: i64,
+    target: i64,
+    k: u32,
+    graph: Arc<G>,
+    rev: bool,
+    dir: &str,
+    budget: usize,
+    threads: usize) -> Result<Vec<GraphPath>> {
+    let mut result = Vec::new();
+    if now == target || k == 0 {
+        return Ok(result);
+    }
+    let mut path = GraphPath::new();
+    let mut visit = HashSet::new();
+    if k < 2 {
+        dfs(now, target, k, graph.as_ref(), &mut result, &mut None, &mut path, rev, &mut visit);
+        return Ok(result);
+    }
+
+    let mut frontier = SpillFrontier::new(dir, budget)?;
+    visit.insert(now);
+    dfs_for_temp(
+        &mut path,
+        now,
+        target,
+        min(3, k / 2),
+        graph.as_ref(),
+        &mut result,
+        &mut frontier,
+        rev,
+        &mut visit);
+
+    let reader = Arc::new(Mutex::new(frontier.into_reader()?));
+    let mut handlers = vec![];
+    for _ in 0..threads.max(1) {
+        let reader = reader.clone();
+        let graph = graph.clone();
+        let handler = thread::spawn(move || -> Result<Vec<GraphPath>> {
+            let mut result = Vec::new();
+            loop {
+                let batch = reader.lock().unwrap().next_batch(BATCH_SIZE)?;
+                if batch.is_empty() {
+                    break;
+                }
+                for mut path in batch {
+                    continue_prefix(now, &mut path, target, k, graph.as_ref(), &mut result, rev);
+                }
+            }
+            Ok(result)
+        });
+        handlers.push(handler);
+    }
+
+    let mut ret = Ok(());
+    for handle in handlers.drain(..) {
+        match handle.join().unwrap() {
+            Ok(mut res) => result.append(&mut res),
+            Err(e) => if ret.is_ok() {
+                ret = Err(e);
+            },
+        }
+    }
+    ret.map(|_| result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::structure::Graph;
+    use crate::algorithm::dfs_parallel::dfs_parallel;
+
+    #[test]
+    fn test_spill_frontier() {
+        let dir = "test_spill_frontier";
+        let paths: Vec<GraphPath> = (0..1000).map(|i| (0..i % 7 + 1).map(|v| v * 1000 + i).collect()).collect();
+        let mut frontier = SpillFrontier::new(dir, 4096).unwrap();
+        for p in paths.iter() {
+            frontier.collect(p);
+        }
+        assert_eq!(frontier.len(), paths.len());
+        let files = frontier.spilled_files().to_vec();
+        assert!(files.len() > 1);
+        assert!(files.iter().all(fs::exists));
+
+        let mut reader = frontier.into_reader().unwrap();
+        let mut read = reader.next_batch(10).unwrap();
+        assert_eq!(read.len(), 10);
+        // 第一个文件读完后就删掉了
+        read.extend(reader.next_batch(usize::MAX).unwrap());
+        assert!(!fs::exists(&files[0]));
+        assert!(reader.next_batch(10).unwrap().is_empty());
+        assert_eq!(read, paths);
+        drop(reader);
+        assert!(files.iter().all(|f| !fs::exists(f)));
+
+        // 没读完就drop也会删掉
+        let mut frontier = SpillFrontier::new(dir, 1024).unwrap();
+        paths.iter().for_each(|p| frontier.collect(p));
+        let files = frontier.spilled_files().to_vec();
+        let mut reader = frontier.into_reader().unwrap();
+        reader.read_path().unwrap();
+        drop(reader);
+        assert!(files.iter().all(|f| !fs::exists(f)));
+        fs::rmr(dir).unwrap();
+    }
+
+    #[test]
+    fn test_dfs_parallel_spill() {
+        let dir = "test_dfs_parallel_spill";
+        let graph = Arc::new(Graph::test_large());
+        let ids = graph.vertices();
+        let mut found = 0;
+        for (i, s) in ids.iter().enumerate().step_by(43).take(6) {
+            let t = ids[(i * 3 + 2) % ids.len()];
+            if *s == t {
+                continue;
+            }
+            let mut expected = Vec::new();
+            dfs_parallel(*s, t, 6, graph.clone(), &mut expected, None, &mut Vec::new(), false, &mut HashSet::new());
+            expected.sort_unstable();
+            for budget in [1 << 12, 1 << 30].iter() {
+                let mut paths = dfs_parallel_spill(*s, t, 6, graph.clone(), false, dir, *budget, 3).unwrap();
+                paths.sort_unstable();
+                assert_eq!(paths, expected);
+            }
+            found += expected.len();
+        }
+        assert!(found > 0);
+        assert!(fs::ls(dir).unwrap().is_empty());
+        fs::rmr(dir).unwrap();
+    }
+
+    #[test]
+    fn test_dfs_parallel_spill_small_k() {
+        let dir = "test_dfs_parallel_spill_small_k";
+        let mut graph = Graph::empty();
+        graph.add_directed_edge(1, 2);
+        graph.add_directed_edge(1, 3);
+        graph.add_directed_edge(3, 2);
+        let graph = Arc::new(graph);
+        assert!(dfs_parallel_spill(1, 2, 0, graph.clone(), false, dir, 0, 2).unwrap().is_empty());
+        assert_eq!(dfs_parallel_spill(1, 2, 1, graph.clone(), false, dir, 0, 2).unwrap(), vec![Vec::<i64>::new()]);
+        let mut paths = dfs_parallel_spill(1, 2, 2, graph, false, dir, 0, 2).unwrap();
+        paths.sort_unstable();
+        assert_eq!(paths, vec![vec![], vec![3]]);
+        fs::rmr(dir).unwrap();
+    }
+}
@@ -0,0 +1,370 @@
+//! 动态边流上的常驻查询
+//!
+//! 先注册(s, t, k)路径查询和k环监控，然后一条一条地插入动态边，
+//! 每次插入只返回因为这条边新出现的路径/环。
+//! 插入(u, v)时先从u逆向、从v正向各做一次有界BFS，只有 dist(s, u) + 1 + dist(v, t) <= k 的查询才需要真正去枚举。
+
+use std::collections::HashMap;
+use crate::structure::{Graph, GraphPath, PathResult};
+use super::incremental_dfs::{bounded_bfs, paths_through_edge, cycles_through_edge};
+use super::incremental_dfs::{new_edges, batch_paths, batch_cycles, HalfPaths};
+
+pub type QueryId = usize;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum StandingQuery {
+    /// s到t、长度不超过k的简单路径
+    Path { s: i64, t: i64, k: u32 },
+    /// 长度不超过k的简单环，vertex为Some时只要经过该点的环
+    Cycle { vertex: Option<i64>, k: u32 },
+}
+
+impl StandingQuery {
+    #[inline]
+    fn k(&self) -> u32 {
+        match self {
+            StandingQuery::Path { k, .. } | StandingQuery::Cycle { k, .. } => *k,
+        }
+    }
+
+    /// 把这个查询的一条结果（QueryMatch或BatchMatch的path）补全成PathResult
+    pub fn to_result(&self, path: &[i64]) -> PathResult {
+        match self {
+            StandingQuery::Path { s, t, .. } => PathResult::from_trimmed(*s, *t, path, false),
+            StandingQuery::Cycle { .. } => PathResult::from_cycle(path),
+        }
+    }
+}
+
+/// 一次插入触发的新结果
+/// 路径查询的path和dfs相同，不包括起点和终点；环监控的path是以最小id开头的环
+/// 完整路径用StandingQuery::to_result补全
+#[derive(Debug, Clone, PartialEq)]
+pub struct QueryMatch {
+    pub query: QueryId,
+    pub path: GraphPath,
+}
+
+/// 一批插入触发的新结果，edges是这条路径/环用到的本批新边，按插入顺序
+#[derive(Debug, Clone, PartialEq)]
+pub struct BatchMatch {
+    pub query: QueryId,
+    pub path: GraphPath,
+    pub edges: Vec<(i64, i64)>,
+}
+
+pub struct StandingQueries {
+    graph: Graph,
+    queries: HashMap<QueryId, StandingQuery>,
+    next_id: QueryId,
+}
+
+impl StandingQueries {
+    pub fn new(graph: Graph) -> Self {
+        StandingQueries {
+            graph,
+            queries: HashMap::new(),
+            next_id: 0,
+        }
+    }
+
+    #[inline]
+    pub fn graph(&self) -> &Graph {
+        &self.graph
+    }
+
+    pub fn into_graph(self) -> Graph {
+        self.graph
+    }
+
+    pub fn register(&mut self, query: StandingQuery) -> QueryId {
+        let id = self.next_id;
+        self.next_id += 1;
+        self.queries.insert(id, query);
+        id
+    }
+
+    pub fn register_path(&mut self, s: i64, t: i64, k: u32) -> QueryId {
+        self.register(StandingQuery::Path { s, t, k })
+    }
+
+    pub fn register_cycle(&mut self, vertex: Option<i64>, k: u32) -> QueryId {
+        self.register(StandingQuery::Cycle { vertex, k })
+    }
+
+    pub fn unregister(&mut self, id: QueryId) -> Option<StandingQuery> {
+        self.queries.remove(&id)
+    }
+
+    #[inline]
+    pub fn query(&self, id: QueryId) -> Option<&StandingQuery> {
+        self.queries.get(&id)
+    }
+
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.queries.len()
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.queries.is_empty()
+    }
+
+    /// 插入一条动态边(u, v)，返回所有因为这条边新出现的结果
+    /// 图里已经有(u, v)时照样加一条平行边，但没有新结果，见Graph上平行边的约定
+    pub fn insert_edge(&mut self, u: i64, v: i64) -> Vec<QueryMatch> {
+        let ret = if self.graph.has_edge(u, v) {
+            Vec::new()
+        } else {
+            self.matches_for_edge(u, v)
+        };
+        self.graph.add_directed_edge(u, v);
+        ret
+    }
+
+    /// 一次插入一批动态边，返回这批边带来的所有新结果
+    /// 用到多条新边的路径只出现一次；每条边都加进图里，重复的边和图里已有的边成为平行边，但不算新边，
+    /// 所以一批插入和按顺序一条条insert_edge得到的图和结果都一样
+    pub fn insert_edges(&mut self, batch: &[(i64, i64)]) -> Vec<BatchMatch> {
+        let new = new_edges(&self.graph, batch);
+        for (u, v) in batch.iter() {
+            self.graph.add_directed_edge(*u, *v);
+        }
+        let batch = new;
+        let mut ret = Vec::new();
+        let max_k = match self.queries.values().map(|q| q.k()).max() {
+            Some(k) if k > 0 => k,
+            _ => return ret,
+        };
+        let graph = &self.graph;
+        let mut to_u = HashMap::new();
+        let mut from_v = HashMap::new();
+        for (u, v) in batch.iter() {
+            to_u.entry(*u).or_insert_with(|| bounded_bfs(*u, max_k - 1, graph, true));
+            from_v.entry(*v).or_insert_with(|| bounded_bfs(*v, max_k - 1, graph, false));
+        }
+
+        let mut halves = HalfPaths::new(graph);
+        let mut ids: Vec<&QueryId> = self.queries.keys().collect();
+        ids.sort();
+        for id in ids {
+            let query = self.queries[id];
+            // 只留下这个查询可能用得上的新边
+            let relevant: Vec<(i64, i64)> = batch.iter().cloned().filter(|(u, v)| match query {
+                StandingQuery::Path { s, t, k } => within(&to_u[u], s, &from_v[v], t, k),
+                StandingQuery::Cycle { vertex, k } => {
+                    let x = vertex.unwrap_or(*u);
+                    within(&to_u[u], x, &from_v[v], x, k)
+                }
+            }).collect();
+            if relevant.is_empty() {
+                continue;
+            }
+            // 去重要看整批新边，只是枚举时跳过不相关的
+            let found = match query {
+                StandingQuery::Path { s, t, k } => batch_paths(s, t, k, &batch, &relevant, &mut halves),
+                StandingQuery::Cycle { vertex, k } => {
+                    let mut cycles = batch_cycles(k, &batch, &relevant, &mut halves);
+                    if let Some(x) = vertex {
+                        cycles.retain(|(c, _)| c.contains(&x));
+                    }
+                    cycles
+                }
+            };
+            ret.extend(found.into_iter().map(|(path, edges)| BatchMatch { query: *id, path, edges }));
+        }
+        ret
+    }
+
+    /// 假设要插入(u, v)，会新出现哪些结果，图不变
+    pub fn matches_for_edge(&self, u: i64, v: i64) -> Vec<QueryMatch> {
+        let mut ret = Vec::new();
+        let max_k = match self.queries.values().map(|q| q.k()).max() {
+            Some(k) if k > 0 => k,
+            _ => return ret,
+        };
+        // to_u[x]：x到u的最短步数；from_v[x]：v到x的最短步数
+        let to_u = bounded_bfs(u, max_k - 1, &self.graph, true);
+        let from_v = bounded_bfs(v, max_k - 1, &self.graph, false);
+
+        let mut ids: Vec<&QueryId> = self.queries.keys().collect();
+        ids.sort();
+        for id in ids {
+            let mut paths = Vec::new();
+            match self.queries[id] {
+                StandingQuery::Path { s, t, k } => {
+                    if !within(&to_u, s, &from_v, t, k) {
+                        continue;
+                    }
+                    paths_through_edge(s, t, k, u, v, &self.graph, &mut paths);
+                }
+                StandingQuery::Cycle { vertex, k } => {
+                    // 环是v -> ... -> x -> ... -> u -> v
+                    let x = vertex.unwrap_or(u);
+                    if !within(&to_u, x, &from_v, x, k) || !within(&to_u, u, &from_v, u, k) {
+                        continue;
+                    }
+                    cycles_through_edge(u, v, k, &self.graph, &mut paths);
+                    if let Some(x) = vertex {
+                        paths.retain(|c| c.contains(&x));
+                    }
+                }
+            }
+            ret.extend(paths.into_iter().map(|path| QueryMatch { query: *id, path }));
+        }
+        ret
+    }
+}
+
+/// dist(s, u) + 1 + dist(v, t) <= k
+#[inline]
+fn within(to_u: &HashMap<i64, u32>, s: i64, from_v: &HashMap<i64, u32>, t: i64, k: u32) -> bool {
+    match (to_u.get(&s), from_v.get(&t)) {
+        (Some(a), Some(b)) => a + b < k,
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+    use crate::algorithm::dfs::dfs;
+    use crate::algorithm::cycle_dfs::cycles_through_set;
+
+    fn all_paths(s: i64, t: i64, k: u32, graph: &Graph) -> Vec<GraphPath> {
+        let mut result = Vec::new();
+        let mut path = GraphPath::new();
+        let mut visit = HashSet::new();
+        dfs(s, t, k, graph, &mut result, &mut None, &mut path, false, &mut visit);
+        result
+    }
+
+    fn all_cycles(k: u32, graph: &Graph) -> Vec<GraphPath> {
+        let mut result = Vec::new();
+        cycles_through_set(&graph.v(), 1, k, graph, &mut result);
+        result
+    }
+
+    #[test]
+    fn test_standing_queries() {
+        let mut graph = Graph::empty();
+        graph.add_directed_edge(1, 2);
+        graph.add_directed_edge(3, 4);
+        graph.add_directed_edge(4, 5);
+        graph.add_undirected_edge(2, 6);
+        let mut sq = StandingQueries::new(graph);
+        let p = sq.register_path(1, 5, 4);
+        let far = sq.register_path(1, 5, 2);
+        let c = sq.register_cycle(None, 3);
+        let c6 = sq.register_cycle(Some(6), 4);
+        assert_eq!(sq.len(), 4);
+
+        assert!(sq.insert_edge(5, 7).is_empty());
+        let m = sq.insert_edge(2, 3);
+        assert_eq!(m, vec![QueryMatch { query: p, path: vec![2, 3, 4] }]);
+        let m = sq.insert_edge(6, 4);
+        assert_eq!(m, vec![QueryMatch { query: p, path: vec![2, 6, 4] }]);
+        let m = sq.insert_edge(4, 2);
+        assert_eq!(m.len(), 3);
+        assert!(m.contains(&QueryMatch { query: c, path: vec![2, 3, 4] }));
+        assert!(m.contains(&QueryMatch { query: c, path: vec![2, 6, 4] }));
+        assert!(m.contains(&QueryMatch { query: c6, path: vec![2, 6, 4] }));
+        assert_eq!(sq.query(c).unwrap().to_result(&[2, 3, 4]).vertices, vec![2, 3, 4, 2]);
+        assert_eq!(sq.query(p).unwrap().to_result(&[2, 3, 4]).vertices, vec![1, 2, 3, 4, 5]);
+        let m = sq.insert_edge(1, 5);
+        assert_eq!(m, vec![QueryMatch { query: p, path: vec![] }, QueryMatch { query: far, path: vec![] }]);
+
+        sq.unregister(p);
+        assert!(sq.query(p).is_none());
+        assert_eq!(sq.insert_edge(1, 4), vec![QueryMatch { query: far, path: vec![4] }]);
+    }
+
+    #[test]
+    fn test_against_recompute() {
+        let mut graph = Graph::empty();
+        let mut edges = Vec::new();
+        for i in 0..60i64 {
+            edges.push(((i * 7) % 13, (i * 11 + 3) % 13));
+        }
+        for (u, v) in edges.iter().take(25) {
+            if u != v {
+                graph.add_directed_edge(*u, *v);
+            }
+        }
+        let mut sq = StandingQueries::new(graph);
+        let p = sq.register_path(1, 3, 5);
+        let c = sq.register_cycle(None, 4);
+        for (u, v) in edges.iter().skip(25) {
+            if u == v {
+                continue;
+            }
+            let mut before_p = all_paths(1, 3, 5, sq.graph());
+            let mut before_c = all_cycles(4, sq.graph());
+            let m = sq.insert_edge(*u, *v);
+            for x in m.iter() {
+                if x.query == p {
+                    before_p.push(x.path.clone());
+                } else {
+                    assert_eq!(x.query, c);
+                    before_c.push(x.path.clone());
+                }
+            }
+            let mut after_p = all_paths(1, 3, 5, sq.graph());
+            let mut after_c = all_cycles(4, sq.graph());
+            before_p.sort();
+            after_p.sort();
+            before_c.sort();
+            after_c.sort();
+            assert_eq!(before_p, after_p);
+            assert_eq!(before_c, after_c);
+        }
+    }
+
+    #[test]
+    fn test_insert_edges() {
+        let mut graph = Graph::empty();
+        graph.add_directed_edge(1, 2);
+        graph.add_directed_edge(3, 4);
+        graph.add_directed_edge(4, 5);
+        graph.add_undirected_edge(2, 6);
+        let batch = vec![(2, 3), (6, 4), (4, 2), (1, 5), (2, 3), (1, 2), (5, 7)];
+
+        let mut one_by_one = StandingQueries::new(Graph::empty());
+        let mut batched = StandingQueries::new(Graph::empty());
+        for sq in [&mut one_by_one, &mut batched].iter_mut() {
+            for (u, v) in graph.adj.iter().flat_map(|(u, vs)| vs.iter().map(move |v| (*u, *v))) {
+                sq.insert_edge(u, v);
+            }
+            sq.register_path(1, 5, 4);
+            sq.register_path(1, 4, 3);
+            sq.register_cycle(None, 3);
+            sq.register_cycle(Some(6), 4);
+        }
+
+        let mut expected = Vec::new();
+        for (u, v) in batch.iter() {
+            expected.extend(one_by_one.insert_edge(*u, *v).into_iter().map(|m| (m.query, m.path)));
+        }
+        let found = batched.insert_edges(&batch);
+        assert_eq!(one_by_one.graph(), batched.graph());
+        let mut got: Vec<(QueryId, GraphPath)> = found.iter().map(|m| (m.query, m.path.clone())).collect();
+        expected.sort();
+        got.sort();
+        assert_eq!(got, expected);
+
+        // 1 -> 2 -> 6 -> 4 -> 5 只用到了新边(6, 4)，环2 -> 3 -> 4 -> 2用到了(2, 3)和(4, 2)
+        assert!(found.contains(&BatchMatch { query: 0, path: vec![2, 6, 4], edges: vec![(6, 4)] }));
+        assert!(found.contains(&BatchMatch { query: 2, path: vec![2, 3, 4], edges: vec![(2, 3), (4, 2)] }));
+        // 已有的边再插一次成为平行边，没有新结果
+        assert!(batched.insert_edges(&batch).is_empty());
+        for (u, v) in batch.iter() {
+            assert!(one_by_one.insert_edge(*u, *v).is_empty());
+        }
+        assert_eq!(one_by_one.graph(), batched.graph());
+        assert_eq!(batched.graph().adj[&6].iter().filter(|x| **x == 4).count(), 2);
+        assert!(one_by_one.insert_edge(1, 2).is_empty());
+        assert_eq!(one_by_one.graph().adj[&1].iter().filter(|x| **x == 2).count(), 4);
+    }
+}
@@ -0,0 +1,133 @@
+use paradfs::algorithm::short_cycles::CycleJob;
+use paradfs::common::io::fs;
+use paradfs::structure::CsrGraph;
+use paradfs::utils::load_binary::{load_binary_graph, load_mapped_graph};
+use paradfs::utils::load_source_text::load_data;
+use paradfs::utils::path_writer::TextPathSink;
+use std::env;
+use std::fs::{File, OpenOptions};
+use std::io;
+use std::io::Write;
+use std::process;
+use std::sync::Arc;
+
+static USAGE: &str = "usage: cycles (--text <static> <dynamic> | --binary <dir> | --mmap <dir>) --max-len <k> --output <file> \
+[--min-len <n>] [--from <id>] [--to <id>] [--batch <n>] [--threads <n>] [--resume]";
+
+fn exit_with_usage(msg: &str) -> ! {
+    eprintln!("{}", msg);
+    eprintln!("{}", USAGE);
+    process::exit(1);
+}
+
+fn parse<T: std::str::FromStr>(name: &str, value: Option<String>) -> T {
+    match value.map(|v| v.parse()) {
+        Some(Ok(x)) => x,
+        _ => exit_with_usage(&format!("invalid value for {}", name)),
+    }
+}
+
+/// 进度：最后一个做完的起点，和那时output落盘的字节数
+fn read_progress(path: &str) -> Option<(i64, u64)> {
+    let text = std::fs::read_to_string(path).ok()?;
+    let mut fields = text.split_whitespace();
+    let last = fields.next()?.parse().ok()?;
+    let len = fields.next()?.parse().ok()?;
+    Some((last, len))
+}
+
+/// 先把output落盘，再写临时文件改名，中途挂掉时progress要么是旧的要么是新的
+fn write_progress(path: &str, output: &str, last: i64) -> io::Result<()> {
+    let out = File::open(output)?;
+    out.sync_all()?;
+    let len = out.metadata()?.len();
+    let tmp = format!("{}.tmp", path);
+    let mut f = File::create(tmp.as_str())?;
+    write!(f, "{} {}", last, len)?;
+    f.sync_all()?;
+    std::fs::rename(tmp.as_str(), path)
+}
+
+/// 全图短环枚举，每做完一批起点就把最后一个起点和output的长度写进<output>.progress，
+/// 加上--resume时先把output截回progress记录的长度（丢掉最后一次checkpoint之后写的结果），
+/// 再从记录的下一个点接着跑，结果追加到output
+fn main() {
+    let mut args = env::args().skip(1);
+    let mut text = None;
+    let mut binary = None;
+    let mut mapped = None;
+    let mut output = None;
+    let mut job = CycleJob::new(0);
+    let mut resume = false;
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--text" => text = Some((parse::<String>(&arg, args.next()), parse::<String>(&arg, args.next()))),
+            "--binary" => binary = Some(parse::<String>(&arg, args.next())),
+            "--mmap" => mapped = Some(parse::<String>(&arg, args.next())),
+            "--output" => output = Some(parse::<String>(&arg, args.next())),
+            "--max-len" => job.max_len = parse(&arg, args.next()),
+            "--min-len" => job.min_len = parse(&arg, args.next()),
+            "--from" => job.from = Some(parse(&arg, args.next())),
+            "--to" => job.to = Some(parse(&arg, args.next())),
+            "--batch" => job.batch_size = parse(&arg, args.next()),
+            "--threads" => job.threads = parse(&arg, args.next()),
+            "--resume" => resume = true,
+            _ => exit_with_usage(&format!("unknown argument {}", arg)),
+        }
+    }
+    if job.max_len == 0 {
+        exit_with_usage("--max-len is required");
+    }
+    let output = output.unwrap_or_else(|| exit_with_usage("--output is required"));
+    if [text.is_some(), binary.is_some(), mapped.is_some()].iter().filter(|x| **x).count() != 1 {
+        exit_with_usage("exactly one of --text, --binary and --mmap is required");
+    }
+    if resume && job.from.is_some() {
+        exit_with_usage("--from can't be used with --resume");
+    }
+    let progress = format!("{}.progress", output);
+    let mut sink = if resume && fs::exists(progress.as_str()) {
+        let (last, len) = read_progress(progress.as_str())
+            .unwrap_or_else(|| exit_with_usage(&format!("invalid progress file {}", progress)));
+        println!("resume after vertex {}", last);
+        job.from = Some(last + 1);
+        OpenOptions::new().write(true).open(output.as_str())
+            .and_then(|f| f.set_len(len))
+            .and_then(|_| TextPathSink::append(output.as_str()))
+    } else {
+        TextPathSink::create(output.as_str())
+    }.unwrap_or_else(|e| {
+        eprintln!("open {} failed: {}", output, e);
+        process::exit(1);
+    });
+
+    let mut checkpoint = |last: i64| write_progress(progress.as_str(), output.as_str(), last);
+    let res = match (text, binary, mapped) {
+        (Some((static_path, dyn_path)), None, None) => {
+            let graph = load_data(static_path, dyn_path).unwrap_or_else(|e| {
+                eprintln!("load graph failed: {}", e);
+                process::exit(1);
+            });
+            job.run(Arc::new(CsrGraph::from_graph(&graph)), &mut sink, &mut checkpoint)
+        }
+        (None, Some(dir), None) => {
+            let graph = load_binary_graph(dir).unwrap_or_else(|e| {
+                eprintln!("load graph failed: {}", e);
+                process::exit(1);
+            });
+            job.run(Arc::new(CsrGraph::from_graph(&graph)), &mut sink, &mut checkpoint)
+        }
+        (None, None, Some(dir)) => {
+            let graph = load_mapped_graph(dir).unwrap_or_else(|e| {
+                eprintln!("map graph failed: {}", e);
+                process::exit(1);
+            });
+            job.run(Arc::new(graph), &mut sink, &mut checkpoint)
+        }
+        _ => unreachable!(),
+    };
+    if let Err(e) = res {
+        eprintln!("cycle job failed: {}", e);
+        process::exit(1);
+    }
+}
@@ -1,5 +1,6 @@
 use paradfs::structure::GraphPath;
-use paradfs::utils::load_source_text::load_data;
+use paradfs::utils::parallel_text::load_data_parallel;
+use paradfs::utils::text_format::TextFormat;
 use paradfs::algorithm::dfs::dfs;
 use paradfs::algorithm::dfs_parallel::dfs_parallel;
 use std::collections::HashSet;
@@ -9,7 +10,7 @@ use std::time::Instant;
 fn main() {
     let static_path = "Amazon.txt.static".to_string();
     let dyn_path = "Amazon.txt.dynamic".to_string();
-    let graph = load_data(static_path, dyn_path).unwrap();
+    let graph = load_data_parallel(static_path, dyn_path, &TextFormat::default(), 4).unwrap();
     
     let now = 344;
     let target = 86800;
@@ -0,0 +1,146 @@
+use std::fmt;
+use std::io;
+use std::num::ParseIntError;
+
+/// crate-wide error of loaders, builders and the io layer
+#[derive(Debug)]
+pub enum Error {
+    /// io error, with the file it happened on if known
+    Io {
+        path: Option<String>,
+        err: io::Error,
+    },
+    /// malformed binary data
+    Format {
+        path: Option<String>,
+        msg: String,
+    },
+    /// malformed text, lines start from 1
+    Parse {
+        path: Option<String>,
+        line: usize,
+        msg: String,
+    },
+    /// not enough bytes left in a ByteBuffer to get or put a value
+    Buffer {
+        need: usize,
+        remaining: usize,
+    },
+    Other(String),
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+impl Error {
+    pub fn new(msg: String) -> Self {
+        Error::Other(msg)
+    }
+
+    pub fn format<S: Into<String>>(msg: S) -> Self {
+        Error::Format {
+            path: None,
+            msg: msg.into(),
+        }
+    }
+
+    pub fn parse<S: Into<String>>(line: usize, msg: S) -> Self {
+        Error::Parse {
+            path: None,
+            line,
+            msg: msg.into(),
+        }
+    }
+
+    pub fn io(path: &str, err: io::Error) -> Self {
+        Error::Io {
+            path: Some(path.to_owned()),
+            err,
+        }
+    }
+
+    /// attach the file the error happened on, an existing path is kept
+    pub fn with_path(self, p: &str) -> Self {
+        match self {
+            Error::Io { path: None, err } => Error::Io { path: Some(p.to_owned()), err },
+            Error::Format { path: None, msg } => Error::Format { path: Some(p.to_owned()), msg },
+            Error::Parse { path: None, line, msg } => Error::Parse { path: Some(p.to_owned()), line, msg },
+            e => e,
+        }
+    }
+
+    /// true if the error is an io error of the given kind
+    pub fn is_io(&self, kind: io::ErrorKind) -> bool {
+        match self {
+            Error::Io { err, .. } => err.kind() == kind,
+            _ => false,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Io { path: Some(p), err } => write!(f, "{}: {}", p, err),
+            Error::Io { path: None, err } => write!(f, "{}", err),
+            Error::Format { path: Some(p), msg } => write!(f, "{}: {}", p, msg),
+            Error::Format { path: None, msg } => write!(f, "{}", msg),
+            Error::Parse { path: Some(p), line, msg } => write!(f, "{}:{}: {}", p, line, msg),
+            Error::Parse { path: None, line, msg } => write!(f, "line {}: {}", line, msg),
+            Error::Buffer { need, remaining } => {
+                write!(f, "buffer needs {} bytes but only {} remain", need, remaining)
+            }
+            Error::Other(msg) => write!(f, "{}", msg),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io { err, .. } => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(err: io::Error) -> Self {
+        Error::Io {
+            path: None,
+            err,
+        }
+    }
+}
+
+impl From<ParseIntError> for Error {
+    fn from(err: ParseIntError) -> Self {
+        Error::Other(format!("{:?}", err))
+    }
+}
+
+impl From<Error> for io::Error {
+    fn from(err: Error) -> Self {
+        match err {
+            Error::Io { err, .. } => err,
+            e => io::Error::new(io::ErrorKind::InvalidData, e.to_string()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_display() {
+        let e = Error::parse(3, "expected 2 fields").with_path("a.txt");
+        assert_eq!(e.to_string(), "a.txt:3: expected 2 fields");
+        let e = Error::format("bad magic").with_path("adj").with_path("other");
+        assert_eq!(e.to_string(), "adj: bad magic");
+        let e: Error = io::Error::new(io::ErrorKind::NotFound, "no such file").into();
+        assert!(e.is_io(io::ErrorKind::NotFound));
+        assert_eq!(e.with_path("x").to_string(), "x: no such file");
+        let e = Error::Buffer { need: 8, remaining: 3 };
+        assert_eq!(io::Error::from(e).kind(), io::ErrorKind::InvalidData);
+    }
+}
@@ -1,27 +1,161 @@
 #![allow(dead_code)]
 use std::mem;
-use std::intrinsics::copy_nonoverlapping;
-use std::intrinsics::copy;
+use crate::common::error::{Error, Result};
 
+/// byte order of multi-byte numbers in a binary format
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ByteOrder {
+    LittleEndian,
+    BigEndian,
+}
+
+impl ByteOrder {
+    #[inline]
+    pub fn native() -> Self {
+        if cfg!(target_endian = "big") {
+            ByteOrder::BigEndian
+        } else {
+            ByteOrder::LittleEndian
+        }
+    }
+
+    /// one-byte tag stored in file headers
+    #[inline]
+    pub fn tag(self) -> u8 {
+        match self {
+            ByteOrder::LittleEndian => 0,
+            ByteOrder::BigEndian => 1,
+        }
+    }
+
+    pub fn from_tag(tag: u8) -> Option<Self> {
+        match tag {
+            0 => Some(ByteOrder::LittleEndian),
+            1 => Some(ByteOrder::BigEndian),
+            _ => None,
+        }
+    }
+}
+
+/// numbers that can be read and written with an explicit byte order
+pub trait Number: Copy {
+    const SIZE: usize;
+
+    fn read_from(bytes: &[u8], order: ByteOrder) -> Self;
+
+    fn write_to(self, bytes: &mut [u8], order: ByteOrder);
+}
+
+macro_rules! impl_number {
+    ($($t:ty),*) => {
+        $(
+            impl Number for $t {
+                const SIZE: usize = mem::size_of::<$t>();
+
+                #[inline]
+                fn read_from(bytes: &[u8], order: ByteOrder) -> Self {
+                    let mut b = [0u8; mem::size_of::<$t>()];
+                    b.copy_from_slice(&bytes[..Self::SIZE]);
+                    match order {
+                        ByteOrder::LittleEndian => <$t>::from_le_bytes(b),
+                        ByteOrder::BigEndian => <$t>::from_be_bytes(b),
+                    }
+                }
+
+                #[inline]
+                fn write_to(self, bytes: &mut [u8], order: ByteOrder) {
+                    let b = match order {
+                        ByteOrder::LittleEndian => self.to_le_bytes(),
+                        ByteOrder::BigEndian => self.to_be_bytes(),
+                    };
+                    bytes[..Self::SIZE].copy_from_slice(&b);
+                }
+            }
+        )*
+    };
+}
+
+impl_number!(u8, i8, u16, i16, u32, i32, u64, i64, u128, i128, usize, isize, f32, f64);
+
+/// raw bytes such as magics and padding, byte order does not apply
+impl<const N: usize> Number for [u8; N] {
+    const SIZE: usize = N;
+
+    #[inline]
+    fn read_from(bytes: &[u8], _order: ByteOrder) -> Self {
+        let mut b = [0u8; N];
+        b.copy_from_slice(&bytes[..N]);
+        b
+    }
+
+    #[inline]
+    fn write_to(self, bytes: &mut [u8], _order: ByteOrder) {
+        bytes[..N].copy_from_slice(&self);
+    }
+}
+
+/// map signed to unsigned so that numbers close to zero get short varints
+#[inline]
+pub fn zigzag_encode(x: i64) -> u64 {
+    ((x << 1) ^ (x >> 63)) as u64
+}
+
+#[inline]
+pub fn zigzag_decode(x: u64) -> i64 {
+    ((x >> 1) as i64) ^ -((x & 1) as i64)
+}
+
+/// bytes taken by `x` as a LEB128 varint
+#[inline]
+pub fn varint_len(x: u64) -> usize {
+    let bits = 64 - (x | 1).leading_zeros() as usize;
+    bits.div_ceil(7)
+}
+
+/// max bytes of a u64 varint
+pub const MAX_VARINT_LEN: usize = 10;
+
+/// a byte buffer with java nio style position and limit
+///
+/// write with `put*`, `flip` to read back with `get*`, `compact` to keep the unread bytes
+/// and continue writing after them. A growable buffer doubles its capacity when a put
+/// does not fit while it is in write mode (limit == capacity); otherwise a short buffer
+/// is reported as `Error::Buffer`.
+#[derive(Clone)]
 pub struct ByteBuffer {
     data: Vec<u8>,
     pos: usize,
     limit: usize,
-    ptr: *mut u8,
+    growable: bool,
 }
 
 impl ByteBuffer {
     pub fn new(size: usize) -> Self {
-        let mut data = vec![0u8; size];
-        let ptr = data.as_mut_ptr();
         ByteBuffer {
-            data,
+            data: vec![0u8; size],
             pos: 0,
             limit: size,
-            ptr,
+            growable: false,
         }
     }
 
+    /// a buffer that grows on put instead of failing
+    pub fn growable(size: usize) -> Self {
+        let mut buf = ByteBuffer::new(size);
+        buf.growable = true;
+        buf
+    }
+
+    #[inline]
+    pub fn is_growable(&self) -> bool {
+        self.growable
+    }
+
+    #[inline]
+    pub fn set_growable(&mut self, growable: bool) {
+        self.growable = growable;
+    }
+
     #[inline]
     pub fn capacity(&self) -> usize {
         self.data.len()
@@ -37,23 +171,24 @@ impl ByteBuffer {
         self.limit
     }
 
+    /// panics if position is beyond the limit
     #[inline]
     pub fn set_position(&mut self, position: usize) {
+        assert!(position <= self.limit, "position {} is beyond limit {}", position, self.limit);
         self.pos = position;
     }
 
+    /// panics if limit is beyond the capacity, position is moved back to limit if needed
     #[inline]
     pub fn set_limit(&mut self, limit: usize) {
+        assert!(limit <= self.capacity(), "limit {} is beyond capacity {}", limit, self.capacity());
         self.limit = limit;
+        self.pos = self.pos.min(limit);
     }
 
     #[inline]
     pub fn remaining(&self) -> usize {
-        if self.limit <= self.pos {
-            0
-        } else {
-            self.limit - self.pos
-        }
+        self.limit - self.pos
     }
 
     #[inline]
@@ -73,61 +208,211 @@ impl ByteBuffer {
         self.limit = self.capacity();
     }
 
-    pub fn get<T: Copy>(&mut self) -> Result<T, ()> {
-        let size = mem::size_of::<T>();
-        if size > self.remaining() {
-            Err(())
-        } else {
-            let ret = unsafe { Ok(*(self.ptr.offset(self.pos as isize) as *const T)) };
-            self.pos += size;
-            ret
+    /// make room for `need` bytes at position, growing the buffer if allowed
+    fn reserve(&mut self, need: usize) -> Result<()> {
+        if need <= self.remaining() {
+            return Ok(());
+        }
+        if !self.growable || self.limit != self.capacity() {
+            return Err(self.short(need));
+        }
+        let size = (self.pos + need).max(self.capacity() * 2);
+        self.data.resize(size, 0);
+        self.limit = size;
+        Ok(())
+    }
+
+    /// same as reserve for an absolute write at pos
+    fn reserve_at(&mut self, pos: usize, need: usize) -> Result<()> {
+        if pos + need <= self.limit {
+            return Ok(());
         }
+        if !self.growable || self.limit != self.capacity() {
+            return Err(self.short_at(pos, need));
+        }
+        let size = (pos + need).max(self.capacity() * 2);
+        self.data.resize(size, 0);
+        self.limit = size;
+        Ok(())
+    }
+
+    /// native byte order
+    #[inline]
+    pub fn get<T: Number>(&mut self) -> Result<T> {
+        self.get_with(ByteOrder::native())
+    }
+
+    #[inline]
+    pub fn get_at<T: Number>(&self, pos: usize) -> Result<T> {
+        self.get_at_with(pos, ByteOrder::native())
+    }
+
+    /// native byte order
+    #[inline]
+    pub fn put<T: Number>(&mut self, data: T) -> Result<()> {
+        self.put_with(data, ByteOrder::native())
     }
 
-    pub fn get_at<T: Copy>(&self, pos: usize) -> Result<T, ()> {
-        let size = mem::size_of::<T>();
-        if size + pos > self.limit {
-            Err(())
+    #[inline]
+    pub fn put_at<T: Number>(&mut self, pos: usize, data: T) -> Result<()> {
+        self.put_at_with(pos, data, ByteOrder::native())
+    }
+
+    /// read a number stored in the given byte order
+    pub fn get_with<T: Number>(&mut self, order: ByteOrder) -> Result<T> {
+        if T::SIZE > self.remaining() {
+            Err(self.short(T::SIZE))
         } else {
-            unsafe {Ok(*(self.ptr.offset(pos as isize) as *const T))}
+            let ret = T::read_from(&self.data[self.pos..self.pos + T::SIZE], order);
+            self.pos += T::SIZE;
+            Ok(ret)
         }
     }
 
-    pub fn put<T: Copy>(&mut self, data: T) -> Result<(), ()> {
-        let size = mem::size_of_val(&data);
-        if size > self.remaining() {
-            Err(())
+    pub fn get_at_with<T: Number>(&self, pos: usize, order: ByteOrder) -> Result<T> {
+        if pos + T::SIZE > self.limit {
+            Err(self.short_at(pos, T::SIZE))
         } else {
-            let p = &data as *const T as *const u8;
-            unsafe {
-                copy_nonoverlapping(p, self.ptr.offset(self.pos as isize), size);
-            }
-            self.pos += size;
-            Ok(())
+            Ok(T::read_from(&self.data[pos..pos + T::SIZE], order))
+        }
+    }
+
+    /// write a number in the given byte order
+    pub fn put_with<T: Number>(&mut self, data: T, order: ByteOrder) -> Result<()> {
+        self.reserve(T::SIZE)?;
+        data.write_to(&mut self.data[self.pos..self.pos + T::SIZE], order);
+        self.pos += T::SIZE;
+        Ok(())
+    }
+
+    pub fn put_at_with<T: Number>(&mut self, pos: usize, data: T, order: ByteOrder) -> Result<()> {
+        self.reserve_at(pos, T::SIZE)?;
+        data.write_to(&mut self.data[pos..pos + T::SIZE], order);
+        Ok(())
+    }
+
+    #[inline]
+    pub fn get_le<T: Number>(&mut self) -> Result<T> {
+        self.get_with(ByteOrder::LittleEndian)
+    }
+
+    #[inline]
+    pub fn get_be<T: Number>(&mut self) -> Result<T> {
+        self.get_with(ByteOrder::BigEndian)
+    }
+
+    #[inline]
+    pub fn put_le<T: Number>(&mut self, data: T) -> Result<()> {
+        self.put_with(data, ByteOrder::LittleEndian)
+    }
+
+    #[inline]
+    pub fn put_be<T: Number>(&mut self, data: T) -> Result<()> {
+        self.put_with(data, ByteOrder::BigEndian)
+    }
+
+    /// fill dst from position, nothing is read if there are not enough bytes
+    pub fn get_slice(&mut self, dst: &mut [u8]) -> Result<()> {
+        if dst.len() > self.remaining() {
+            return Err(self.short(dst.len()));
         }
+        dst.copy_from_slice(&self.data[self.pos..self.pos + dst.len()]);
+        self.pos += dst.len();
+        Ok(())
     }
 
-    pub fn put_at<T: Copy>(&self, pos: usize, data: T) -> Result<(), ()> {
-        let size = mem::size_of::<T>();
-        if pos + size > self.limit {
-            Err(())
-        }  else {
-            let p = &data as *const T as *const u8;
-            unsafe {
-                copy_nonoverlapping(p, self.ptr.offset(pos as isize), size);
+    /// write src at position, nothing is written if it does not fit
+    pub fn put_slice(&mut self, src: &[u8]) -> Result<()> {
+        self.reserve(src.len())?;
+        self.data[self.pos..self.pos + src.len()].copy_from_slice(src);
+        self.pos += src.len();
+        Ok(())
+    }
+
+    /// the bytes between position and limit
+    #[inline]
+    pub fn remaining_slice(&self) -> &[u8] {
+        &self.data[self.pos..self.limit]
+    }
+
+    #[inline]
+    pub fn remaining_slice_mut(&mut self) -> &mut [u8] {
+        &mut self.data[self.pos..self.limit]
+    }
+
+    /// unsigned LEB128 varint, 7 bits per byte with the high bit as continuation flag
+    pub fn put_varint(&mut self, data: u64) -> Result<()> {
+        self.reserve(varint_len(data))?;
+        let mut x = data;
+        while x >= 0x80 {
+            self.data[self.pos] = (x as u8) | 0x80;
+            self.pos += 1;
+            x >>= 7;
+        }
+        self.data[self.pos] = x as u8;
+        self.pos += 1;
+        Ok(())
+    }
+
+    /// position is unchanged on error
+    pub fn get_varint(&mut self) -> Result<u64> {
+        let mut ret = 0u64;
+        let mut pos = self.pos;
+        for i in 0..MAX_VARINT_LEN {
+            if pos >= self.limit {
+                return Err(self.short(i + 1));
+            }
+            let b = self.data[pos];
+            pos += 1;
+            if i == MAX_VARINT_LEN - 1 && b > 1 {
+                return Err(Error::format("varint overflows u64"));
+            }
+            ret |= ((b & 0x7f) as u64) << (7 * i);
+            if b & 0x80 == 0 {
+                self.pos = pos;
+                return Ok(ret);
             }
-            Ok(())
         }
+        Err(Error::format("varint overflows u64"))
+    }
+
+    /// zigzag + varint
+    #[inline]
+    pub fn put_signed_varint(&mut self, data: i64) -> Result<()> {
+        self.put_varint(zigzag_encode(data))
+    }
+
+    #[inline]
+    pub fn get_signed_varint(&mut self) -> Result<i64> {
+        self.get_varint().map(zigzag_decode)
+    }
+
+    #[inline]
+    fn short(&self, need: usize) -> Error {
+        Error::Buffer { need, remaining: self.remaining() }
     }
 
+    #[inline]
+    fn short_at(&self, pos: usize, need: usize) -> Error {
+        Error::Buffer { need, remaining: self.limit.saturating_sub(pos) }
+    }
+
+    /// the whole backing array, ignoring position and limit
     #[inline]
     pub fn array(&mut self) -> &mut [u8] {
         self.data.as_mut_slice()
     }
 
+    #[inline]
+    pub fn as_slice(&self) -> &[u8] {
+        self.data.as_slice()
+    }
+
+    /// move the unread bytes to the front and get ready to write after them
     pub fn compact(&mut self) {
-        unsafe {copy(self.ptr.offset(self.pos as isize), self.ptr, self.remaining());}
-        self.pos = self.remaining();
+        let remaining = self.remaining();
+        self.data.copy_within(self.pos..self.limit, 0);
+        self.pos = remaining;
         self.limit = self.capacity();
     }
 
@@ -154,19 +439,77 @@ mod tests {
         let x = buf.get::<usize>().unwrap();
         assert_eq!(x, 1);
 
-        #[derive(Debug, Copy, Clone)]
-        struct Point {
-            x: i32,
-            y: i64,
-        }
-
         buf.clear();
-        buf.put(Point{x:1, y:2});
+        buf.put(1i32);
+        buf.put(2i64);
+        buf.put(*b"ab");
+        buf.flip();
+        assert_eq!(buf.get::<i32>().unwrap(), 1);
+        assert_eq!(buf.get::<i64>().unwrap(), 2);
+        assert_eq!(buf.get::<[u8; 2]>().unwrap(), *b"ab");
+        assert!(buf.get::<u8>().is_err());
+
+    }
+
+    #[test]
+    fn test_byte_order() {
+        let mut buf = ByteBuffer::new(64);
+        buf.put_le(0x0102_0304u32).unwrap();
+        buf.put_be(0x0102_0304u32).unwrap();
+        buf.put_be(-2i64).unwrap();
+        buf.put_with(1.5f64, ByteOrder::native()).unwrap();
+        buf.put_at_with(60, 7u16, ByteOrder::BigEndian).unwrap();
+        assert_eq!(&buf.array()[0..8], &[4, 3, 2, 1, 1, 2, 3, 4]);
+        assert_eq!(&buf.array()[60..62], &[0, 7]);
+        buf.flip();
+        assert_eq!(buf.get_at_with::<u32>(4, ByteOrder::LittleEndian).unwrap(), 0x0403_0201);
+        assert_eq!(buf.get_le::<u32>().unwrap(), 0x0102_0304);
+        assert_eq!(buf.get_be::<u32>().unwrap(), 0x0102_0304);
+        assert_eq!(buf.get_be::<i64>().unwrap(), -2);
+        assert_eq!(buf.get::<f64>().unwrap(), 1.5);
+        assert!(buf.get_le::<u8>().is_err());
+        assert_eq!(ByteOrder::from_tag(ByteOrder::BigEndian.tag()), Some(ByteOrder::BigEndian));
+        assert_eq!(ByteOrder::from_tag(9), None);
+    }
+
+    #[test]
+    fn test_varint() {
+        let values = [0u64, 1, 127, 128, 300, 16383, 16384, u32::MAX as u64, u64::MAX];
+        let mut buf = ByteBuffer::new(256);
+        for x in values.iter() {
+            let before = buf.position();
+            buf.put_varint(*x).unwrap();
+            assert_eq!(buf.position() - before, varint_len(*x));
+        }
+        for x in [0i64, -1, 1, -64, 64, i64::MIN, i64::MAX].iter() {
+            buf.put_signed_varint(*x).unwrap();
+        }
         buf.flip();
-        let p = buf.get::<Point>().unwrap();
-        assert_eq!(p.x, 1);
-        assert_eq!(p.y, 2);
+        assert_eq!(buf.get::<[u8; 3]>().unwrap(), [0, 1, 127]);
+        assert_eq!(buf.get::<[u8; 2]>().unwrap(), [0x80, 0x01]);
+        for x in values.iter().skip(4) {
+            assert_eq!(buf.get_varint().unwrap(), *x);
+        }
+        for x in [0i64, -1, 1, -64, 64, i64::MIN, i64::MAX].iter() {
+            assert_eq!(buf.get_signed_varint().unwrap(), *x);
+        }
+        assert!(buf.get_varint().is_err());
 
+        assert_eq!(zigzag_encode(-1), 1);
+        assert_eq!(zigzag_encode(1), 2);
+        assert_eq!(varint_len(u64::MAX), MAX_VARINT_LEN);
+
+        // truncated and overflowing varints
+        let mut buf = ByteBuffer::new(16);
+        buf.put([0xffu8; 11]).unwrap();
+        buf.flip();
+        assert!(buf.get_varint().is_err());
+        assert_eq!(buf.position(), 0);
+        buf.set_limit(3);
+        assert!(buf.get_varint().is_err());
+        let mut small = ByteBuffer::new(1);
+        assert!(small.put_varint(128).is_err());
+        assert_eq!(small.position(), 0);
     }
 
     #[allow(unused_must_use)]
@@ -183,4 +526,50 @@ mod tests {
         assert_eq!(buf.get::<usize>().unwrap(), 2);
     }
 
-}
\ No newline at end of file
+    #[test]
+    fn test_growable() {
+        let mut buf = ByteBuffer::new(4);
+        assert!(buf.put(1u64).is_err());
+        assert_eq!(buf.position(), 0);
+
+        let mut buf = ByteBuffer::growable(4);
+        for i in 0..100u64 {
+            buf.put_le(i).unwrap();
+        }
+        buf.put_varint(u64::MAX).unwrap();
+        buf.put_slice(b"tail").unwrap();
+        assert!(buf.capacity() >= 800 + MAX_VARINT_LEN + 4);
+        buf.put_at_with(0, 7u64, ByteOrder::LittleEndian).unwrap();
+        buf.flip();
+        assert_eq!(buf.get_le::<u64>().unwrap(), 7);
+        for i in 1..100u64 {
+            assert_eq!(buf.get_le::<u64>().unwrap(), i);
+        }
+        assert_eq!(buf.get_varint().unwrap(), u64::MAX);
+        let mut tail = [0u8; 4];
+        buf.get_slice(&mut tail).unwrap();
+        assert_eq!(&tail, b"tail");
+        assert!(buf.get_slice(&mut tail).is_err());
+
+        // no growth while reading
+        assert!(buf.put(1u8).is_err());
+        buf.set_limit(2);
+        assert_eq!(buf.position(), 2);
+        assert!(buf.put_slice(&[1]).is_err());
+    }
+
+    #[test]
+    fn test_slices() {
+        let mut buf = ByteBuffer::new(8);
+        buf.put_slice(&[1, 2, 3, 4, 5]).unwrap();
+        assert!(buf.put_slice(&[0; 4]).is_err());
+        assert_eq!(buf.position(), 5);
+        buf.flip();
+        buf.get::<u8>().unwrap();
+        assert_eq!(buf.remaining_slice(), &[2, 3, 4, 5]);
+        buf.remaining_slice_mut()[0] = 9;
+        buf.compact();
+        assert_eq!((buf.position(), buf.limit()), (4, 8));
+        assert_eq!(&buf.as_slice()[..4], &[9, 3, 4, 5]);
+    }
+}
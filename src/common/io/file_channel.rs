@@ -1,66 +1,352 @@
 #![allow(dead_code)]
 #![allow(unused_must_use)]
-use std::fs;
-use std::fs::File;
+use std::fs::{File, OpenOptions};
 use std::path::{Path};
 use std::io::prelude::*;
-use std::io::Error;
-use std::slice;
+use std::io::SeekFrom;
+use crate::common::error::{Error, Result};
 
 use super::*;
 
+/// how a FileChannel opens its file
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OpenMode {
+    /// read only, the file must exist
+    Read,
+    /// write only, created or truncated
+    Write,
+    /// write only at the end, created if missing
+    Append,
+    /// read and write, created if missing, existing content is kept
+    ReadWrite,
+}
+
+impl OpenMode {
+    fn options(self) -> OpenOptions {
+        let mut options = OpenOptions::new();
+        match self {
+            OpenMode::Read => options.read(true),
+            OpenMode::Write => options.write(true).create(true).truncate(true),
+            OpenMode::Append => options.append(true).create(true),
+            OpenMode::ReadWrite => options.read(true).write(true).create(true).truncate(false),
+        };
+        options
+    }
+}
+
+#[cfg(unix)]
+fn pread(f: &File, data: &mut [u8], offset: u64) -> std::io::Result<usize> {
+    std::os::unix::fs::FileExt::read_at(f, data, offset)
+}
+
+#[cfg(unix)]
+fn pwrite(f: &File, data: &[u8], offset: u64) -> std::io::Result<usize> {
+    std::os::unix::fs::FileExt::write_at(f, data, offset)
+}
+
+/// on windows the file cursor is moved
+#[cfg(windows)]
+fn pread(f: &File, data: &mut [u8], offset: u64) -> std::io::Result<usize> {
+    std::os::windows::fs::FileExt::seek_read(f, data, offset)
+}
+
+#[cfg(windows)]
+fn pwrite(f: &File, data: &[u8], offset: u64) -> std::io::Result<usize> {
+    std::os::windows::fs::FileExt::seek_write(f, data, offset)
+}
+
 pub struct FileChannel {
     f: File,
     filename: String,
 }
 
 impl FileChannel {
-    pub fn create<P: AsRef<Path>>(p: P) -> Self {
-        let f = File::create(p.as_ref()).unwrap();
-        FileChannel {
+    pub fn open_with<P: AsRef<Path>>(p: P, mode: OpenMode) -> Result<Self> {
+        let filename = p.as_ref().to_string_lossy().into_owned();
+        let f = mode.options().open(p.as_ref()).map_err(|e| Error::io(&filename, e))?;
+        Ok(FileChannel {
             f,
-            filename: p.as_ref().to_str().unwrap().to_owned(),
-        }
+            filename,
+        })
     }
 
-    pub fn open<P: AsRef<Path>>(p: P) -> Self {
-        let f = File::open(p.as_ref()).unwrap();
-        FileChannel {
-            f,
-            filename: p.as_ref().to_str().unwrap().to_owned(),
-        }
+    #[inline]
+    pub fn create<P: AsRef<Path>>(p: P) -> Result<Self> {
+        FileChannel::open_with(p, OpenMode::Write)
+    }
+
+    #[inline]
+    pub fn open<P: AsRef<Path>>(p: P) -> Result<Self> {
+        FileChannel::open_with(p, OpenMode::Read)
+    }
+
+    #[inline]
+    pub fn append<P: AsRef<Path>>(p: P) -> Result<Self> {
+        FileChannel::open_with(p, OpenMode::Append)
+    }
+
+    #[inline]
+    pub fn filename(&self) -> &str {
+        &self.filename
+    }
+
+    #[inline]
+    fn err(&self, e: std::io::Error) -> Error {
+        Error::io(&self.filename, e)
     }
 
     /// after write, the buf is cleared
-    pub fn write(&mut self, buf: &mut ByteBuffer) -> Result<(), Error> {
-        let ptr = buf.array().as_mut_ptr();
-        let data = unsafe {
-            slice::from_raw_parts_mut(ptr.offset(buf.position() as isize), buf.limit() - buf.position())
-        };
-        self.f.write_all(data)?;
+    pub fn write(&mut self, buf: &mut ByteBuffer) -> Result<()> {
+        self.f.write_all(buf.remaining_slice()).map_err(|e| Error::io(&self.filename, e))?;
         buf.clear();
         Ok(())
     }
 
     /// read at most bytes buf remaining
-    pub fn read(&mut self, buf: &mut ByteBuffer) -> Result<usize, Error> {
-        let ptr = buf.array().as_mut_ptr();
+    pub fn read(&mut self, buf: &mut ByteBuffer) -> Result<usize> {
         let position = buf.position();
-        let data = unsafe {
-            slice::from_raw_parts_mut(ptr.offset(position as isize), buf.limit() - position)
-        };
-        let size = self.f.read(data)?;
+        let size = self.f.read(buf.remaining_slice_mut()).map_err(|e| Error::io(&self.filename, e))?;
         buf.set_position(position + size);
         Ok(size)
     }
 
+    /// read from offset until buf is full or the end of file, the file position is not used
+    /// so several threads can read one channel at different offsets
+    pub fn read_at(&self, buf: &mut ByteBuffer, offset: u64) -> Result<usize> {
+        let mut total = 0;
+        while buf.has_remaining() {
+            let position = buf.position();
+            let size = match pread(&self.f, buf.remaining_slice_mut(), offset + total as u64) {
+                Ok(size) => size,
+                Err(ref e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(self.err(e)),
+            };
+            if size == 0 {
+                break;
+            }
+            buf.set_position(position + size);
+            total += size;
+        }
+        Ok(total)
+    }
+
+    /// write all bytes buf remaining at offset, the file position is not used; after write, the buf is cleared
+    pub fn write_at(&self, buf: &mut ByteBuffer, offset: u64) -> Result<()> {
+        let mut data = buf.remaining_slice();
+        let mut offset = offset;
+        while !data.is_empty() {
+            match pwrite(&self.f, data, offset) {
+                Ok(0) => return Err(self.err(std::io::ErrorKind::WriteZero.into())),
+                Ok(size) => {
+                    data = &data[size..];
+                    offset += size as u64;
+                }
+                Err(ref e) if e.kind() == std::io::ErrorKind::Interrupted => {}
+                Err(e) => return Err(self.err(e)),
+            }
+        }
+        buf.clear();
+        Ok(())
+    }
+
+    /// move the file position used by read and write, returns the new position
+    pub fn seek(&mut self, pos: u64) -> Result<u64> {
+        self.f.seek(SeekFrom::Start(pos)).map_err(|e| Error::io(&self.filename, e))
+    }
+
+    /// current file position
+    pub fn position(&mut self) -> Result<u64> {
+        self.f.stream_position().map_err(|e| Error::io(&self.filename, e))
+    }
+
+    /// cut or extend the file to len bytes, the file position is unchanged
+    pub fn truncate(&mut self, len: u64) -> Result<()> {
+        self.f.set_len(len).map_err(|e| Error::io(&self.filename, e))
+    }
+
+    pub fn flush(&mut self) -> Result<()> {
+        self.f.flush().map_err(|e| Error::io(&self.filename, e))
+    }
+
+    /// flush file data to the disk
+    pub fn sync(&mut self) -> Result<()> {
+        self.f.sync_data().map_err(|e| Error::io(&self.filename, e))
+    }
+
+    #[inline]
+    pub fn len(&self) -> Result<u64> {
+        let meta = self.f.metadata().map_err(|e| Error::io(&self.filename, e))?;
+        Ok(meta.len())
+    }
+
     #[inline]
-    pub fn len(&self) -> u64 {
-        fs::metadata(self.filename.as_str()).unwrap().len()
+    pub fn is_empty(&self) -> Result<bool> {
+        Ok(self.len()? == 0)
     }
 
 }
 
+/// FileChannel with a write buffer: puts go to the buffer, which is written out when full
+/// and on flush or drop
+pub struct BufferedWriter {
+    fc: FileChannel,
+    buf: ByteBuffer,
+}
+
+impl BufferedWriter {
+    pub fn new(fc: FileChannel, capacity: usize) -> Self {
+        BufferedWriter { fc, buf: ByteBuffer::new(capacity) }
+    }
+
+    #[inline]
+    pub fn channel(&self) -> &FileChannel {
+        &self.fc
+    }
+
+    /// make room for need bytes, values larger than the buffer are an error
+    fn reserve(&mut self, need: usize) -> Result<()> {
+        if self.buf.remaining() < need {
+            self.write_buf()?;
+        }
+        Ok(())
+    }
+
+    fn write_buf(&mut self) -> Result<()> {
+        self.buf.flip();
+        self.fc.write(&mut self.buf)
+    }
+
+    pub fn put<T: Number>(&mut self, data: T) -> Result<()> {
+        self.reserve(T::SIZE)?;
+        self.buf.put(data)
+    }
+
+    pub fn put_with<T: Number>(&mut self, data: T, order: ByteOrder) -> Result<()> {
+        self.reserve(T::SIZE)?;
+        self.buf.put_with(data, order)
+    }
+
+    #[inline]
+    pub fn put_le<T: Number>(&mut self, data: T) -> Result<()> {
+        self.put_with(data, ByteOrder::LittleEndian)
+    }
+
+    pub fn put_varint(&mut self, data: u64) -> Result<()> {
+        self.reserve(varint_len(data))?;
+        self.buf.put_varint(data)
+    }
+
+    #[inline]
+    pub fn put_signed_varint(&mut self, data: i64) -> Result<()> {
+        self.put_varint(zigzag_encode(data))
+    }
+
+    /// slices larger than the buffer are written directly
+    pub fn put_slice(&mut self, src: &[u8]) -> Result<()> {
+        if src.len() <= self.buf.capacity() {
+            self.reserve(src.len())?;
+            return self.buf.put_slice(src);
+        }
+        self.write_buf()?;
+        self.fc.f.write_all(src).map_err(|e| Error::io(&self.fc.filename, e))
+    }
+
+    /// write out the buffer
+    pub fn flush(&mut self) -> Result<()> {
+        self.write_buf()?;
+        self.fc.flush()
+    }
+}
+
+impl Drop for BufferedWriter {
+    fn drop(&mut self) {
+        let _ = self.write_buf();
+    }
+}
+
+/// FileChannel with a read buffer that is refilled when a get runs short
+pub struct BufferedReader {
+    fc: FileChannel,
+    buf: ByteBuffer,
+    eof: bool,
+}
+
+impl BufferedReader {
+    pub fn new(fc: FileChannel, capacity: usize) -> Self {
+        let mut buf = ByteBuffer::new(capacity);
+        buf.flip();
+        BufferedReader { fc, buf, eof: false }
+    }
+
+    #[inline]
+    pub fn channel(&self) -> &FileChannel {
+        &self.fc
+    }
+
+    /// read until at least need bytes (at most the capacity) are buffered or the end of file
+    fn fill(&mut self, need: usize) -> Result<()> {
+        let need = need.min(self.buf.capacity());
+        if self.buf.remaining() >= need || self.eof {
+            return Ok(());
+        }
+        self.buf.compact();
+        while self.buf.position() < need {
+            if self.fc.read(&mut self.buf)? == 0 {
+                self.eof = true;
+                break;
+            }
+        }
+        self.buf.flip();
+        Ok(())
+    }
+
+    /// true once everything in the file has been read
+    pub fn is_eof(&mut self) -> Result<bool> {
+        self.fill(1)?;
+        Ok(!self.buf.has_remaining())
+    }
+
+    pub fn get<T: Number>(&mut self) -> Result<T> {
+        self.fill(T::SIZE)?;
+        self.buf.get()
+    }
+
+    pub fn get_with<T: Number>(&mut self, order: ByteOrder) -> Result<T> {
+        self.fill(T::SIZE)?;
+        self.buf.get_with(order)
+    }
+
+    #[inline]
+    pub fn get_le<T: Number>(&mut self) -> Result<T> {
+        self.get_with(ByteOrder::LittleEndian)
+    }
+
+    pub fn get_varint(&mut self) -> Result<u64> {
+        self.fill(MAX_VARINT_LEN)?;
+        self.buf.get_varint()
+    }
+
+    #[inline]
+    pub fn get_signed_varint(&mut self) -> Result<i64> {
+        self.get_varint().map(zigzag_decode)
+    }
+
+    /// fill dst, slices larger than the buffer are read in pieces
+    pub fn get_slice(&mut self, dst: &mut [u8]) -> Result<()> {
+        let mut done = 0;
+        while done < dst.len() {
+            self.fill(1)?;
+            let n = self.buf.remaining().min(dst.len() - done);
+            if n == 0 {
+                return Err(Error::Buffer { need: dst.len() - done, remaining: 0 });
+            }
+            self.buf.get_slice(&mut dst[done..done + n])?;
+            done += n;
+        }
+        Ok(())
+    }
+}
+
 #[cfg(test)]
 mod tests {
 
@@ -72,9 +358,10 @@ mod tests {
     #[test]
     fn test_file_channel() {
         mkdir(TEST_DIR);
-        let test_file = create_path(&vec![TEST_DIR, "aaa"]);
-        let mut fc = FileChannel::create(test_file.as_str());
-        assert_eq!(fc.len(), 0);
+        let test_file = create_path(&[TEST_DIR, "aaa"]);
+        let mut fc = FileChannel::create(test_file.as_str()).unwrap();
+        assert_eq!(fc.len().unwrap(), 0);
+        assert!(fc.is_empty().unwrap());
         let mut buf = ByteBuffer::new(1024);
         for i in 0..20usize {
             buf.put(i);
@@ -82,11 +369,11 @@ mod tests {
         buf.flip();
         let limit = buf.limit();
         fc.write(&mut buf);
-        assert_eq!(fc.len() as usize, limit);
+        assert_eq!(fc.len().unwrap() as usize, limit);
         assert_eq!(buf.position(), 0);
         assert_eq!(buf.limit(), buf.capacity());
 
-        let mut fc_read = FileChannel::open(test_file.as_str());
+        let mut fc_read = FileChannel::open(test_file.as_str()).unwrap();
         fc_read.read(&mut buf).unwrap();
         buf.flip();
         let mut now = 0usize;
@@ -96,16 +383,102 @@ mod tests {
         }
 
         buf.clear();
-        let mut fc_read = FileChannel::open(test_file.as_str());
+        let mut fc_read = FileChannel::open(test_file.as_str()).unwrap();
         for _ in 0..10 {
             buf.put(100usize).unwrap();
         }
         fc_read.read(&mut buf).unwrap();
         buf.flip();
-        assert_eq!(buf.limit(), 80 + (fc_read.len() as usize));
+        assert_eq!(buf.limit(), 80 + (fc_read.len().unwrap() as usize));
 
         rmr(TEST_DIR);
+        let err = FileChannel::open(test_file.as_str()).err().unwrap();
+        assert!(err.is_io(std::io::ErrorKind::NotFound));
+        assert!(err.to_string().starts_with(test_file.as_str()));
     }
 
+    #[test]
+    fn test_positional_and_modes() {
+        let dir = "test_file_channel_modes";
+        mkdir(dir);
+        let path = create_path(&[dir, "data"]);
+        let mut buf = ByteBuffer::new(4096);
+        let mut fc = FileChannel::create(path.as_str()).unwrap();
+        for i in 0..512u64 {
+            buf.put_le(i).unwrap();
+        }
+        buf.flip();
+        fc.write(&mut buf).unwrap();
+        assert_eq!(fc.position().unwrap(), 4096);
 
-}
\ No newline at end of file
+        // several threads read one channel at different offsets
+        let fc = std::sync::Arc::new(FileChannel::open(path.as_str()).unwrap());
+        let handlers: Vec<_> = (0..4u64).map(|i| {
+            let fc = fc.clone();
+            std::thread::spawn(move || {
+                let mut buf = ByteBuffer::new(1024);
+                assert_eq!(fc.read_at(&mut buf, i * 1024).unwrap(), 1024);
+                buf.flip();
+                (0..128u64).all(|j| buf.get_le::<u64>().unwrap() == i * 128 + j)
+            })
+        }).collect();
+        assert!(handlers.into_iter().all(|h| h.join().unwrap()));
+        let mut buf = ByteBuffer::new(64);
+        assert_eq!(fc.read_at(&mut buf, 4090).unwrap(), 6);
+
+        let mut fc = FileChannel::open_with(path.as_str(), OpenMode::ReadWrite).unwrap();
+        buf.clear();
+        buf.put_le(u64::MAX).unwrap();
+        buf.flip();
+        fc.write_at(&mut buf, 8).unwrap();
+        assert_eq!(fc.position().unwrap(), 0);
+        assert_eq!(fc.seek(8).unwrap(), 8);
+        buf.set_limit(16);
+        fc.read(&mut buf).unwrap();
+        buf.flip();
+        assert_eq!((buf.get_le::<u64>().unwrap(), buf.get_le::<u64>().unwrap()), (u64::MAX, 2));
+        fc.truncate(100).unwrap();
+        assert_eq!(fc.len().unwrap(), 100);
+        fc.sync().unwrap();
+
+        let mut fc = FileChannel::append(path.as_str()).unwrap();
+        buf.clear();
+        buf.put_slice(b"end").unwrap();
+        buf.flip();
+        fc.write(&mut buf).unwrap();
+        fc.flush().unwrap();
+        assert_eq!(fc.len().unwrap(), 103);
+        assert!(FileChannel::open(create_path(&[dir, "missing"])).is_err());
+        rmr(dir);
+    }
+
+    #[test]
+    fn test_buffered() {
+        let dir = "test_file_channel_buffered";
+        mkdir(dir);
+        let path = create_path(&[dir, "data"]);
+        {
+            let mut w = BufferedWriter::new(FileChannel::create(path.as_str()).unwrap(), 16);
+            for i in 0..100i64 {
+                w.put_le(i).unwrap();
+                w.put_signed_varint(-i).unwrap();
+            }
+            w.put_slice(&[7u8; 40]).unwrap();
+            w.put(1u8).unwrap();
+        }
+        let mut r = BufferedReader::new(FileChannel::open(path.as_str()).unwrap(), 16);
+        for i in 0..100i64 {
+            assert_eq!(r.get_le::<i64>().unwrap(), i);
+            assert_eq!(r.get_signed_varint().unwrap(), -i);
+        }
+        let mut data = [0u8; 40];
+        r.get_slice(&mut data).unwrap();
+        assert_eq!(data, [7u8; 40]);
+        assert!(!r.is_eof().unwrap());
+        assert_eq!(r.get::<u8>().unwrap(), 1);
+        assert!(r.is_eof().unwrap());
+        assert!(r.get::<u8>().is_err());
+        assert!(r.get_slice(&mut data).is_err());
+        rmr(dir);
+    }
+}
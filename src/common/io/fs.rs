@@ -15,8 +15,8 @@ pub fn ls<P: AsRef<Path>>(path: P) -> Result<Vec<String>, Error> {
     let mut ret = Vec::new();
     let paths = fs::read_dir(path)?;
     for path in paths {
-        let path_buf = path.unwrap().path();
-        let filename = path_buf.to_str().unwrap().to_owned();
+        let path_buf = path?.path();
+        let filename = path_buf.to_string_lossy().into_owned();
         ret.push(filename);
     }
     Ok(ret)
@@ -48,12 +48,29 @@ pub fn is_dir<P: AsRef<Path>>(path: P) -> bool {
     path.as_ref().is_dir()
 }
 
-pub fn create_path<P: AsRef<Path>>(components: &Vec<P>) -> String {
+/// fsync a file or a directory; syncing a directory makes the entries created,
+/// renamed or removed in it durable
+pub fn sync<P: AsRef<Path>>(path: P) -> Result<(), Error> {
+    File::open(path)?.sync_all()
+}
+
+/// fsync every regular file directly under dir, then dir itself
+pub fn sync_dir_all<P: AsRef<Path>>(dir: P) -> Result<(), Error> {
+    for entry in fs::read_dir(dir.as_ref())? {
+        let path = entry?.path();
+        if path.is_file() {
+            sync(&path)?;
+        }
+    }
+    sync(dir)
+}
+
+pub fn create_path<P: AsRef<Path>>(components: &[P]) -> String {
     let mut path_buf = PathBuf::new();
     for c in components.iter() {
         path_buf.push(c);
     }
-    path_buf.to_str().unwrap().to_owned()
+    path_buf.to_string_lossy().into_owned()
 }
 
 #[allow(unused_imports)]
@@ -73,13 +90,13 @@ mod tests {
 //        assert!(exists(test_dir) && is_dir(test_dir));
 //
 //        let test_path1 = "test_dir/aaa";
-//        let test_path2 = create_path(&vec![test_dir, "aaa"]);
+//        let test_path2 = create_path(&[test_dir, "aaa"]);
 //        assert_eq!(test_path1, test_path2.as_str());
 //
 //        touch(test_path1);
 //        assert!(exists(test_path1));
 //
-//        let test_path3 = create_path(&vec![test_dir, "bbb"]);
+//        let test_path3 = create_path(&[test_dir, "bbb"]);
 //        touch(test_path3.as_str());
 //        assert!(exists(test_path3.as_str()));
 //
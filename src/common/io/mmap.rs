@@ -0,0 +1,135 @@
+use std::fs::File;
+use std::io::{Error, ErrorKind};
+use std::path::Path;
+use std::slice;
+
+/// read-only memory map of a whole file, unmapped on drop
+pub struct Mmap {
+    ptr: *const u8,
+    len: usize,
+}
+
+unsafe impl Send for Mmap {}
+
+unsafe impl Sync for Mmap {}
+
+// `offset` is declared as a 64-bit off_t, which only holds on 64-bit unix targets
+#[cfg(all(unix, target_pointer_width = "64"))]
+mod sys {
+    use std::os::raw::{c_int, c_void};
+
+    pub const PROT_READ: c_int = 1;
+    pub const MAP_PRIVATE: c_int = 2;
+    pub const MAP_FAILED: *mut c_void = !0 as *mut c_void;
+
+    extern "C" {
+        pub fn mmap(addr: *mut c_void, len: usize, prot: c_int, flags: c_int, fd: c_int, offset: i64) -> *mut c_void;
+        pub fn munmap(addr: *mut c_void, len: usize) -> c_int;
+    }
+}
+
+impl Mmap {
+    #[cfg(all(unix, target_pointer_width = "64"))]
+    pub fn open<P: AsRef<Path>>(p: P) -> Result<Self, Error> {
+        use std::os::unix::io::AsRawFd;
+        let f = File::open(p.as_ref())?;
+        let len = f.metadata()?.len() as usize;
+        if len == 0 {
+            return Ok(Mmap { ptr: std::ptr::null(), len: 0 });
+        }
+        let ptr = unsafe {
+            sys::mmap(std::ptr::null_mut(), len, sys::PROT_READ, sys::MAP_PRIVATE, f.as_raw_fd(), 0)
+        };
+        if ptr == sys::MAP_FAILED {
+            return Err(Error::last_os_error());
+        }
+        Ok(Mmap { ptr: ptr as *const u8, len })
+    }
+
+    #[cfg(not(all(unix, target_pointer_width = "64")))]
+    pub fn open<P: AsRef<Path>>(_p: P) -> Result<Self, Error> {
+        Err(Error::new(ErrorKind::Other, "mmap is only supported on 64-bit unix"))
+    }
+
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    #[inline]
+    pub fn as_slice(&self) -> &[u8] {
+        if self.len == 0 {
+            &[]
+        } else {
+            unsafe { slice::from_raw_parts(self.ptr, self.len) }
+        }
+    }
+
+    /// view `count` values of T starting at byte `offset`, which must be aligned for T
+    pub fn slice_at<T: Copy>(&self, offset: usize, count: usize) -> Result<&[T], Error> {
+        let size = std::mem::size_of::<T>();
+        let end = count.checked_mul(size).and_then(|x| x.checked_add(offset));
+        match end {
+            Some(end) if end <= self.len => {}
+            _ => return Err(Error::new(ErrorKind::UnexpectedEof, "slice out of mapped range")),
+        }
+        if count == 0 {
+            return Ok(&[]);
+        }
+        let ptr = unsafe { self.ptr.add(offset) };
+        if !(ptr as usize).is_multiple_of(std::mem::align_of::<T>()) {
+            return Err(Error::new(ErrorKind::InvalidData, "unaligned slice in mapped file"));
+        }
+        Ok(unsafe { slice::from_raw_parts(ptr as *const T, count) })
+    }
+}
+
+impl Drop for Mmap {
+    fn drop(&mut self) {
+        #[cfg(all(unix, target_pointer_width = "64"))]
+        {
+            if self.len > 0 {
+                unsafe {
+                    sys::munmap(self.ptr as *mut _, self.len);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::fs::*;
+
+    static TEST_DIR: &str = "test_dir_mmap";
+
+    #[test]
+    fn test_mmap() {
+        mkdir(TEST_DIR).unwrap();
+        let test_file = create_path(&[TEST_DIR, "aaa"]);
+        let data: Vec<u8> = (0..64u64).flat_map(|x| x.to_ne_bytes().to_vec()).collect();
+        std::fs::write(test_file.as_str(), &data).unwrap();
+
+        let map = Mmap::open(test_file.as_str()).unwrap();
+        assert_eq!(map.len(), 512);
+        assert_eq!(map.as_slice(), data.as_slice());
+        let x: &[u64] = map.slice_at(8, 3).unwrap();
+        assert_eq!(x, &[1, 2, 3]);
+        assert!(map.slice_at::<u64>(8, 64).is_err());
+        assert!(map.slice_at::<u64>(4, 1).is_err());
+
+        let empty_file = create_path(&[TEST_DIR, "empty"]);
+        touch(empty_file.as_str()).unwrap();
+        let map = Mmap::open(empty_file.as_str()).unwrap();
+        assert!(map.is_empty());
+        assert!(map.as_slice().is_empty());
+
+        rmr(TEST_DIR).unwrap();
+    }
+}
@@ -1,6 +1,10 @@
 mod buffer;
 mod file_channel;
+mod mmap;
 
 pub mod fs;
-pub use self::buffer::ByteBuffer;
-pub use self::file_channel::FileChannel;
\ No newline at end of file
+pub mod checksum;
+pub use self::buffer::{ByteBuffer, ByteOrder, Number};
+pub use self::buffer::{zigzag_encode, zigzag_decode, varint_len, MAX_VARINT_LEN};
+pub use self::file_channel::{FileChannel, OpenMode, BufferedReader, BufferedWriter};
+pub use self::mmap::Mmap;
\ No newline at end of file
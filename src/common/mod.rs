@@ -1 +1,3 @@
 pub mod io;
+pub mod error;
+pub use self::error::{Error, Result};
@@ -0,0 +1,173 @@
+use crate::structure::{Graph, AdjacentList};
+use crate::structure::view::GraphView;
+
+/// 压缩稀疏行（CSR）格式的只读图
+/// ids升序排列，第i个点的出边是out_neighbors[out_offsets[i]..out_offsets[i + 1]]，入边同理
+#[derive(PartialEq, Debug)]
+pub struct CsrGraph {
+    ids: Vec<i64>,
+    out_offsets: Vec<usize>,
+    out_neighbors: Vec<i64>,
+    in_offsets: Vec<usize>,
+    in_neighbors: Vec<i64>,
+}
+
+impl CsrGraph {
+    pub fn from_graph(graph: &Graph) -> Self {
+        let mut ids: Vec<i64> = graph.adj.keys().chain(graph.rev_adj.keys()).cloned().collect();
+        ids.sort_unstable();
+        ids.dedup();
+        let (out_offsets, out_neighbors) = build_rows(&ids, &graph.adj);
+        let (in_offsets, in_neighbors) = build_rows(&ids, &graph.rev_adj);
+        CsrGraph {
+            ids,
+            out_offsets,
+            out_neighbors,
+            in_offsets,
+            in_neighbors,
+        }
+    }
+
+    /// 直接从有向边列表建图，每个点的邻居按边在列表里的顺序排列，
+    /// 所以和先逐条add_directed_edge再from_graph得到的图相同
+    pub fn from_edges(edges: &[(i64, i64)]) -> Self {
+        let mut ids: Vec<i64> = edges.iter().flat_map(|(u, v)| vec![*u, *v]).collect();
+        ids.sort_unstable();
+        ids.dedup();
+        let (out_offsets, out_neighbors) = build_rows_from_edges(&ids, edges.iter().cloned());
+        let (in_offsets, in_neighbors) = build_rows_from_edges(&ids, edges.iter().map(|(u, v)| (*v, *u)));
+        CsrGraph {
+            ids,
+            out_offsets,
+            out_neighbors,
+            in_offsets,
+            in_neighbors,
+        }
+    }
+
+    #[inline]
+    pub fn index(&self, id: i64) -> Option<usize> {
+        self.ids.binary_search(&id).ok()
+    }
+
+    #[inline]
+    pub fn ids(&self) -> &[i64] {
+        &self.ids
+    }
+
+    #[inline]
+    pub fn node_count(&self) -> usize {
+        self.ids.len()
+    }
+
+    #[inline]
+    pub fn edge_count(&self) -> usize {
+        self.out_neighbors.len()
+    }
+
+    /// 出边的(offsets, neighbors)
+    #[inline]
+    pub fn out_rows(&self) -> (&[usize], &[i64]) {
+        (&self.out_offsets, &self.out_neighbors)
+    }
+
+    /// 入边的(offsets, neighbors)
+    #[inline]
+    pub fn in_rows(&self) -> (&[usize], &[i64]) {
+        (&self.in_offsets, &self.in_neighbors)
+    }
+}
+
+fn build_rows(ids: &[i64], map: &AdjacentList) -> (Vec<usize>, Vec<i64>) {
+    let mut offsets = Vec::with_capacity(ids.len() + 1);
+    let mut neighbors = Vec::new();
+    offsets.push(0);
+    for id in ids.iter() {
+        if let Some(adj) = map.get(id) {
+            neighbors.extend_from_slice(adj);
+        }
+        offsets.push(neighbors.len());
+    }
+    (offsets, neighbors)
+}
+
+/// 计数排序，同一行里保持边的原有顺序
+fn build_rows_from_edges<I: Iterator<Item = (i64, i64)> + Clone>(ids: &[i64], edges: I) -> (Vec<usize>, Vec<i64>) {
+    let index = |id: i64| ids.binary_search(&id).unwrap();
+    let mut offsets = vec![0; ids.len() + 1];
+    for (u, _) in edges.clone() {
+        offsets[index(u) + 1] += 1;
+    }
+    for i in 0..ids.len() {
+        offsets[i + 1] += offsets[i];
+    }
+    let mut next = offsets.clone();
+    let mut neighbors = vec![0; offsets[ids.len()]];
+    for (u, v) in edges {
+        let i = index(u);
+        neighbors[next[i]] = v;
+        next[i] += 1;
+    }
+    (offsets, neighbors)
+}
+
+impl GraphView for CsrGraph {
+    #[inline]
+    fn out_neighbors(&self, id: i64) -> &[i64] {
+        match self.index(id) {
+            Some(i) => &self.out_neighbors[self.out_offsets[i]..self.out_offsets[i + 1]],
+            None => &[],
+        }
+    }
+
+    #[inline]
+    fn in_neighbors(&self, id: i64) -> &[i64] {
+        match self.index(id) {
+            Some(i) => &self.in_neighbors[self.in_offsets[i]..self.in_offsets[i + 1]],
+            None => &[],
+        }
+    }
+
+    fn vertices(&self) -> Vec<i64> {
+        self.ids.iter()
+            .enumerate()
+            .filter(|(i, _)| self.out_offsets[*i + 1] > self.out_offsets[*i])
+            .map(|(_, id)| *id)
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_graph() {
+        let graph = Graph::test();
+        let csr = CsrGraph::from_graph(&graph);
+        assert_eq!(csr.ids(), &[1, 2, 3]);
+        assert_eq!(csr.node_count(), 3);
+        assert_eq!(csr.edge_count(), 3);
+        assert_eq!(csr.out_neighbors(1), graph.out_neighbors(1));
+        assert_eq!(csr.in_neighbors(3), &[1]);
+        assert!(csr.out_neighbors(3).is_empty());
+        assert!(csr.out_neighbors(100).is_empty());
+        let mut v = csr.vertices();
+        v.sort();
+        assert_eq!(v, vec![1, 2]);
+    }
+
+    #[test]
+    fn test_from_edges() {
+        let edges = vec![(3, 1), (1, 2), (3, -5), (1, 3), (3, 1)];
+        let mut graph = Graph::empty();
+        for (u, v) in edges.iter() {
+            graph.add_directed_edge(*u, *v);
+        }
+        let csr = CsrGraph::from_edges(&edges);
+        assert_eq!(csr, CsrGraph::from_graph(&graph));
+        assert_eq!(csr.out_neighbors(3), &[1, -5, 1]);
+        assert_eq!(csr.in_neighbors(1), &[3, 3]);
+        assert_eq!(CsrGraph::from_edges(&[]), CsrGraph::from_graph(&Graph::empty()));
+    }
+}
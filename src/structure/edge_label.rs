@@ -0,0 +1,132 @@
+use std::collections::HashMap;
+
+/// 静态层（无向边）的标签名
+pub static STATIC_LABEL: &str = "static";
+/// 动态层（有向边）的标签名
+pub static DYNAMIC_LABEL: &str = "dynamic";
+
+/// 边标签表：给(src, dst)有向边挂层/类型标签
+/// 同一对点之间可以有平行边（比如静态边u-v和动态边u->v），每条边一个标签，按加边的顺序存，
+/// 和邻接表里(src, dst)出现的顺序一一对应
+/// 标签名会被intern成u32，边上只存id
+#[derive(PartialEq, Debug, Default)]
+pub struct EdgeLabels {
+    names: Vec<String>,
+    ids: HashMap<String, u32>,
+    edges: HashMap<(i64, i64), Vec<u32>>,
+}
+
+impl EdgeLabels {
+    pub fn new() -> Self {
+        EdgeLabels {
+            names: Vec::new(),
+            ids: HashMap::new(),
+            edges: HashMap::new(),
+        }
+    }
+
+    /// 返回标签名对应的id，不存在则新分配一个
+    pub fn intern(&mut self, name: &str) -> u32 {
+        if let Some(id) = self.ids.get(name) {
+            return *id;
+        }
+        let id = self.names.len() as u32;
+        self.names.push(name.to_owned());
+        self.ids.insert(name.to_owned(), id);
+        id
+    }
+
+    /// 给有向边(src, dst)打标签，已有标签（含平行边的）会被覆盖
+    pub fn set_label(&mut self, src: i64, dst: i64, name: &str) {
+        let id = self.intern(name);
+        self.edges.insert((src, dst), vec![id]);
+    }
+
+    /// 给无向边的两个方向都打上同一个标签
+    pub fn set_undirected_label(&mut self, id1: i64, id2: i64, name: &str) {
+        self.set_label(id1, id2, name);
+        self.set_label(id2, id1, name);
+    }
+
+    /// 图里又加了一条(src, dst)边，给这条平行边打标签，已有的标签不变
+    pub fn add_label(&mut self, src: i64, dst: i64, name: &str) {
+        let id = self.intern(name);
+        self.edges.entry((src, dst)).or_default().push(id);
+    }
+
+    /// 和add_undirected_edge对应，两个方向各加一条平行边的标签
+    pub fn add_undirected_label(&mut self, id1: i64, id2: i64, name: &str) {
+        self.add_label(id1, id2, name);
+        self.add_label(id2, id1, name);
+    }
+
+    /// 第一条(src, dst)边的标签
+    #[inline]
+    pub fn label_id(&self, src: i64, dst: i64) -> Option<u32> {
+        self.label_ids(src, dst).first().cloned()
+    }
+
+    /// 所有(src, dst)平行边的标签，按加边的顺序
+    #[inline]
+    pub fn label_ids(&self, src: i64, dst: i64) -> &[u32] {
+        self.edges.get(&(src, dst)).map(|x| x.as_slice()).unwrap_or(&[])
+    }
+
+    #[inline]
+    pub fn label(&self, src: i64, dst: i64) -> Option<&str> {
+        self.label_id(src, dst).map(|id| self.names[id as usize].as_str())
+    }
+
+    #[inline]
+    pub fn name(&self, id: u32) -> &str {
+        self.names[id as usize].as_str()
+    }
+
+    /// 所有已知标签名，下标即id
+    #[inline]
+    pub fn names(&self) -> &[String] {
+        &self.names
+    }
+
+    /// 有标签的边数，平行边分开算
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.edges.values().map(Vec::len).sum()
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.edges.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_edge_labels() {
+        let mut labels = EdgeLabels::new();
+        labels.set_undirected_label(1, 2, STATIC_LABEL);
+        labels.set_label(1, 3, DYNAMIC_LABEL);
+        assert_eq!(labels.len(), 3);
+        assert_eq!(labels.label(1, 2), Some(STATIC_LABEL));
+        assert_eq!(labels.label(2, 1), Some(STATIC_LABEL));
+        assert_eq!(labels.label(1, 3), Some(DYNAMIC_LABEL));
+        assert_eq!(labels.label(3, 1), None);
+        assert_eq!(labels.names().len(), 2);
+
+        labels.set_label(1, 3, STATIC_LABEL);
+        assert_eq!(labels.label(1, 3), Some(STATIC_LABEL));
+        assert_eq!(labels.names().len(), 2);
+
+        // 平行边各有各的标签
+        labels.add_label(2, 1, DYNAMIC_LABEL);
+        assert_eq!(labels.len(), 4);
+        assert_eq!(labels.label(2, 1), Some(STATIC_LABEL));
+        assert_eq!(labels.label_ids(2, 1), &[0, 1]);
+        assert!(labels.label_ids(3, 1).is_empty());
+        labels.set_label(2, 1, DYNAMIC_LABEL);
+        assert_eq!(labels.label_ids(2, 1), &[1]);
+    }
+}
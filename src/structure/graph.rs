@@ -1,6 +1,11 @@
 use std::collections::HashMap;
 use crate::structure::AdjacentList;
 
+/// 邻接表存的有向图，同一对点之间可以有平行边（比如静态边u-v和动态边u->v），邻接表里出现多次
+/// 平行边的约定：加边时总是加进邻接表；增量接口（StandingQueries、LivePaths、incremental_path）
+/// 在图里已经有同一条边时不报告新结果，因为经过它的点序列之前已经报告过了；
+/// cycle_dfs、short_cycles、pattern_dfs和增量接口按点序列去重，平行边不会让同一条路径或环出现多次；
+/// dfs、dfs_parallel直接按邻接表走，有平行边时同一个点序列会出现多次
 #[derive(PartialEq, Debug)]
 pub struct Graph {
     pub adj: AdjacentList,
@@ -66,8 +71,48 @@ impl Graph {
     }
 
     pub fn add_directed_edge(&mut self, id1: i64, id2: i64) {
-        self.adj.entry(id1).or_insert(Vec::new()).push(id2);
-        self.rev_adj.entry(id2).or_insert(Vec::new()).push(id1);
+        self.adj.entry(id1).or_default().push(id2);
+        self.rev_adj.entry(id2).or_default().push(id1);
+    }
+
+    /// 删掉一条有向边(id1, id2)，有重边时只删一条，返回是否删到了
+    pub fn remove_directed_edge(&mut self, id1: i64, id2: i64) -> bool {
+        let removed = match self.adj.get_mut(&id1) {
+            Some(x) => match x.iter().position(|v| *v == id2) {
+                Some(idx) => {
+                    x.remove(idx);
+                    true
+                }
+                None => false,
+            },
+            None => false,
+        };
+        if !removed {
+            return false;
+        }
+        if let Some(x) = self.rev_adj.get_mut(&id2) {
+            if let Some(idx) = x.iter().position(|v| *v == id1) {
+                x.remove(idx);
+            }
+        }
+        if self.adj.get(&id1).is_some_and(|x| x.is_empty()) {
+            self.adj.remove(&id1);
+        }
+        if self.rev_adj.get(&id2).is_some_and(|x| x.is_empty()) {
+            self.rev_adj.remove(&id2);
+        }
+        true
+    }
+
+    pub fn remove_undirected_edge(&mut self, id1: i64, id2: i64) -> bool {
+        let a = self.remove_directed_edge(id1, id2);
+        let b = self.remove_directed_edge(id2, id1);
+        a && b
+    }
+
+    #[inline]
+    pub fn has_edge(&self, id1: i64, id2: i64) -> bool {
+        self.out_v(id1).is_some_and(|x| x.contains(&id2))
     }
 
     pub fn test_large() -> Self {
@@ -124,4 +169,18 @@ mod tests {
         assert_eq!(graph.cal_degree(3), 1);
 
     }
+
+    #[test]
+    fn test_remove_edge() {
+        let mut graph = Graph::test();
+        graph.add_directed_edge(1, 3);
+        assert!(graph.remove_directed_edge(1, 3));
+        assert!(graph.has_edge(1, 3));
+        assert!(graph.remove_directed_edge(1, 3));
+        assert!(!graph.has_edge(1, 3));
+        assert!(!graph.remove_directed_edge(1, 3));
+        assert!(graph.in_v(3).is_none());
+        assert!(graph.remove_undirected_edge(2, 1));
+        assert_eq!(graph, Graph::empty());
+    }
 }
\ No newline at end of file
@@ -0,0 +1,210 @@
+//! 直接mmap到内存里的CSR图，打开时只检查文件头和长度，不解析内容
+//!
+//! 文件布局（每段都按8字节对齐）：
+//! magic(8B) | byte_order(u8，0小端1大端) | 保留(7B) | node_count(u64) | edge_count(u64) | rev_edge_count(u64)
+//! | ids[node_count](i64，升序) | out_offsets[node_count + 1](u64) | out_neighbors[edge_count](i64)
+//! | in_offsets[node_count + 1](u64) | in_neighbors[rev_edge_count](i64)
+//! 数组是直接映射出来用的，所以文件只能是写它的机器的字节序，换字节序的机器要重新build
+
+use std::path::Path;
+use crate::common::io::{Mmap, ByteOrder};
+use crate::common::error::{Error, Result};
+use crate::structure::view::GraphView;
+
+pub const CSR_MAGIC: [u8; 8] = *b"PDFSCSR\0";
+pub const CSR_FILE: &str = "csr";
+const HEADER_SIZE: usize = 40;
+
+pub struct MappedCsrGraph {
+    map: Mmap,
+    node_count: usize,
+    edge_count: usize,
+    rev_edge_count: usize,
+}
+
+impl MappedCsrGraph {
+    pub fn open<P: AsRef<Path>>(p: P) -> Result<Self> {
+        let path = p.as_ref().to_string_lossy().into_owned();
+        MappedCsrGraph::map(Mmap::open(p).map_err(|e| Error::io(&path, e))?).map_err(|e| e.with_path(&path))
+    }
+
+    fn map(map: Mmap) -> Result<Self> {
+        if map.len() < HEADER_SIZE || map.as_slice()[0..8] != CSR_MAGIC {
+            return Err(Error::format("not a csr graph file"));
+        }
+        match ByteOrder::from_tag(map.as_slice()[8]) {
+            Some(order) if order == ByteOrder::native() => {}
+            Some(order) => return Err(Error::format(format!(
+                "csr graph file is {:?} but this machine is {:?}", order, ByteOrder::native()))),
+            None => return Err(Error::format("unknown byte order in csr graph file")),
+        }
+        let header: &[u64] = map.slice_at(16, 3).map_err(|e| Error::format(e.to_string()))?;
+        let node_count = header[0] as usize;
+        let edge_count = header[1] as usize;
+        let rev_edge_count = header[2] as usize;
+        let ret = MappedCsrGraph {
+            map,
+            node_count,
+            edge_count,
+            rev_edge_count,
+        };
+        if [node_count, edge_count, rev_edge_count].iter().any(|x| *x > ret.map.len() / 8) {
+            return Err(Error::format("csr graph header is corrupted"));
+        }
+        let expected = HEADER_SIZE + 8 * (node_count + 2 * (node_count + 1) + edge_count + rev_edge_count);
+        if ret.map.len() != expected {
+            return Err(Error::format(format!("csr graph file has {} bytes, expected {}", ret.map.len(), expected)));
+        }
+        let out_offsets = ret.out_offsets();
+        let in_offsets = ret.in_offsets();
+        if out_offsets[node_count] as usize != edge_count || in_offsets[node_count] as usize != rev_edge_count {
+            return Err(Error::format("csr graph offsets do not match edge counts"));
+        }
+        if out_offsets.windows(2).any(|w| w[0] > w[1]) || in_offsets.windows(2).any(|w| w[0] > w[1]) {
+            return Err(Error::format("csr graph offsets are not sorted"));
+        }
+        // index用二分查找，id必须严格升序
+        if ret.ids().windows(2).any(|w| w[0] >= w[1]) {
+            return Err(Error::format("csr graph ids are not sorted"));
+        }
+        Ok(ret)
+    }
+
+    #[inline]
+    fn section<T: Copy>(&self, offset: usize, count: usize) -> &[T] {
+        // 长度和对齐在open里已经检查过
+        self.map.slice_at(offset, count).unwrap()
+    }
+
+    #[inline]
+    pub fn ids(&self) -> &[i64] {
+        self.section(HEADER_SIZE, self.node_count)
+    }
+
+    #[inline]
+    fn out_offsets(&self) -> &[u64] {
+        self.section(HEADER_SIZE + 8 * self.node_count, self.node_count + 1)
+    }
+
+    #[inline]
+    fn out_edges(&self) -> &[i64] {
+        self.section(HEADER_SIZE + 8 * (2 * self.node_count + 1), self.edge_count)
+    }
+
+    #[inline]
+    fn in_offsets(&self) -> &[u64] {
+        self.section(HEADER_SIZE + 8 * (2 * self.node_count + 1 + self.edge_count), self.node_count + 1)
+    }
+
+    #[inline]
+    fn in_edges(&self) -> &[i64] {
+        self.section(HEADER_SIZE + 8 * (3 * self.node_count + 2 + self.edge_count), self.rev_edge_count)
+    }
+
+    #[inline]
+    pub fn index(&self, id: i64) -> Option<usize> {
+        self.ids().binary_search(&id).ok()
+    }
+
+    #[inline]
+    pub fn node_count(&self) -> usize {
+        self.node_count
+    }
+
+    #[inline]
+    pub fn edge_count(&self) -> usize {
+        self.edge_count
+    }
+}
+
+impl GraphView for MappedCsrGraph {
+    #[inline]
+    fn out_neighbors(&self, id: i64) -> &[i64] {
+        match self.index(id) {
+            Some(i) => {
+                let offsets = self.out_offsets();
+                &self.out_edges()[offsets[i] as usize..offsets[i + 1] as usize]
+            }
+            None => &[],
+        }
+    }
+
+    #[inline]
+    fn in_neighbors(&self, id: i64) -> &[i64] {
+        match self.index(id) {
+            Some(i) => {
+                let offsets = self.in_offsets();
+                &self.in_edges()[offsets[i] as usize..offsets[i + 1] as usize]
+            }
+            None => &[],
+        }
+    }
+
+    fn vertices(&self) -> Vec<i64> {
+        let offsets = self.out_offsets();
+        self.ids().iter()
+            .enumerate()
+            .filter(|(i, _)| offsets[*i + 1] > offsets[*i])
+            .map(|(_, id)| *id)
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+    use crate::common::io::fs;
+    use crate::structure::{Graph, CsrGraph};
+    use crate::utils::build_binary::build_binary_graph;
+    use crate::utils::load_binary::load_mapped_graph;
+    use crate::algorithm::dfs::dfs;
+
+    static TEST_DIR: &str = "test_dir_mapped_csr";
+
+    fn paths<G: GraphView>(s: i64, t: i64, k: u32, graph: &G) -> Vec<Vec<i64>> {
+        let mut result = Vec::new();
+        dfs(s, t, k, graph, &mut result, &mut None, &mut Vec::new(), false, &mut HashSet::new());
+        result.sort();
+        result
+    }
+
+    #[test]
+    fn test_mapped_graph() {
+        let graph = Graph::test_large();
+        build_binary_graph(&graph, TEST_DIR.to_owned()).unwrap();
+        let mapped = load_mapped_graph(TEST_DIR.to_owned()).unwrap();
+        let csr = CsrGraph::from_graph(&graph);
+        assert_eq!(mapped.ids(), csr.ids());
+        assert_eq!(mapped.node_count(), csr.node_count());
+        assert_eq!(mapped.edge_count(), csr.edge_count());
+        for id in csr.ids().iter() {
+            assert_eq!(mapped.out_neighbors(*id), csr.out_neighbors(*id));
+            assert_eq!(mapped.in_neighbors(*id), csr.in_neighbors(*id));
+        }
+        assert!(mapped.out_neighbors(-1).is_empty());
+
+        assert_eq!(paths(1, 3, 5, &mapped), paths(1, 3, 5, &graph));
+
+        // 截断的文件打开时报错
+        let path = fs::create_path(&[TEST_DIR, CSR_FILE]);
+        let data = std::fs::read(path.as_str()).unwrap();
+        std::fs::write(path.as_str(), &data[..data.len() - 8]).unwrap();
+        assert!(load_mapped_graph(TEST_DIR.to_owned()).is_err());
+        std::fs::write(path.as_str(), b"not a graph").unwrap();
+        assert!(MappedCsrGraph::open(path.as_str()).is_err());
+        let mut foreign = data.clone();
+        foreign[8] = if ByteOrder::native() == ByteOrder::LittleEndian { 1 } else { 0 };
+        std::fs::write(path.as_str(), &foreign).unwrap();
+        assert!(MappedCsrGraph::open(path.as_str()).is_err());
+        // 交换前两个id
+        let mut unsorted = data.clone();
+        let (a, b) = unsorted[HEADER_SIZE..HEADER_SIZE + 16].split_at_mut(8);
+        a.swap_with_slice(b);
+        std::fs::write(path.as_str(), &unsorted).unwrap();
+        let err = MappedCsrGraph::open(path.as_str()).err().unwrap();
+        assert!(err.to_string().contains("not sorted"), "{}", err);
+
+        fs::rmr(TEST_DIR).unwrap();
+    }
+}
@@ -1,5 +1,23 @@
 pub mod graph;
+pub mod edge_label;
+pub mod view;
+pub mod csr;
+pub mod mapped_csr;
+pub mod sink;
+pub mod snapshot;
+pub mod window;
+pub mod path_result;
+pub mod path_trie;
 pub use graph::Graph;
+pub use edge_label::EdgeLabels;
+pub use view::GraphView;
+pub use csr::CsrGraph;
+pub use mapped_csr::MappedCsrGraph;
+pub use sink::{PathSink, PathCollector};
+pub use snapshot::{EdgeUpdate, Snapshot, VersionedGraph};
+pub use window::{Window, WindowedGraph};
+pub use path_result::{Direction, PathEdge, PathResult};
+pub use path_trie::PathTrie;
 
 use std::collections::HashMap;
 pub type AdjacentList=HashMap<i64, Vec<i64>>;
@@ -0,0 +1,144 @@
+use crate::structure::{EdgeLabels, GraphPath};
+
+/// 路径上的一步是顺着出边走的还是逆着入边走的（rev为true的反向搜索）
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Direction {
+    Forward,
+    Backward,
+}
+
+/// 路径上的一条边，from/to是走的顺序，原图里的边见graph_edge
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct PathEdge {
+    pub from: i64,
+    pub to: i64,
+    pub direction: Direction,
+    /// 边所在的层，即EdgeLabels里的标签id，没打标签时为None
+    pub layer: Option<u32>,
+}
+
+impl PathEdge {
+    /// 原图里的有向边(src, dst)
+    #[inline]
+    pub fn graph_edge(&self) -> (i64, i64) {
+        match self.direction {
+            Direction::Forward => (self.from, self.to),
+            Direction::Backward => (self.to, self.from),
+        }
+    }
+}
+
+/// 一条完整的结果路径：含起点和终点的点序列，以及每一步的边
+/// dfs等函数返回的只有中间点（不含起点和终点），是旧的表示，用from_trimmed转换
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct PathResult {
+    pub vertices: GraphPath,
+    pub edges: Vec<PathEdge>,
+}
+
+impl PathResult {
+    /// vertices是按走的顺序排的完整路径，rev为true时每一步都是逆着边走的
+    pub fn from_vertices(vertices: GraphPath, rev: bool) -> Self {
+        let direction = if rev { Direction::Backward } else { Direction::Forward };
+        let edges = vertices.windows(2)
+            .map(|w| PathEdge { from: w[0], to: w[1], direction, layer: None })
+            .collect();
+        PathResult { vertices, edges }
+    }
+
+    /// 从dfs的结果（不含起点和终点）补全
+    pub fn from_trimmed(source: i64, target: i64, trimmed: &[i64], rev: bool) -> Self {
+        let mut vertices = Vec::with_capacity(trimmed.len() + 2);
+        vertices.push(source);
+        vertices.extend_from_slice(trimmed);
+        vertices.push(target);
+        PathResult::from_vertices(vertices, rev)
+    }
+
+    /// 从cycle_dfs、short_cycles的环（不重复首尾）补全，vertices首尾都是环的第一个点
+    pub fn from_cycle(cycle: &[i64]) -> Self {
+        let mut vertices = Vec::with_capacity(cycle.len() + 1);
+        vertices.extend_from_slice(cycle);
+        if let Some(first) = cycle.first() {
+            vertices.push(*first);
+        }
+        PathResult::from_vertices(vertices, false)
+    }
+
+    /// 边数
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.edges.len()
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.edges.is_empty()
+    }
+
+    #[inline]
+    pub fn source(&self) -> Option<i64> {
+        self.vertices.first().cloned()
+    }
+
+    #[inline]
+    pub fn target(&self) -> Option<i64> {
+        self.vertices.last().cloned()
+    }
+
+    /// 旧的表示：去掉起点和终点
+    pub fn trimmed(&self) -> GraphPath {
+        if self.vertices.len() < 2 {
+            return GraphPath::new();
+        }
+        self.vertices[1..self.vertices.len() - 1].to_vec()
+    }
+
+    /// 按原图里的边查标签，填上每条边的layer，平行边取第一条的标签
+    pub fn set_layers(&mut self, labels: &EdgeLabels) {
+        for e in self.edges.iter_mut() {
+            let (src, dst) = e.graph_edge();
+            e.layer = labels.label_id(src, dst);
+        }
+    }
+
+    /// 每条边的层名
+    pub fn layer_names<'a>(&self, labels: &'a EdgeLabels) -> Vec<Option<&'a str>> {
+        self.edges.iter().map(|e| e.layer.map(|id| labels.name(id))).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::structure::edge_label::{STATIC_LABEL, DYNAMIC_LABEL};
+
+    #[test]
+    fn test_path_result() {
+        let mut labels = EdgeLabels::new();
+        labels.set_undirected_label(1, 2, STATIC_LABEL);
+        labels.set_label(2, 3, DYNAMIC_LABEL);
+
+        let mut p = PathResult::from_trimmed(1, 3, &[2], false);
+        assert_eq!(p.vertices, vec![1, 2, 3]);
+        assert_eq!((p.len(), p.source(), p.target()), (2, Some(1), Some(3)));
+        assert_eq!(p.trimmed(), vec![2]);
+        p.set_layers(&labels);
+        assert_eq!(p.layer_names(&labels), vec![Some(STATIC_LABEL), Some(DYNAMIC_LABEL)]);
+
+        // 反向搜索从3走到1，边还是原图里的方向
+        let mut p = PathResult::from_trimmed(3, 1, &[2], true);
+        assert_eq!(p.edges[0].graph_edge(), (2, 3));
+        assert_eq!(p.edges[0].direction, Direction::Backward);
+        p.set_layers(&labels);
+        assert_eq!(p.layer_names(&labels), vec![Some(DYNAMIC_LABEL), Some(STATIC_LABEL)]);
+
+        let p = PathResult::from_trimmed(1, 3, &[], false);
+        assert_eq!((p.len(), p.trimmed()), (1, vec![]));
+        assert!(PathResult::from_vertices(vec![5], false).is_empty());
+
+        let p = PathResult::from_cycle(&[1, 2, 3]);
+        assert_eq!(p.vertices, vec![1, 2, 3, 1]);
+        assert_eq!((p.len(), p.edges[2].graph_edge()), (3, (3, 1)));
+    }
+}
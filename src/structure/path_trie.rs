@@ -0,0 +1,337 @@
+use std::io;
+use std::mem::size_of;
+use crate::structure::{GraphPath, PathCollector, PathSink};
+
+const NONE: u32 = u32::MAX;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Node {
+    vertex: i64,
+    first_child: u32,
+    last_child: u32,
+    next_sibling: u32,
+    /// 从根到这个点是不是一条存进来的路径
+    end: bool,
+}
+
+impl Node {
+    fn new(vertex: i64) -> Self {
+        Node {
+            vertex,
+            first_child: NONE,
+            last_child: NONE,
+            next_sibling: NONE,
+            end: false,
+        }
+    }
+}
+
+/// 前缀树存路径集合，公共前缀只存一份
+/// 同一次查询的结果、dfs_for_temp切出的前缀都有很长的公共前缀，比一条路径一个Vec省内存
+/// 孩子用兄弟链表串起来，按插入顺序遍历；插入时先看最后一个孩子，dfs顺序插入时基本不用扫链表
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PathTrie {
+    nodes: Vec<Node>,
+    len: usize,
+}
+
+impl Default for PathTrie {
+    fn default() -> Self {
+        PathTrie::new()
+    }
+}
+
+impl PathTrie {
+    pub fn new() -> Self {
+        PathTrie {
+            nodes: vec![Node::new(0)],
+            len: 0,
+        }
+    }
+
+    pub fn from_paths<'a, I: IntoIterator<Item = &'a GraphPath>>(paths: I) -> Self {
+        let mut trie = PathTrie::new();
+        for p in paths {
+            trie.insert(p);
+        }
+        trie
+    }
+
+    /// 路径条数
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// 不含根的结点数，即存下来的点数
+    #[inline]
+    pub fn node_count(&self) -> usize {
+        self.nodes.len() - 1
+    }
+
+    /// 结点数组占的堆内存
+    #[inline]
+    pub fn heap_size(&self) -> usize {
+        self.nodes.capacity() * size_of::<Node>()
+    }
+
+    fn child(&self, node: u32, vertex: i64) -> Option<u32> {
+        let n = &self.nodes[node as usize];
+        if n.last_child != NONE && self.nodes[n.last_child as usize].vertex == vertex {
+            return Some(n.last_child);
+        }
+        let mut c = n.first_child;
+        while c != NONE {
+            if self.nodes[c as usize].vertex == vertex {
+                return Some(c);
+            }
+            c = self.nodes[c as usize].next_sibling;
+        }
+        None
+    }
+
+    /// 插入一条路径，已经存在时返回false
+    pub fn insert(&mut self, path: &[i64]) -> bool {
+        let mut node = 0;
+        for v in path.iter() {
+            node = match self.child(node, *v) {
+                Some(c) => c,
+                None => {
+                    let c = self.nodes.len() as u32;
+                    self.nodes.push(Node::new(*v));
+                    let parent = &mut self.nodes[node as usize];
+                    let last = parent.last_child;
+                    parent.last_child = c;
+                    if last == NONE {
+                        parent.first_child = c;
+                    } else {
+                        self.nodes[last as usize].next_sibling = c;
+                    }
+                    c
+                }
+            };
+        }
+        let n = &mut self.nodes[node as usize];
+        if n.end {
+            return false;
+        }
+        n.end = true;
+        self.len += 1;
+        true
+    }
+
+    pub fn contains(&self, path: &[i64]) -> bool {
+        let mut node = 0;
+        for v in path.iter() {
+            node = match self.child(node, *v) {
+                Some(c) => c,
+                None => return false,
+            };
+        }
+        self.nodes[node as usize].end
+    }
+
+    /// 把other里的路径都插进来
+    pub fn merge(&mut self, other: &PathTrie) {
+        other.for_each(|p| {
+            self.insert(p);
+        });
+    }
+
+    /// 先序遍历，对每条路径调用f，父路径在子路径前面，兄弟按插入顺序
+    pub fn for_each<F: FnMut(&[i64])>(&self, mut f: F) {
+        let mut path = GraphPath::new();
+        if self.nodes[0].end {
+            f(&path);
+        }
+        // 栈里存下一个要访问的结点，回溯时把path截到它父结点的深度
+        let mut stack = vec![(self.nodes[0].first_child, 0)];
+        while let Some((node, depth)) = stack.pop() {
+            if node == NONE {
+                continue;
+            }
+            let n = &self.nodes[node as usize];
+            stack.push((n.next_sibling, depth));
+            path.truncate(depth);
+            path.push(n.vertex);
+            if n.end {
+                f(&path);
+            }
+            stack.push((n.first_child, depth + 1));
+        }
+    }
+
+    /// 和for_each一样，但只对先序下标模parts等于part的路径调用f
+    /// 几个线程共享一棵树各取一份，路径不用复制出来
+    pub fn for_each_part<F: FnMut(&[i64])>(&self, part: usize, parts: usize, mut f: F) {
+        let mut idx = 0;
+        self.for_each(|p| {
+            if idx % parts == part {
+                f(p);
+            }
+            idx += 1;
+        });
+    }
+
+    /// 结点数组收缩到实际大小，建好以后只读的树用
+    pub fn shrink_to_fit(&mut self) {
+        self.nodes.shrink_to_fit();
+    }
+
+    /// 和for_each顺序相同，每条路径复制一份
+    pub fn iter(&self) -> Iter<'_> {
+        Iter {
+            trie: self,
+            root: self.nodes[0].end,
+            stack: vec![(self.nodes[0].first_child, 0)],
+            path: GraphPath::new(),
+        }
+    }
+
+    /// 按for_each的顺序写给sink，比如用QuerySink包一层BinaryPathSink写成二进制结果文件
+    pub fn write_to<S: PathSink + ?Sized>(&self, sink: &mut S) -> io::Result<()> {
+        let mut ret = Ok(());
+        self.for_each(|p| {
+            if ret.is_ok() {
+                ret = sink.push(p);
+            }
+        });
+        ret
+    }
+
+    pub fn to_paths(&self) -> Vec<GraphPath> {
+        let mut ret = Vec::with_capacity(self.len);
+        self.for_each(|p| ret.push(p.to_vec()));
+        ret
+    }
+}
+
+pub struct Iter<'a> {
+    trie: &'a PathTrie,
+    /// 空路径还没返回
+    root: bool,
+    stack: Vec<(u32, usize)>,
+    path: GraphPath,
+}
+
+impl<'a> Iterator for Iter<'a> {
+    type Item = GraphPath;
+
+    fn next(&mut self) -> Option<GraphPath> {
+        if self.root {
+            self.root = false;
+            return Some(GraphPath::new());
+        }
+        while let Some((node, depth)) = self.stack.pop() {
+            if node == NONE {
+                continue;
+            }
+            let n = &self.trie.nodes[node as usize];
+            self.stack.push((n.next_sibling, depth));
+            self.path.truncate(depth);
+            self.path.push(n.vertex);
+            self.stack.push((n.first_child, depth + 1));
+            if n.end {
+                return Some(self.path.clone());
+            }
+        }
+        None
+    }
+}
+
+impl PathCollector for PathTrie {
+    #[inline]
+    fn collect(&mut self, path: &[i64]) {
+        self.insert(path);
+    }
+}
+
+impl PathSink for PathTrie {
+    #[inline]
+    fn push(&mut self, path: &[i64]) -> io::Result<()> {
+        self.insert(path);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use crate::utils::path_writer::{BinaryPathReader, BinaryPathSink, QuerySink};
+
+    #[test]
+    fn test_path_trie() {
+        let paths = [vec![1, 2, 3], vec![1, 2, 4], vec![1, 5], vec![1, 2], vec![6]];
+        let mut trie = PathTrie::from_paths(paths.iter());
+        assert_eq!(trie.len(), 5);
+        assert_eq!(trie.node_count(), 6);
+        assert!(!trie.insert(&[1, 2, 4]));
+        assert!(trie.contains(&[1, 2]) && trie.contains(&[6]));
+        assert!(!trie.contains(&[1]) && !trie.contains(&[]) && !trie.contains(&[1, 2, 3, 4]));
+
+        // 先序：父路径在前，兄弟按插入顺序
+        let expected = vec![vec![1, 2], vec![1, 2, 3], vec![1, 2, 4], vec![1, 5], vec![6]];
+        assert_eq!(trie.to_paths(), expected);
+        assert_eq!(trie.iter().collect::<Vec<_>>(), expected);
+        let mut part = Vec::new();
+        trie.for_each_part(1, 2, |p| part.push(p.to_vec()));
+        assert_eq!(part, vec![vec![1, 2, 3], vec![1, 5]]);
+
+        assert!(trie.insert(&[]));
+        assert_eq!(trie.iter().next(), Some(vec![]));
+        assert_eq!((trie.len(), trie.node_count()), (6, 6));
+
+        let mut other = PathTrie::new();
+        other.collect(&[1, 5]);
+        other.collect(&[7, 8]);
+        trie.merge(&other);
+        assert_eq!(trie.len(), 7);
+        assert!(trie.contains(&[7, 8]));
+        assert!(PathTrie::default().is_empty());
+    }
+
+    #[test]
+    fn test_path_trie_shared_prefix() {
+        let prefix: GraphPath = (0..50).collect();
+        let paths: Vec<GraphPath> = (100..200).map(|v| {
+            let mut p = prefix.clone();
+            p.push(v);
+            p
+        }).collect();
+        let trie = PathTrie::from_paths(paths.iter());
+        assert_eq!(trie.node_count(), 150);
+        let vec_size: usize = paths.iter().map(|p| p.capacity() * size_of::<i64>()).sum();
+        assert!(trie.heap_size() * 4 < vec_size);
+        let mut sorted = trie.to_paths();
+        sorted.sort_unstable();
+        assert_eq!(sorted, paths);
+    }
+
+    #[test]
+    fn test_path_trie_binary() {
+        let dir = "test_path_trie_binary";
+        fs::create_dir_all(dir).unwrap();
+        let file = format!("{}/paths.bin", dir);
+        let trie = PathTrie::from_paths([vec![2, 3], vec![2, 4, 5], vec![6]].iter());
+        {
+            let mut writer = BinaryPathSink::create(&file).unwrap();
+            let mut sink = QuerySink::new(&mut writer, 7, 1, 0);
+            trie.write_to(&mut sink).unwrap();
+        }
+        let read: Vec<(u64, GraphPath)> = BinaryPathReader::open(&file).unwrap()
+            .map(|r| r.unwrap())
+            .collect();
+        fs::remove_dir_all(dir).unwrap();
+        assert_eq!(read, vec![
+            (7, vec![1, 2, 3, 0]),
+            (7, vec![1, 2, 4, 5, 0]),
+            (7, vec![1, 6, 0]),
+        ]);
+    }
+}
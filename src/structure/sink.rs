@@ -0,0 +1,31 @@
+use std::io;
+use crate::structure::GraphPath;
+
+/// 结果路径的去处：内存里的Vec，或者边算边写的文件
+pub trait PathSink {
+    fn push(&mut self, path: &[i64]) -> io::Result<()>;
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// 内存里的结果集合，dfs找到的路径交给它，不会失败
+pub trait PathCollector {
+    fn collect(&mut self, path: &[i64]);
+}
+
+impl PathCollector for Vec<GraphPath> {
+    #[inline]
+    fn collect(&mut self, path: &[i64]) {
+        Vec::push(self, path.to_vec());
+    }
+}
+
+impl PathSink for Vec<GraphPath> {
+    #[inline]
+    fn push(&mut self, path: &[i64]) -> io::Result<()> {
+        Vec::push(self, path.to_vec());
+        Ok(())
+    }
+}
@@ -0,0 +1,315 @@
+//! 带版本号的图快照
+//!
+//! 不可变的base图加上一层增量覆盖（delta）：delta里存的是被改过的点的完整邻接表，
+//! 查邻居时先查delta再查base。每次写都基于当前快照拷一份delta（只拷Arc指针和被改的那几个点），
+//! 然后发布成新版本；读的人pin住某个版本的Arc<Snapshot>，之后的写不会影响它。
+//! 每次写要拷的delta随被改过的点数增长，VersionedGraph在delta超过compact_threshold个点时自动compact，
+//! 把它合并进新的base；合并要物化整张图，所以阈值不宜太小。
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, RwLock};
+use crate::structure::{Graph, AdjacentList};
+use crate::structure::view::GraphView;
+
+/// 一次边更新
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum EdgeUpdate {
+    Insert(i64, i64),
+    Delete(i64, i64),
+}
+
+type Overlay = HashMap<i64, Arc<Vec<i64>>>;
+
+/// VersionedGraph默认在delta超过这么多个点时自动compact
+pub const DEFAULT_COMPACT_THRESHOLD: usize = 1 << 16;
+
+/// 某个版本的只读图
+#[derive(Debug, Clone)]
+pub struct Snapshot {
+    version: u64,
+    base: Arc<Graph>,
+    adj: Arc<Overlay>,
+    rev_adj: Arc<Overlay>,
+}
+
+impl Snapshot {
+    pub fn new(graph: Graph) -> Self {
+        Snapshot {
+            version: 0,
+            base: Arc::new(graph),
+            adj: Arc::new(HashMap::new()),
+            rev_adj: Arc::new(HashMap::new()),
+        }
+    }
+
+    #[inline]
+    pub fn version(&self) -> u64 {
+        self.version
+    }
+
+    /// delta里被改过的点数
+    #[inline]
+    pub fn delta_len(&self) -> usize {
+        self.adj.len() + self.rev_adj.len()
+    }
+
+    /// 在这个快照上应用一批更新，得到下一个版本，自己不变
+    /// 代价：拷一份delta的HashMap（delta_len个Arc指针），再加上这批更新碰到的点的邻接表，
+    /// 所以delta越大每次写越慢，需要compact
+    pub fn apply(&self, updates: &[EdgeUpdate]) -> Snapshot {
+        let mut adj = (*self.adj).clone();
+        let mut rev_adj = (*self.rev_adj).clone();
+        for update in updates.iter() {
+            match *update {
+                EdgeUpdate::Insert(u, v) => {
+                    modify(&mut adj, &self.base.adj, u, |x| x.push(v));
+                    modify(&mut rev_adj, &self.base.rev_adj, v, |x| x.push(u));
+                }
+                EdgeUpdate::Delete(u, v) => {
+                    let mut removed = false;
+                    modify(&mut adj, &self.base.adj, u, |x| {
+                        if let Some(idx) = x.iter().position(|y| *y == v) {
+                            x.remove(idx);
+                            removed = true;
+                        }
+                    });
+                    if removed {
+                        modify(&mut rev_adj, &self.base.rev_adj, v, |x| {
+                            if let Some(idx) = x.iter().position(|y| *y == u) {
+                                x.remove(idx);
+                            }
+                        });
+                    }
+                }
+            }
+        }
+        Snapshot {
+            version: self.version + 1,
+            base: self.base.clone(),
+            adj: Arc::new(adj),
+            rev_adj: Arc::new(rev_adj),
+        }
+    }
+
+    /// 把delta合并进base，内容和版本号都不变
+    pub fn compact(&self) -> Snapshot {
+        let mut snapshot = Snapshot::new(self.to_graph());
+        snapshot.version = self.version;
+        snapshot
+    }
+
+    /// 物化成一个普通的Graph
+    pub fn to_graph(&self) -> Graph {
+        Graph::new(merge(&self.base.adj, &self.adj), merge(&self.base.rev_adj, &self.rev_adj))
+    }
+}
+
+/// 从delta（没有就从base拷一份）里取出id的邻接表来改
+fn modify<F: FnOnce(&mut Vec<i64>)>(overlay: &mut Overlay, base: &AdjacentList, id: i64, f: F) {
+    let entry = overlay.entry(id).or_insert_with(|| {
+        Arc::new(base.get(&id).cloned().unwrap_or_default())
+    });
+    f(Arc::make_mut(entry));
+}
+
+fn merge(base: &AdjacentList, overlay: &Overlay) -> AdjacentList {
+    let mut ret = AdjacentList::new();
+    for (id, x) in base.iter() {
+        if !overlay.contains_key(id) {
+            ret.insert(*id, x.clone());
+        }
+    }
+    for (id, x) in overlay.iter() {
+        if !x.is_empty() {
+            ret.insert(*id, x.as_ref().clone());
+        }
+    }
+    ret
+}
+
+impl GraphView for Snapshot {
+    #[inline]
+    fn out_neighbors(&self, id: i64) -> &[i64] {
+        match self.adj.get(&id) {
+            Some(x) => x.as_slice(),
+            None => self.base.out_neighbors(id),
+        }
+    }
+
+    #[inline]
+    fn in_neighbors(&self, id: i64) -> &[i64] {
+        match self.rev_adj.get(&id) {
+            Some(x) => x.as_slice(),
+            None => self.base.in_neighbors(id),
+        }
+    }
+
+    fn vertices(&self) -> Vec<i64> {
+        let mut ret: Vec<i64> = self.base.adj.keys().filter(|id| !self.adj.contains_key(id)).cloned().collect();
+        ret.extend(self.adj.iter().filter(|(_, x)| !x.is_empty()).map(|(id, _)| *id));
+        ret
+    }
+}
+
+/// 可以边读边写的图：读的人pin一个版本，写的人发布新版本
+pub struct VersionedGraph {
+    current: RwLock<Arc<Snapshot>>,
+    writer: Mutex<()>,
+    compact_threshold: usize,
+}
+
+impl VersionedGraph {
+    pub fn new(graph: Graph) -> Self {
+        VersionedGraph::with_compact_threshold(graph, DEFAULT_COMPACT_THRESHOLD)
+    }
+
+    /// apply之后delta超过threshold个点就自动compact
+    pub fn with_compact_threshold(graph: Graph, threshold: usize) -> Self {
+        VersionedGraph {
+            current: RwLock::new(Arc::new(Snapshot::new(graph))),
+            writer: Mutex::new(()),
+            compact_threshold: threshold,
+        }
+    }
+
+    /// 拿到当前版本，之后的写对它不可见
+    #[inline]
+    pub fn pin(&self) -> Arc<Snapshot> {
+        self.current.read().unwrap().clone()
+    }
+
+    #[inline]
+    pub fn version(&self) -> u64 {
+        self.pin().version()
+    }
+
+    /// 应用一批更新并发布，返回新版本号；delta超过阈值时发布的是compact之后的快照
+    pub fn apply(&self, updates: &[EdgeUpdate]) -> u64 {
+        let _guard = self.writer.lock().unwrap();
+        let mut next = self.pin().apply(updates);
+        if next.delta_len() > self.compact_threshold {
+            next = next.compact();
+        }
+        let version = next.version();
+        *self.current.write().unwrap() = Arc::new(next);
+        version
+    }
+
+    pub fn add_directed_edge(&self, id1: i64, id2: i64) -> u64 {
+        self.apply(&[EdgeUpdate::Insert(id1, id2)])
+    }
+
+    pub fn remove_directed_edge(&self, id1: i64, id2: i64) -> u64 {
+        self.apply(&[EdgeUpdate::Delete(id1, id2)])
+    }
+
+    /// 把当前版本的delta合并进base，已经pin住的旧版本不受影响
+    pub fn compact(&self) {
+        let _guard = self.writer.lock().unwrap();
+        let next = self.pin().compact();
+        *self.current.write().unwrap() = Arc::new(next);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+    use std::thread;
+    use crate::algorithm::dfs::dfs;
+    use crate::algorithm::dfs_parallel::dfs_parallel;
+
+    fn paths<G: GraphView>(s: i64, t: i64, k: u32, graph: &G) -> Vec<Vec<i64>> {
+        let mut result = Vec::new();
+        let mut path = Vec::new();
+        let mut visit = HashSet::new();
+        dfs(s, t, k, graph, &mut result, &mut None, &mut path, false, &mut visit);
+        result.sort();
+        result
+    }
+
+    #[test]
+    fn test_snapshot_isolation() {
+        let vg = VersionedGraph::new(Graph::test());
+        let v0 = vg.pin();
+        assert_eq!(vg.add_directed_edge(3, 4), 1);
+        assert_eq!(vg.apply(&[EdgeUpdate::Insert(2, 4), EdgeUpdate::Delete(1, 2)]), 2);
+        let v2 = vg.pin();
+
+        assert_eq!(v0.version(), 0);
+        assert!(v0.out_neighbors(3).is_empty());
+        assert_eq!(v0.out_neighbors(1), &[2, 3]);
+        assert_eq!(v2.out_neighbors(1), &[3]);
+        assert_eq!(v2.out_neighbors(3), &[4]);
+        assert_eq!(v2.in_neighbors(4), &[3, 2]);
+        assert_eq!(v2.in_neighbors(2), &[] as &[i64]);
+
+        assert_eq!(paths(1, 4, 3, v0.as_ref()), Vec::<Vec<i64>>::new());
+        assert_eq!(paths(1, 4, 3, v2.as_ref()), vec![vec![3]]);
+
+        let mut expected = Graph::test();
+        expected.add_directed_edge(3, 4);
+        expected.add_directed_edge(2, 4);
+        expected.remove_directed_edge(1, 2);
+        assert_eq!(v2.to_graph(), expected);
+        let mut vertices = v2.vertices();
+        vertices.sort();
+        assert_eq!(vertices, vec![1, 2, 3]);
+
+        vg.compact();
+        let v3 = vg.pin();
+        assert_eq!(v3.version(), 2);
+        assert_eq!(v3.delta_len(), 0);
+        assert_eq!(v3.to_graph(), expected);
+        assert_eq!(v2.out_neighbors(1), &[3]);
+    }
+
+    #[test]
+    fn test_auto_compact() {
+        let vg = VersionedGraph::with_compact_threshold(Graph::empty(), 4);
+        vg.add_directed_edge(1, 2);
+        assert_eq!(vg.pin().delta_len(), 2);
+        vg.add_directed_edge(3, 4);
+        assert_eq!(vg.pin().delta_len(), 4);
+        let before = vg.pin();
+        // 第三条边让delta超过4个点，发布的版本已经合并进base
+        assert_eq!(vg.add_directed_edge(5, 6), 3);
+        let after = vg.pin();
+        assert_eq!((after.version(), after.delta_len()), (3, 0));
+        let mut expected = Graph::empty();
+        for (u, v) in [(1, 2), (3, 4), (5, 6)].iter() {
+            expected.add_directed_edge(*u, *v);
+        }
+        assert_eq!(after.to_graph(), expected);
+        assert_eq!(before.delta_len(), 4);
+        assert!(before.out_neighbors(5).is_empty());
+    }
+
+    #[test]
+    fn test_concurrent_readers() {
+        let vg = Arc::new(VersionedGraph::new(Graph::empty()));
+        let mut handlers = vec![];
+        for _ in 0..4 {
+            let vg = vg.clone();
+            handlers.push(thread::spawn(move || {
+                for _ in 0..200 {
+                    let snapshot = vg.pin();
+                    // 写的人每次加一条边0 -> i -> 1000，一个版本里的路径数一定等于版本号
+                    let mut result = Vec::new();
+                    let mut path = Vec::new();
+                    let mut visit = HashSet::new();
+                    dfs_parallel(0, 1000, 2, snapshot.clone(), &mut result, None, &mut path, false, &mut visit);
+                    assert_eq!(result.len() as u64, snapshot.version() / 2);
+                }
+            }));
+        }
+        for i in 1..100 {
+            vg.add_directed_edge(0, i);
+            vg.add_directed_edge(i, 1000);
+        }
+        for h in handlers {
+            h.join().unwrap();
+        }
+        assert_eq!(vg.version(), 198);
+    }
+}
@@ -0,0 +1,29 @@
+use crate::structure::Graph;
+
+/// 只读的邻接访问，算法可以同时跑在Graph和CsrGraph上
+pub trait GraphView {
+    /// 从id往外探一度
+    fn out_neighbors(&self, id: i64) -> &[i64];
+
+    /// 从id逆向探一度
+    fn in_neighbors(&self, id: i64) -> &[i64];
+
+    /// 所有有出边的点，不保证顺序
+    fn vertices(&self) -> Vec<i64>;
+}
+
+impl GraphView for Graph {
+    #[inline]
+    fn out_neighbors(&self, id: i64) -> &[i64] {
+        self.out_v(id).map(|x| x.as_slice()).unwrap_or(&[])
+    }
+
+    #[inline]
+    fn in_neighbors(&self, id: i64) -> &[i64] {
+        self.in_v(id).map(|x| x.as_slice()).unwrap_or(&[])
+    }
+
+    fn vertices(&self) -> Vec<i64> {
+        self.v()
+    }
+}
@@ -0,0 +1,157 @@
+//! 动态边的滑动窗口
+//!
+//! 静态边一直留在图里，动态边带着时间戳按插入顺序排队，超出窗口（时间或条数）的从队头淘汰，
+//! 同时从adj/rev_adj里删掉。每淘汰一条边就回调一次evict，回调时边已经删了，
+//! 有重边时可以用graph.has_edge判断这条边是否真的从图里消失了。
+
+use std::collections::VecDeque;
+use crate::structure::Graph;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Window {
+    /// 只保留时间戳在(now - d, now]里的动态边
+    Time(u64),
+    /// 只保留最近的n条动态边
+    Count(usize),
+}
+
+pub struct WindowedGraph {
+    graph: Graph,
+    window: Window,
+    /// 窗口里的动态边(时间戳, 起点, 终点)，时间戳不减
+    dynamic: VecDeque<(u64, i64, i64)>,
+    now: u64,
+}
+
+impl WindowedGraph {
+    /// graph里已有的边都当作静态边
+    pub fn new(graph: Graph, window: Window) -> Self {
+        WindowedGraph {
+            graph,
+            window,
+            dynamic: VecDeque::new(),
+            now: 0,
+        }
+    }
+
+    #[inline]
+    pub fn graph(&self) -> &Graph {
+        &self.graph
+    }
+
+    #[inline]
+    pub fn into_graph(self) -> Graph {
+        self.graph
+    }
+
+    #[inline]
+    pub fn window(&self) -> Window {
+        self.window
+    }
+
+    /// 最新的时间戳
+    #[inline]
+    pub fn now(&self) -> u64 {
+        self.now
+    }
+
+    /// 窗口里的动态边数
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.dynamic.len()
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.dynamic.is_empty()
+    }
+
+    /// 窗口里的动态边，从旧到新
+    pub fn dynamic_edges(&self) -> impl Iterator<Item = &(u64, i64, i64)> {
+        self.dynamic.iter()
+    }
+
+    /// 加一条动态边，然后淘汰窗口外的边，返回淘汰的条数
+    /// 时间戳比已有的小时按当前时间算
+    pub fn insert_edge(&mut self, u: i64, v: i64, ts: u64, evict: &mut dyn FnMut(&Graph, i64, i64)) -> usize {
+        self.now = self.now.max(ts);
+        self.graph.add_directed_edge(u, v);
+        self.dynamic.push_back((self.now, u, v));
+        self.expire(evict)
+    }
+
+    /// 时间推进到now（没有新边时也要调用），返回淘汰的条数
+    pub fn advance(&mut self, now: u64, evict: &mut dyn FnMut(&Graph, i64, i64)) -> usize {
+        self.now = self.now.max(now);
+        self.expire(evict)
+    }
+
+    fn expire(&mut self, evict: &mut dyn FnMut(&Graph, i64, i64)) -> usize {
+        let mut cnt = 0;
+        while let Some((ts, u, v)) = self.dynamic.front().cloned() {
+            let expired = match self.window {
+                Window::Time(d) => ts + d <= self.now,
+                Window::Count(n) => self.dynamic.len() > n,
+            };
+            if !expired {
+                break;
+            }
+            self.dynamic.pop_front();
+            self.graph.remove_directed_edge(u, v);
+            evict(&self.graph, u, v);
+            cnt += 1;
+        }
+        cnt
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::algorithm::live_paths::LivePaths;
+
+    #[test]
+    fn test_time_window() {
+        let mut wg = WindowedGraph::new(Graph::test(), Window::Time(10));
+        let mut evicted = Vec::new();
+        let mut hook = |_: &Graph, u, v| evicted.push((u, v));
+        assert_eq!(wg.insert_edge(3, 4, 1, &mut hook), 0);
+        assert_eq!(wg.insert_edge(1, 2, 5, &mut hook), 0);
+        assert_eq!(wg.insert_edge(4, 5, 11, &mut hook), 1);
+        assert_eq!(wg.len(), 2);
+        assert!(!wg.graph().has_edge(3, 4));
+        assert_eq!(wg.advance(14, &mut hook), 0);
+        assert_eq!(wg.advance(15, &mut hook), 1);
+        assert_eq!(wg.advance(100, &mut hook), 1);
+        assert!(wg.is_empty());
+        assert_eq!(evicted, vec![(3, 4), (1, 2), (4, 5)]);
+        // 和动态边重复的静态边留在图里
+        let mut graph = wg.into_graph();
+        graph.adj.values_mut().for_each(|x| x.sort());
+        assert_eq!(graph, Graph::test());
+    }
+
+    #[test]
+    fn test_count_window() {
+        let mut wg = WindowedGraph::new(Graph::test(), Window::Count(2));
+        let mut hook = |_: &Graph, _, _| {};
+        wg.insert_edge(3, 4, 0, &mut hook);
+        wg.insert_edge(2, 4, 0, &mut hook);
+        assert_eq!(wg.insert_edge(5, 6, 0, &mut hook), 1);
+        assert_eq!(wg.dynamic_edges().map(|e| (e.1, e.2)).collect::<Vec<_>>(), vec![(2, 4), (5, 6)]);
+        assert!(!wg.graph().has_edge(3, 4));
+        assert!(wg.graph().has_edge(2, 4));
+
+        // 淘汰时同步更新结果集
+        let mut live = LivePaths::build(1, 4, 3, wg.graph());
+        assert_eq!(live.paths().cloned().collect::<Vec<_>>(), vec![vec![2]]);
+        let mut hook = |graph: &Graph, u, v| {
+            if !graph.has_edge(u, v) {
+                live.remove_paths_with_edge(u, v);
+            }
+        };
+        wg.insert_edge(6, 7, 0, &mut hook);
+        wg.insert_edge(7, 8, 0, &mut hook);
+        assert!(live.is_empty());
+    }
+}
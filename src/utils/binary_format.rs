@@ -0,0 +1,202 @@
+//! build_binary写出、load_binary读入的adj/rev_adj文件格式
+//!
+//! header(32B): magic "PDFSADJ\0" | byte_order(u8，0小端1大端) | encoding(u8) | 保留(2B) | version(u32)
+//! | node_count(u64) | edge_count(u64)
+//! 若干block: len(u32) | crc32(u32) | len字节的记录
+//! encoding为raw时每条记录是 id(i64) | count(u32) | neighbors(i64 * count)；
+//! 为delta_varint时记录按id升序，邻居也排好序，id存和block里上一个id之差的zigzag varint（第一个存原值），
+//! count存varint，第一个邻居存zigzag varint，之后的邻居存和前一个邻居之差的varint
+//! footer index: 每个block一项 offset(u64) | node_count(u64)
+//! trailer(24B): index_offset(u64) | block_count(u64) | index的crc32(u32) | "PDFE"
+//! byte_order之后的所有数都按header声明的字节序存，默认小端，Hadoop等JVM程序可以直接写大端
+
+use crate::common::io::{ByteBuffer, ByteOrder, MAX_VARINT_LEN};
+use crate::common::error::{Error, Result};
+
+pub const ADJ_MAGIC: [u8; 8] = *b"PDFSADJ\0";
+pub const END_MAGIC: [u8; 4] = *b"PDFE";
+pub const FORMAT_VERSION: u32 = 1;
+
+pub const HEADER_SIZE: usize = 32;
+pub const BLOCK_HEADER_SIZE: usize = 8;
+pub const INDEX_ENTRY_SIZE: usize = 16;
+pub const TRAILER_SIZE: usize = 24;
+
+/// 邻接表记录的编码
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Encoding {
+    /// 定长8字节id
+    Raw,
+    /// 排序后差分再varint，邻居列表的顺序不保留
+    DeltaVarint,
+}
+
+impl Encoding {
+    #[inline]
+    pub fn tag(self) -> u8 {
+        match self {
+            Encoding::Raw => 0,
+            Encoding::DeltaVarint => 1,
+        }
+    }
+
+    pub fn from_tag(tag: u8) -> Option<Self> {
+        match tag {
+            0 => Some(Encoding::Raw),
+            1 => Some(Encoding::DeltaVarint),
+            _ => None,
+        }
+    }
+
+    /// 一条有count个邻居的记录最多占多少字节
+    #[inline]
+    pub fn max_record_size(self, count: usize) -> usize {
+        match self {
+            Encoding::Raw => 12 + count * 8,
+            Encoding::DeltaVarint => MAX_VARINT_LEN * (count + 2),
+        }
+    }
+}
+
+/// 写二进制图的选项
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BuildOptions {
+    pub byte_order: ByteOrder,
+    pub encoding: Encoding,
+    /// 同时写出可以直接mmap的<output_dir>/csr，格式见structure::mapped_csr
+    pub mapped: bool,
+}
+
+impl Default for BuildOptions {
+    fn default() -> Self {
+        BuildOptions {
+            byte_order: ByteOrder::LittleEndian,
+            encoding: Encoding::Raw,
+            mapped: true,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Header {
+    pub byte_order: ByteOrder,
+    pub encoding: Encoding,
+    pub version: u32,
+    pub node_count: u64,
+    pub edge_count: u64,
+}
+
+impl Header {
+    pub fn new(options: &BuildOptions, node_count: u64, edge_count: u64) -> Self {
+        Header {
+            byte_order: options.byte_order,
+            encoding: options.encoding,
+            version: FORMAT_VERSION,
+            node_count,
+            edge_count,
+        }
+    }
+
+    pub fn write(&self, buf: &mut ByteBuffer) -> Result<()> {
+        let order = self.byte_order;
+        buf.put(ADJ_MAGIC)?;
+        buf.put(order.tag())?;
+        buf.put(self.encoding.tag())?;
+        buf.put([0u8; 2])?;
+        buf.put_with(self.version, order)?;
+        buf.put_with(self.node_count, order)?;
+        buf.put_with(self.edge_count, order)
+    }
+
+    /// 读header并检查magic、字节序和版本
+    pub fn read(buf: &mut ByteBuffer) -> Result<Self> {
+        let err = |_| Error::format("unexpected end of file in header");
+        let magic = buf.get::<[u8; 8]>().map_err(err)?;
+        if magic != ADJ_MAGIC {
+            return Err(Error::format("bad magic, not a binary graph file"));
+        }
+        let tag = buf.get::<u8>().map_err(err)?;
+        let order = ByteOrder::from_tag(tag)
+            .ok_or_else(|| Error::format(format!("unknown byte order {}", tag)))?;
+        let tag = buf.get::<u8>().map_err(err)?;
+        let encoding = Encoding::from_tag(tag)
+            .ok_or_else(|| Error::format(format!("unknown encoding {}", tag)))?;
+        buf.get::<[u8; 2]>().map_err(err)?;
+        let header = Header {
+            byte_order: order,
+            encoding,
+            version: buf.get_with(order).map_err(err)?,
+            node_count: buf.get_with(order).map_err(err)?,
+            edge_count: buf.get_with(order).map_err(err)?,
+        };
+        if header.version != FORMAT_VERSION {
+            return Err(Error::format(format!(
+                "unsupported format version {}, expected {}", header.version, FORMAT_VERSION)));
+        }
+        Ok(header)
+    }
+}
+
+/// 写一条记录，prev_id是同一个block里上一条记录的id，block开头传None
+/// delta_varint要求neighbors已经排好序
+pub fn put_record(buf: &mut ByteBuffer, header: &Header, prev_id: Option<i64>, id: i64, neighbors: &[i64]) -> Result<()> {
+    let order = header.byte_order;
+    match header.encoding {
+        Encoding::Raw => {
+            buf.put_with(id, order)?;
+            buf.put_with(neighbors.len() as u32, order)?;
+            for x in neighbors.iter() {
+                buf.put_with(*x, order)?;
+            }
+        }
+        Encoding::DeltaVarint => {
+            buf.put_signed_varint(id.wrapping_sub(prev_id.unwrap_or(0)))?;
+            buf.put_varint(neighbors.len() as u64)?;
+            let mut prev = None;
+            for x in neighbors.iter() {
+                match prev {
+                    None => buf.put_signed_varint(*x)?,
+                    Some(p) => buf.put_varint((*x as u64).wrapping_sub(p as u64))?,
+                }
+                prev = Some(*x);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// 读一条记录，prev_id同put_record
+pub fn get_record(buf: &mut ByteBuffer, header: &Header, prev_id: Option<i64>) -> Result<(i64, Vec<i64>)> {
+    let order = header.byte_order;
+    match header.encoding {
+        Encoding::Raw => {
+            let id = buf.get_with::<i64>(order)?;
+            let count = buf.get_with::<u32>(order)? as usize;
+            if count * 8 > buf.remaining() {
+                return Err(Error::format(format!("record of {} has {} neighbors, more than the rest of block", id, count)));
+            }
+            let mut neighbors = Vec::with_capacity(count);
+            for _ in 0..count {
+                neighbors.push(buf.get_with(order)?);
+            }
+            Ok((id, neighbors))
+        }
+        Encoding::DeltaVarint => {
+            let id = prev_id.unwrap_or(0).wrapping_add(buf.get_signed_varint()?);
+            let count = buf.get_varint()? as usize;
+            if count > buf.remaining() {
+                return Err(Error::format(format!("record of {} has {} neighbors, more than the rest of block", id, count)));
+            }
+            let mut neighbors = Vec::with_capacity(count);
+            for i in 0..count {
+                let x = if i == 0 {
+                    buf.get_signed_varint()?
+                } else {
+                    (neighbors[i - 1] as u64).wrapping_add(buf.get_varint()?) as i64
+                };
+                neighbors.push(x);
+            }
+            Ok((id, neighbors))
+        }
+    }
+}
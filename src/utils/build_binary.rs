@@ -1,38 +1,165 @@
 // 把load上来的数据 write出去
 use crate::common::io::*;
-use crate::structure::{AdjacentList, Graph};
+use crate::common::io::checksum::{crc32, Crc32};
+use super::binary_format::*;
+use crate::structure::{AdjacentList, Graph, CsrGraph};
+use crate::structure::mapped_csr::{CSR_MAGIC, CSR_FILE};
+use crate::common::error::{Error, Result};
 use std::time::Instant;
 
-pub fn build_binary_graph(graph: &Graph, output_dir: String) {
+pub fn build_binary_graph(graph: &Graph, output_dir: String) -> Result<()> {
+    build_binary_graph_with(graph, output_dir, &BuildOptions::default())
+}
+
+pub fn build_binary_graph_with(graph: &Graph, output_dir: String, options: &BuildOptions) -> Result<()> {
     println!("start to build graph");
     let now = Instant::now();
-    fs::mkdir(output_dir.as_str()).unwrap();
-    let adj_path = fs::create_path(&vec![output_dir.as_str(), "adj"]);
-    let rev_adj_path = fs::create_path(&vec![output_dir.as_str(), "rev_adj"]);
-    write_data(adj_path, &graph.adj);
-    write_data(rev_adj_path, &graph.rev_adj);
+    fs::mkdir(output_dir.as_str()).map_err(|e| Error::io(&output_dir, e))?;
+    let adj_path = fs::create_path(&[output_dir.as_str(), "adj"]);
+    let rev_adj_path = fs::create_path(&[output_dir.as_str(), "rev_adj"]);
+    write_data(adj_path, &graph.adj, options)?;
+    write_data(rev_adj_path, &graph.rev_adj, options)?;
+    if options.mapped {
+        write_mapped(graph, fs::create_path(&[output_dir.as_str(), CSR_FILE]))?;
+    }
     println!("finish to build graph, cost {:?}", now.elapsed());
+    Ok(())
 }
 
-fn write_data(path: String, map: &AdjacentList) {
+fn write_data(path: String, map: &AdjacentList, options: &BuildOptions) -> Result<()> {
     if fs::exists(path.as_str()) {
         println!("{} already exists", path);
-        return;
+        return Ok(());
     }
     let mut buf = ByteBuffer::new(128<<20); // 128MB
-    let mut fc = FileChannel::create(path);
-    for (id, adj) in map.iter() {
-        if buf.remaining() < 12 + adj.len() * 8 {
-            buf.flip();
-            fc.write(&mut buf).unwrap();
+    let mut fc = FileChannel::create(path)?;
+    let edge_count = map.values().map(|x| x.len() as u64).sum();
+    let order = options.byte_order;
+    let header = Header::new(options, map.len() as u64, edge_count);
+    header.write(&mut buf)?;
+    buf.flip();
+    fc.write(&mut buf)?;
+
+    let delta = options.encoding == Encoding::DeltaVarint;
+    let mut ids: Vec<i64> = map.keys().cloned().collect();
+    if delta {
+        ids.sort_unstable();
+    }
+    let mut block = BlockWriter::new(HEADER_SIZE as u64);
+    let mut prev = None;
+    let mut sorted = Vec::new();
+    buf.set_position(BLOCK_HEADER_SIZE);
+    for id in ids {
+        let mut adj = map[&id].as_slice();
+        if delta {
+            sorted.clear();
+            sorted.extend_from_slice(adj);
+            sorted.sort_unstable();
+            adj = sorted.as_slice();
+        }
+        if buf.remaining() < options.encoding.max_record_size(adj.len()) && block.nodes > 0 {
+            block.flush(&mut fc, &mut buf, order)?;
+            prev = None;
             println!("write {} B", buf.capacity());
         }
-        buf.put(*id).unwrap();
-        buf.put(adj.len() as u32).unwrap();
-        for x in adj.iter() {
-            buf.put(*x).unwrap();
+        put_record(&mut buf, &header, prev, id, adj)?;
+        prev = Some(id);
+        block.nodes += 1;
+    }
+    if block.nodes > 0 {
+        block.flush(&mut fc, &mut buf, order)?;
+    }
+
+    // footer index和trailer
+    buf.clear();
+    let mut crc = Crc32::new();
+    for (offset, nodes) in block.index.iter() {
+        let start = buf.position();
+        buf.put_with(*offset, order)?;
+        buf.put_with(*nodes, order)?;
+        crc.update(&buf.array()[start..start + INDEX_ENTRY_SIZE]);
+        if buf.remaining() < INDEX_ENTRY_SIZE + TRAILER_SIZE {
+            buf.flip();
+            fc.write(&mut buf)?;
+        }
+    }
+    buf.put_with(block.offset, order)?;
+    buf.put_with(block.index.len() as u64, order)?;
+    buf.put_with(crc.finish(), order)?;
+    buf.put(END_MAGIC)?;
+    buf.flip();
+    fc.write(&mut buf)
+}
+
+/// 记录已经写出的block，buf的前BLOCK_HEADER_SIZE字节留给block头
+struct BlockWriter {
+    offset: u64,
+    nodes: u64,
+    index: Vec<(u64, u64)>,
+}
+
+impl BlockWriter {
+    fn new(offset: u64) -> Self {
+        BlockWriter {
+            offset,
+            nodes: 0,
+            index: Vec::new(),
         }
     }
+
+    fn flush(&mut self, fc: &mut FileChannel, buf: &mut ByteBuffer, order: ByteOrder) -> Result<()> {
+        let end = buf.position();
+        let crc = crc32(&buf.array()[BLOCK_HEADER_SIZE..end]);
+        buf.put_at_with(0, (end - BLOCK_HEADER_SIZE) as u32, order)?;
+        buf.put_at_with(4, crc, order)?;
+        buf.flip();
+        fc.write(buf)?;
+        self.index.push((self.offset, self.nodes));
+        self.offset += end as u64;
+        self.nodes = 0;
+        buf.set_position(BLOCK_HEADER_SIZE);
+        Ok(())
+    }
+}
+
+/// 写出可以直接mmap的CSR文件，格式见structure::mapped_csr
+fn write_mapped(graph: &Graph, path: String) -> Result<()> {
+    let now = Instant::now();
+    if fs::exists(path.as_str()) {
+        println!("{} already exists", path);
+        return Ok(());
+    }
+    let csr = CsrGraph::from_graph(graph);
+    let (out_offsets, out_neighbors) = csr.out_rows();
+    let (in_offsets, in_neighbors) = csr.in_rows();
+    let mut buf = ByteBuffer::new(128<<20); // 128MB
+    let mut fc = FileChannel::create(path)?;
+    // mmap之后直接当数组用，只能是本机字节序
+    let order = ByteOrder::native();
+    buf.put(CSR_MAGIC)?;
+    buf.put(order.tag())?;
+    buf.put([0u8; 7])?;
+    buf.put_with(csr.node_count() as u64, order)?;
+    buf.put_with(out_neighbors.len() as u64, order)?;
+    buf.put_with(in_neighbors.len() as u64, order)?;
+    write_section(&mut fc, &mut buf, csr.ids().iter().cloned())?;
+    write_section(&mut fc, &mut buf, out_offsets.iter().map(|x| *x as u64))?;
+    write_section(&mut fc, &mut buf, out_neighbors.iter().cloned())?;
+    write_section(&mut fc, &mut buf, in_offsets.iter().map(|x| *x as u64))?;
+    write_section(&mut fc, &mut buf, in_neighbors.iter().cloned())?;
     buf.flip();
-    fc.write(&mut buf).unwrap();
-}
\ No newline at end of file
+    fc.write(&mut buf)?;
+    println!("write csr graph, cost {:?}", now.elapsed());
+    Ok(())
+}
+
+fn write_section<T: Number, I: Iterator<Item = T>>(fc: &mut FileChannel, buf: &mut ByteBuffer, data: I) -> Result<()> {
+    for x in data {
+        if buf.remaining() < 8 {
+            buf.flip();
+            fc.write(buf)?;
+        }
+        buf.put_with(x, ByteOrder::native())?;
+    }
+    Ok(())
+}
@@ -1,53 +1,89 @@
-use std::fs::File;
-use std::io::BufReader;
-use std::io::prelude::*;
 use crate::structure::AdjacentList;
 use crate::common::io::*;
 use super::load_source_text::LoadError;
+use super::text_format::TextFormat;
 
 pub fn load_text_data(path: String) -> Result<AdjacentList, LoadError> {
+    load_text_data_with(path, &TextFormat::default())
+}
+
+/// 按format读边表
+pub fn load_text_data_with(path: String, format: &TextFormat) -> Result<AdjacentList, LoadError> {
     let mut adj = AdjacentList::new();
-    let f = File::open(path)?;
     println!("start to load static data");
-    let reader = BufReader::new(f);
-    for line in reader.lines() {
-        let l = line?;
-        let items: Vec<&str> = l.split_whitespace().collect();
-        let src_id = items[0].parse()?;
-        let dst_id = items[1].parse()?;
-        adj.entry(src_id).or_insert(Vec::new()).push(dst_id);
-    }
-    
+    format.for_each_edge(path.as_str(), |e| adj.entry(e.src).or_default().push(e.dst))?;
     Ok(adj)
 }
 
-fn write_data(path: String, map: &AdjacentList) {
+/// 按load_source_binary读的part文件格式写出，大端字节序：
+/// src_id(i64) | count(i32) | dst_id(i64) * count | 分隔符(1B)
+fn write_data(path: String, map: &AdjacentList) -> Result<(), LoadError> {
     if fs::exists(path.as_str()) {
         println!("{} already exists", path);
-        return;
+        return Ok(());
     }
     let mut buf = ByteBuffer::new(128<<20); // 128MB
-    let mut fc = FileChannel::create(path);
+    let mut fc = FileChannel::create(path)?;
     for (id, adj) in map.iter() {
-        if buf.remaining() < 12 + adj.len() * 8 {
+        let need = 8 + 4 + adj.len() * 8 + 1;
+        if buf.remaining() < need {
             buf.flip();
-            fc.write(&mut buf).unwrap();
+            fc.write(&mut buf)?;
             println!("write {} B", buf.capacity());
+            if buf.remaining() < need {
+                buf = ByteBuffer::new(need);
+            }
         }
-        buf.put(*id).unwrap();
-        buf.put(adj.len() as u32).unwrap();
+        buf.put_be(*id)?;
+        buf.put_be(adj.len() as i32)?;
         for x in adj.iter() {
-            buf.put(*x).unwrap();
+            buf.put_be(*x)?;
         }
+        buf.put(b'\n')?;
     }
     buf.flip();
-    fc.write(&mut buf).unwrap();
+    fc.write(&mut buf)
+}
+
+/// 输出目录下的static、dyn两个目录各写一个part文件，可以直接交给load_source_binary::load读
+pub fn build_source_binary(output_dir: String, static_path: String, dynamic_path: String) -> Result<(), LoadError> {
+    let static_adj = load_text_data(static_path)?;
+    write_data(part_file(output_dir.as_str(), "static")?, &static_adj)?;
+    let dyn_adj = load_text_data(dynamic_path)?;
+    write_data(part_file(output_dir.as_str(), "dyn")?, &dyn_adj)
+}
+
+fn part_file(output_dir: &str, name: &str) -> Result<String, LoadError> {
+    let dir = fs::create_path(&[output_dir, name]);
+    fs::mkdir(dir.as_str()).map_err(|e| LoadError::io(&dir, e))?;
+    Ok(fs::create_path(&[dir.as_str(), "part-r-00000"]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::load_source_binary;
+
+    #[test]
+    fn test_build_and_load() {
+        let dir = "test_build_source_binary";
+        fs::mkdir(dir).unwrap();
+        let static_text = fs::create_path(&[dir, "static.txt"]);
+        let dyn_text = fs::create_path(&[dir, "dyn.txt"]);
+        std::fs::write(static_text.as_str(), "1 2\n2 3\n-5 4294967296\n").unwrap();
+        std::fs::write(dyn_text.as_str(), "# dynamic\n1 3\n3 1\n1 4\n").unwrap();
+        let out = fs::create_path(&[dir, "out"]);
+        build_source_binary(out.clone(), static_text, dyn_text).unwrap();
+
+        let graph = load_source_binary::load(
+            fs::create_path(&[out.as_str(), "dyn"]),
+            fs::create_path(&[out.as_str(), "static"])).unwrap();
+        let mut edges: Vec<(i64, i64)> = graph.adj.iter()
+            .flat_map(|(u, vs)| vs.iter().map(move |v| (*u, *v)))
+            .collect();
+        edges.sort_unstable();
+        // 静态边是无向的，读回来两个方向都有
+        assert_eq!(edges, vec![(-5, 4294967296), (1, 2), (1, 3), (1, 4), (2, 1), (2, 3), (3, 1), (3, 2), (4294967296, -5)]);
+        fs::rmr(dir).unwrap();
+    }
 }
-pub fn build_source_binary(output_dir: String, static_path: String, dynamic_path: String) {
-    let static_adj = load_text_data(static_path).unwrap();
-    let static_out = fs::create_path(&vec![output_dir.as_str(), "static"]);
-    write_data(static_out, &static_adj);
-    let dyn_adj = load_text_data(dynamic_path).unwrap();
-    let dyn_out = fs::create_path(&vec![output_dir.as_str(), "dyn"]);
-    write_data(dyn_out, &dyn_adj);
-}
\ No newline at end of file
@@ -0,0 +1,292 @@
+//! 把查询结果或者导出的子图写成DOT（Graphviz）和GraphML，方便分析时画图
+//!
+//! 起点和终点的点带role属性；边带kind属性区分静态边和动态边，可选带multiplicity，即经过这条边的结果路径数
+
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+use std::fs::File;
+use std::io;
+use std::io::BufWriter;
+use std::io::prelude::*;
+use crate::common::error::{Error, Result};
+use crate::structure::{EdgeLabels, GraphPath, GraphView};
+use crate::structure::edge_label::{STATIC_LABEL, DYNAMIC_LABEL};
+
+/// 导出格式
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExportFormat {
+    Dot,
+    GraphML,
+}
+
+/// 子图里的一条有向边
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SubEdge {
+    /// 路径经过的次数，induced子图里是重边的条数
+    pub multiplicity: usize,
+    /// 边的类型，一般是static或dynamic，不知道时为None
+    pub kind: Option<String>,
+}
+
+/// 要导出的子图，点和边都按id排序，输出是确定的
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Subgraph {
+    pub source: Option<i64>,
+    pub target: Option<i64>,
+    pub nodes: BTreeSet<i64>,
+    pub edges: BTreeMap<(i64, i64), SubEdge>,
+}
+
+impl Subgraph {
+    pub fn new() -> Self {
+        Subgraph::default()
+    }
+
+    /// 一次查询所有结果路径的并，paths是dfs的结果，不含起点和终点
+    pub fn from_paths(source: i64, target: i64, paths: &[GraphPath]) -> Self {
+        let mut sub = Subgraph::new();
+        sub.source = Some(source);
+        sub.target = Some(target);
+        sub.nodes.insert(source);
+        sub.nodes.insert(target);
+        for p in paths.iter() {
+            let mut prev = source;
+            for v in p.iter().chain(std::iter::once(&target)) {
+                sub.add_edge(prev, *v);
+                prev = *v;
+            }
+        }
+        sub
+    }
+
+    /// graph在nodes上的导出子图，两端都在nodes里的边都会带上
+    pub fn induced<G: GraphView>(graph: &G, nodes: &[i64]) -> Self {
+        let mut sub = Subgraph::new();
+        let set: HashSet<i64> = nodes.iter().cloned().collect();
+        for u in nodes.iter() {
+            sub.nodes.insert(*u);
+            for v in graph.out_neighbors(*u).iter().filter(|v| set.contains(v)) {
+                sub.add_edge(*u, *v);
+            }
+        }
+        sub
+    }
+
+    /// 加一条边，已有的边multiplicity加一
+    pub fn add_edge(&mut self, src: i64, dst: i64) {
+        self.nodes.insert(src);
+        self.nodes.insert(dst);
+        self.edges.entry((src, dst))
+            .or_insert(SubEdge { multiplicity: 0, kind: None })
+            .multiplicity += 1;
+    }
+
+    /// 标记起点和终点，induced子图用
+    pub fn set_endpoints(&mut self, source: Option<i64>, target: Option<i64>) {
+        self.source = source;
+        self.target = target;
+        self.nodes.extend(source.iter().chain(target.iter()));
+    }
+
+    /// 按load_labeled_data给的标签设置边的kind
+    pub fn label_edges(&mut self, labels: &EdgeLabels) {
+        for ((u, v), e) in self.edges.iter_mut() {
+            e.kind = labels.label(*u, *v).map(|x| x.to_owned());
+        }
+    }
+
+    /// 没有标签时按图推断：反向边也在图里的是静态（无向）边，否则是动态边
+    pub fn infer_kinds<G: GraphView>(&mut self, graph: &G) {
+        for ((u, v), e) in self.edges.iter_mut() {
+            let undirected = graph.out_neighbors(*v).contains(u);
+            e.kind = Some(if undirected { STATIC_LABEL } else { DYNAMIC_LABEL }.to_owned());
+        }
+    }
+
+    fn role(&self, id: i64) -> Option<&'static str> {
+        if Some(id) == self.source {
+            Some("source")
+        } else if Some(id) == self.target {
+            Some("target")
+        } else {
+            None
+        }
+    }
+
+    pub fn write_dot<W: Write>(&self, w: &mut W, multiplicity: bool) -> io::Result<()> {
+        writeln!(w, "digraph paths {{")?;
+        for id in self.nodes.iter() {
+            match self.role(*id) {
+                Some("source") => writeln!(w, "  \"{}\" [role=\"source\", shape=doublecircle, color=green];", id)?,
+                Some(_) => writeln!(w, "  \"{}\" [role=\"target\", shape=doublecircle, color=red];", id)?,
+                None => writeln!(w, "  \"{}\";", id)?,
+            }
+        }
+        for ((u, v), e) in self.edges.iter() {
+            let mut attrs = vec![];
+            if let Some(kind) = &e.kind {
+                attrs.push(format!("kind=\"{}\"", escape_dot(kind)));
+                if kind == STATIC_LABEL {
+                    attrs.push("style=dashed".to_owned());
+                }
+            }
+            if multiplicity {
+                attrs.push(format!("multiplicity={}", e.multiplicity));
+                attrs.push(format!("label=\"{}\"", e.multiplicity));
+            }
+            if attrs.is_empty() {
+                writeln!(w, "  \"{}\" -> \"{}\";", u, v)?;
+            } else {
+                writeln!(w, "  \"{}\" -> \"{}\" [{}];", u, v, attrs.join(", "))?;
+            }
+        }
+        writeln!(w, "}}")
+    }
+
+    pub fn write_graphml<W: Write>(&self, w: &mut W, multiplicity: bool) -> io::Result<()> {
+        writeln!(w, "<?xml version=\"1.0\" encoding=\"UTF-8\"?>")?;
+        writeln!(w, "<graphml xmlns=\"http://graphml.graphdrawing.org/xmlns\">")?;
+        writeln!(w, "  <key id=\"role\" for=\"node\" attr.name=\"role\" attr.type=\"string\"/>")?;
+        writeln!(w, "  <key id=\"kind\" for=\"edge\" attr.name=\"kind\" attr.type=\"string\"/>")?;
+        if multiplicity {
+            writeln!(w, "  <key id=\"multiplicity\" for=\"edge\" attr.name=\"multiplicity\" attr.type=\"long\"/>")?;
+        }
+        writeln!(w, "  <graph id=\"paths\" edgedefault=\"directed\">")?;
+        for id in self.nodes.iter() {
+            match self.role(*id) {
+                Some(role) => writeln!(w, "    <node id=\"n{}\"><data key=\"role\">{}</data></node>", id, role)?,
+                None => writeln!(w, "    <node id=\"n{}\"/>", id)?,
+            }
+        }
+        for ((u, v), e) in self.edges.iter() {
+            write!(w, "    <edge source=\"n{}\" target=\"n{}\">", u, v)?;
+            if let Some(kind) = &e.kind {
+                write!(w, "<data key=\"kind\">{}</data>", escape_xml(kind))?;
+            }
+            if multiplicity {
+                write!(w, "<data key=\"multiplicity\">{}</data>", e.multiplicity)?;
+            }
+            writeln!(w, "</edge>")?;
+        }
+        writeln!(w, "  </graph>")?;
+        writeln!(w, "</graphml>")
+    }
+
+    /// 写到文件path
+    pub fn save(&self, path: &str, format: ExportFormat, multiplicity: bool) -> Result<()> {
+        let file = File::create(path).map_err(|e| Error::io(path, e))?;
+        let mut w = BufWriter::new(file);
+        match format {
+            ExportFormat::Dot => self.write_dot(&mut w, multiplicity),
+            ExportFormat::GraphML => self.write_graphml(&mut w, multiplicity),
+        }.and_then(|_| w.flush()).map_err(|e| Error::io(path, e))
+    }
+}
+
+fn escape_dot(s: &str) -> String {
+    s.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+fn escape_xml(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::io::fs;
+    use crate::structure::Graph;
+
+    /// 1和2之间是静态边，其他是动态边
+    fn graph() -> (Graph, EdgeLabels) {
+        let mut graph = Graph::empty();
+        let mut labels = EdgeLabels::new();
+        graph.add_undirected_edge(1, 2);
+        labels.set_undirected_label(1, 2, STATIC_LABEL);
+        for (u, v) in [(2, 4), (1, 3), (3, 4), (4, 5)].iter() {
+            graph.add_directed_edge(*u, *v);
+            labels.set_label(*u, *v, DYNAMIC_LABEL);
+        }
+        (graph, labels)
+    }
+
+    #[test]
+    fn test_from_paths() {
+        let (graph, labels) = graph();
+        let mut sub = Subgraph::from_paths(1, 4, &[vec![2], vec![3], vec![]]);
+        assert_eq!(sub.nodes.iter().cloned().collect::<Vec<_>>(), vec![1, 2, 3, 4]);
+        assert_eq!(sub.edges.len(), 5);
+        assert_eq!(sub.edges[&(1, 4)].multiplicity, 1);
+        sub.label_edges(&labels);
+        assert_eq!(sub.edges[&(1, 2)].kind.as_deref(), Some(STATIC_LABEL));
+        assert_eq!(sub.edges[&(1, 4)].kind, None);
+        let mut inferred = sub.clone();
+        inferred.infer_kinds(&graph);
+        assert_eq!(inferred.edges[&(1, 2)].kind.as_deref(), Some(STATIC_LABEL));
+        assert_eq!(inferred.edges[&(3, 4)].kind.as_deref(), Some(DYNAMIC_LABEL));
+
+        let sub = Subgraph::from_paths(1, 4, &[vec![2], vec![3, 2], vec![3]]);
+        assert_eq!(sub.edges[&(2, 4)].multiplicity, 2);
+        assert_eq!(sub.edges[&(1, 3)].multiplicity, 2);
+        let mut out = Vec::new();
+        sub.write_dot(&mut out, true).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), "digraph paths {
+  \"1\" [role=\"source\", shape=doublecircle, color=green];
+  \"2\";
+  \"3\";
+  \"4\" [role=\"target\", shape=doublecircle, color=red];
+  \"1\" -> \"2\" [multiplicity=1, label=\"1\"];
+  \"1\" -> \"3\" [multiplicity=2, label=\"2\"];
+  \"2\" -> \"4\" [multiplicity=2, label=\"2\"];
+  \"3\" -> \"2\" [multiplicity=1, label=\"1\"];
+  \"3\" -> \"4\" [multiplicity=1, label=\"1\"];
+}
+");
+    }
+
+    #[test]
+    fn test_induced() {
+        let (graph, labels) = graph();
+        let mut sub = Subgraph::induced(&graph, &[1, 2, 4, 5, 100]);
+        sub.label_edges(&labels);
+        sub.set_endpoints(Some(1), None);
+        let edges: Vec<(i64, i64)> = sub.edges.keys().cloned().collect();
+        assert_eq!(edges, vec![(1, 2), (2, 1), (2, 4), (4, 5)]);
+        assert!(sub.nodes.contains(&100));
+
+        let mut out = Vec::new();
+        sub.write_dot(&mut out, false).unwrap();
+        let dot = String::from_utf8(out).unwrap();
+        assert!(dot.contains("\"1\" -> \"2\" [kind=\"static\", style=dashed];\n"));
+        assert!(dot.contains("\"4\" -> \"5\" [kind=\"dynamic\"];\n"));
+        assert!(!dot.contains("multiplicity"));
+
+        let mut out = Vec::new();
+        sub.write_graphml(&mut out, true).unwrap();
+        let xml = String::from_utf8(out).unwrap();
+        assert!(xml.contains("<node id=\"n1\"><data key=\"role\">source</data></node>"));
+        assert!(xml.contains("<node id=\"n100\"/>"));
+        assert!(xml.contains("<edge source=\"n2\" target=\"n4\"><data key=\"kind\">dynamic</data><data key=\"multiplicity\">1</data></edge>"));
+        assert_eq!(xml.matches("<edge ").count(), 4);
+        assert!(xml.trim_end().ends_with("</graphml>"));
+    }
+
+    #[test]
+    fn test_save() {
+        let dir = "test_dir_export";
+        fs::mkdir(dir).unwrap();
+        let mut sub = Subgraph::from_paths(-1, 2, &[vec![-3]]);
+        sub.edges.get_mut(&(-1, -3)).unwrap().kind = Some("a<\"b\">&".to_owned());
+        let dot = fs::create_path(&[dir, "paths.dot"]);
+        sub.save(dot.as_str(), ExportFormat::Dot, false).unwrap();
+        assert!(std::fs::read_to_string(dot.as_str()).unwrap().contains("\"-1\" -> \"-3\" [kind=\"a<\\\"b\\\">&\"];"));
+        let xml = fs::create_path(&[dir, "paths.graphml"]);
+        sub.save(xml.as_str(), ExportFormat::GraphML, false).unwrap();
+        assert!(std::fs::read_to_string(xml.as_str()).unwrap().contains("<data key=\"kind\">a&lt;&quot;b&quot;&gt;&amp;</data>"));
+        fs::rmr(dir).unwrap();
+        let err = sub.save(dot.as_str(), ExportFormat::Dot, false).unwrap_err();
+        assert!(err.is_io(io::ErrorKind::NotFound));
+    }
+}
@@ -0,0 +1,346 @@
+//! 读公开数据集常用的图格式，都得到Graph，可以直接交给build_binary_graph转成二进制
+//!
+//! SNAP：#开头的注释头加"src dst"边表，注释里写了Undirected的按无向边读
+//! Matrix Market：coordinate格式，general按有向边读，symmetric/skew-symmetric/hermitian按无向边读，
+//! 点号就是文件里的行列号（从1开始），值列忽略
+//! METIS：第一行"n m [fmt [ncon]]"，之后第i行是点i的邻居（从1开始），点权和边权忽略
+
+use std::fs::File;
+use std::io::BufReader;
+use std::io::prelude::*;
+use crate::common::error::{Error, Result};
+use crate::structure::Graph;
+use super::text_format::TextFormat;
+
+/// 支持导入的格式
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImportFormat {
+    Snap,
+    MatrixMarket,
+    Metis,
+}
+
+impl ImportFormat {
+    /// 按扩展名猜格式：.mtx是Matrix Market，.graph/.metis是METIS，其他当SNAP边表
+    pub fn from_path(path: &str) -> Self {
+        let ext = std::path::Path::new(path)
+            .extension()
+            .map(|x| x.to_string_lossy().to_lowercase())
+            .unwrap_or_default();
+        match ext.as_str() {
+            "mtx" => ImportFormat::MatrixMarket,
+            "graph" | "metis" => ImportFormat::Metis,
+            _ => ImportFormat::Snap,
+        }
+    }
+}
+
+pub fn import_graph(path: &str, format: ImportFormat) -> Result<Graph> {
+    match format {
+        ImportFormat::Snap => import_snap(path),
+        ImportFormat::MatrixMarket => import_matrix_market(path),
+        ImportFormat::Metis => import_metis(path),
+    }
+}
+
+/// 按行读文件，f的参数是行号（从1开始）和这一行
+fn for_each_line<F: FnMut(usize, &str) -> Result<()>>(path: &str, mut f: F) -> Result<()> {
+    let file = File::open(path).map_err(|e| Error::io(path, e))?;
+    let reader = BufReader::new(file);
+    for (idx, line) in reader.lines().enumerate() {
+        let l = line.map_err(|e| Error::io(path, e))?;
+        f(idx + 1, &l).map_err(|e| e.with_path(path))?;
+    }
+    Ok(())
+}
+
+fn parse_num<T: std::str::FromStr>(x: &str, line: usize, what: &str) -> Result<T>
+    where T::Err: std::fmt::Display {
+    x.parse::<T>().map_err(|e| Error::parse(line, format!("invalid {} {:?}: {}", what, x, e)))
+}
+
+/// SNAP边表，开头注释里有"Undirected"时每条边按无向边加
+pub fn import_snap(path: &str) -> Result<Graph> {
+    let mut undirected = false;
+    let file = File::open(path).map_err(|e| Error::io(path, e))?;
+    for line in BufReader::new(file).lines() {
+        let l = line.map_err(|e| Error::io(path, e))?;
+        let l = l.trim();
+        if !l.is_empty() && !l.starts_with('#') {
+            break;
+        }
+        if l.starts_with('#') && l.to_lowercase().contains("undirected") {
+            undirected = true;
+        }
+    }
+    let mut graph = Graph::empty();
+    TextFormat::default().for_each_edge(path, |e| {
+        if undirected {
+            graph.add_undirected_edge(e.src, e.dst);
+        } else {
+            graph.add_directed_edge(e.src, e.dst);
+        }
+    })?;
+    Ok(graph)
+}
+
+/// Matrix Market coordinate文件，每个非零元(i, j)是一条边i -> j，对称矩阵只存了下三角，按无向边加
+pub fn import_matrix_market(path: &str) -> Result<Graph> {
+    let mut graph = Graph::empty();
+    let mut symmetric = None;
+    // (行数, 列数, 非零元个数)
+    let mut size: Option<(i64, i64, usize)> = None;
+    let mut entries = 0;
+    for_each_line(path, |line, l| {
+        let sym = match symmetric {
+            None => {
+                let items: Vec<String> = l.split_whitespace().map(|x| x.to_lowercase()).collect();
+                if items.len() != 5 || items[0] != "%%matrixmarket" || items[1] != "matrix" {
+                    return Err(Error::parse(line, "expected header \"%%MatrixMarket matrix coordinate <field> <symmetry>\""));
+                }
+                if items[2] != "coordinate" {
+                    return Err(Error::parse(line, format!("unsupported format {:?}, only coordinate", items[2])));
+                }
+                symmetric = match items[4].as_str() {
+                    "general" => Some(false),
+                    "symmetric" | "skew-symmetric" | "hermitian" => Some(true),
+                    x => return Err(Error::parse(line, format!("unknown symmetry {:?}", x))),
+                };
+                return Ok(());
+            }
+            Some(x) => x,
+        };
+        let l = l.trim();
+        if l.is_empty() || l.starts_with('%') {
+            return Ok(());
+        }
+        let items: Vec<&str> = l.split_whitespace().collect();
+        match size {
+            None => {
+                if items.len() != 3 {
+                    return Err(Error::parse(line, format!("expected \"rows cols entries\", found {} fields", items.len())));
+                }
+                size = Some((parse_num(items[0], line, "size")?, parse_num(items[1], line, "size")?,
+                             parse_num(items[2], line, "size")?));
+            }
+            Some((rows, cols, nnz)) => {
+                if items.len() < 2 {
+                    return Err(Error::parse(line, format!("expected at least 2 fields, found {}", items.len())));
+                }
+                let i: i64 = parse_num(items[0], line, "row")?;
+                let j: i64 = parse_num(items[1], line, "column")?;
+                if i < 1 || i > rows || j < 1 || j > cols {
+                    return Err(Error::parse(line, format!("entry ({}, {}) out of {}x{} matrix", i, j, rows, cols)));
+                }
+                entries += 1;
+                if entries > nnz {
+                    return Err(Error::parse(line, format!("more than {} entries", nnz)));
+                }
+                if sym && i != j {
+                    graph.add_undirected_edge(i, j);
+                } else {
+                    graph.add_directed_edge(i, j);
+                }
+            }
+        }
+        Ok(())
+    })?;
+    match size {
+        None => Err(Error::format("missing size line").with_path(path)),
+        Some((_, _, nnz)) if nnz != entries => {
+            Err(Error::format(format!("expected {} entries, found {}", nnz, entries)).with_path(path))
+        }
+        _ => Ok(graph),
+    }
+}
+
+/// METIS邻接表文件，每条无向边在两个端点的行里各出现一次，所以逐项按有向边加
+pub fn import_metis(path: &str) -> Result<Graph> {
+    let mut graph = Graph::empty();
+    // (点数, 边数, 是否有边权, 每行开头要跳过的点大小和点权个数)
+    let mut header: Option<(i64, usize, bool, usize)> = None;
+    let mut vertex = 0;
+    let mut entries = 0;
+    for_each_line(path, |line, l| {
+        if l.trim_start().starts_with('%') {
+            return Ok(());
+        }
+        let items: Vec<&str> = l.split_whitespace().collect();
+        let (n, _, edge_weights, ncon) = match header {
+            None => {
+                if items.is_empty() {
+                    return Ok(());
+                }
+                if items.len() > 4 || items.len() < 2 {
+                    return Err(Error::parse(line, "expected header \"n m [fmt [ncon]]\""));
+                }
+                let n = parse_num(items[0], line, "vertex count")?;
+                let m = parse_num(items[1], line, "edge count")?;
+                let fmt = items.get(2).copied().unwrap_or("0");
+                if fmt.len() > 3 || fmt.chars().any(|c| c != '0' && c != '1') {
+                    return Err(Error::parse(line, format!("invalid fmt {:?}", fmt)));
+                }
+                let fmt = format!("{:0>3}", fmt);
+                let vertex_weights = &fmt[1..2] == "1";
+                let ncon = match items.get(3) {
+                    Some(x) if vertex_weights => parse_num(x, line, "ncon")?,
+                    _ => if vertex_weights { 1 } else { 0 },
+                };
+                // 第一位是点的大小，和点权一样忽略
+                let skip = ncon + if &fmt[0..1] == "1" { 1 } else { 0 };
+                header = Some((n, m, &fmt[2..3] == "1", skip));
+                return Ok(());
+            }
+            Some(x) => x,
+        };
+        vertex += 1;
+        if vertex > n {
+            if items.is_empty() {
+                return Ok(());
+            }
+            return Err(Error::parse(line, format!("more than {} vertices", n)));
+        }
+        if items.len() < ncon {
+            return Err(Error::parse(line, format!("expected {} vertex weights, found {}", ncon, items.len())));
+        }
+        let rest = &items[ncon..];
+        let step = if edge_weights { 2 } else { 1 };
+        if !rest.len().is_multiple_of(step) {
+            return Err(Error::parse(line, "neighbor without edge weight"));
+        }
+        for x in rest.iter().step_by(step) {
+            let u: i64 = parse_num(x, line, "vertex")?;
+            if u < 1 || u > n {
+                return Err(Error::parse(line, format!("vertex {} out of 1..={}", u, n)));
+            }
+            graph.add_directed_edge(vertex, u);
+            entries += 1;
+        }
+        Ok(())
+    })?;
+    match header {
+        None => Err(Error::format("missing header").with_path(path)),
+        Some((n, _, _, _)) if vertex < n => {
+            Err(Error::format(format!("expected {} vertices, found {}", n, vertex)).with_path(path))
+        }
+        Some((_, m, _, _)) if entries != 2 * m => {
+            Err(Error::format(format!("header says {} edges, found {} adjacency entries", m, entries)).with_path(path))
+        }
+        _ => Ok(graph),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::io::fs;
+    use crate::structure::GraphView;
+    use crate::utils::build_binary::build_binary_graph;
+    use crate::utils::load_binary::load_binary_graph;
+
+    fn write(dir: &str, name: &str, content: &str) -> String {
+        fs::mkdir(dir).unwrap();
+        let path = fs::create_path(&[dir, name]);
+        std::fs::write(path.as_str(), content).unwrap();
+        path
+    }
+
+    /// 无向三角形1-2-3
+    fn triangle() -> Graph {
+        let mut graph = Graph::empty();
+        graph.add_undirected_edge(1, 2);
+        graph.add_undirected_edge(2, 3);
+        graph.add_undirected_edge(3, 1);
+        graph
+    }
+
+    #[test]
+    fn test_snap() {
+        let dir = "test_dir_import_snap";
+        let path = write(dir, "snap.txt", "# Directed graph (each unordered pair of nodes is saved once)\n# FromNodeId\tToNodeId\n1\t2\n2\t3\n");
+        let graph = import_graph(path.as_str(), ImportFormat::from_path(path.as_str())).unwrap();
+        assert_eq!(graph.out_neighbors(1), &[2]);
+        assert_eq!(graph.in_neighbors(2), &[1]);
+
+        let path = write(dir, "snap_undirected.txt", "# Undirected graph: ../../data/output/email.txt\n1 2\n2 3\n3 1\n");
+        assert_eq!(import_snap(path.as_str()).unwrap(), triangle());
+        let path = write(dir, "snap_bad.txt", "# Nodes: 2\n1 2\n2\n");
+        assert_eq!(import_snap(path.as_str()).unwrap_err().to_string(), format!("{}:3: expected 2 fields, found 1", path));
+        fs::rmr(dir).unwrap();
+    }
+
+    #[test]
+    fn test_matrix_market() {
+        let dir = "test_dir_import_mtx";
+        let path = write(dir, "sym.mtx", "%%MatrixMarket matrix coordinate pattern symmetric\n% comment\n3 3 4\n2 1\n3 2\n1 3\n2 2\n");
+        assert_eq!(ImportFormat::from_path(path.as_str()), ImportFormat::MatrixMarket);
+        let mut expected = triangle();
+        expected.add_directed_edge(2, 2);
+        assert_eq!(import_matrix_market(path.as_str()).unwrap(), expected);
+
+        let path = write(dir, "general.mtx", "%%MatrixMarket matrix coordinate real general\n3 4 2\n1 4 0.5\n3 1 -1e3\n");
+        let graph = import_matrix_market(path.as_str()).unwrap();
+        assert_eq!(graph.out_neighbors(1), &[4]);
+        assert_eq!(graph.in_neighbors(1), &[3]);
+
+        let bad = [
+            ("%%MatrixMarket matrix array real general\n2 2\n", "1: unsupported format"),
+            ("%%MatrixMarket matrix coordinate real general\n2 2 1\n3 1 1\n", "3: entry (3, 1) out of 2x2 matrix"),
+            ("%%MatrixMarket matrix coordinate real general\n2 2 2\n1 1 1\n", "expected 2 entries, found 1"),
+            ("1 2\n", "1: expected header"),
+        ];
+        for (content, msg) in bad.iter() {
+            let path = write(dir, "bad.mtx", content);
+            let err = import_matrix_market(path.as_str()).unwrap_err().to_string();
+            assert!(err.starts_with(path.as_str()) && err.contains(msg), "{}", err);
+        }
+        fs::rmr(dir).unwrap();
+    }
+
+    #[test]
+    fn test_metis() {
+        let dir = "test_dir_import_metis";
+        let path = write(dir, "tri.graph", "% triangle\n3 3\n2 3\n1 3\n1 2\n");
+        let graph = import_metis(path.as_str()).unwrap();
+        assert_eq!(ImportFormat::from_path(path.as_str()), ImportFormat::Metis);
+        let mut expected = triangle();
+        expected.adj.values_mut().for_each(|x| x.sort_unstable());
+        expected.rev_adj.values_mut().for_each(|x| x.sort_unstable());
+        let mut sorted = graph;
+        sorted.adj.values_mut().for_each(|x| x.sort_unstable());
+        sorted.rev_adj.values_mut().for_each(|x| x.sort_unstable());
+        assert_eq!(sorted, expected);
+
+        // 点权2个、带边权，点4是孤立点
+        let path = write(dir, "weighted.graph", "4 1 011 2\n5 6 2 9\n7 8 1 9\n0 0\n0 0\n");
+        let graph = import_metis(path.as_str()).unwrap();
+        assert_eq!(graph.out_neighbors(1), &[2]);
+        assert_eq!(graph.out_neighbors(2), &[1]);
+        assert!(graph.out_neighbors(4).is_empty());
+
+        let bad = [
+            ("3 3\n2 3\n1 3\n", "expected 3 vertices, found 2"),
+            ("2 1\n2\n3\n", "3: vertex 3 out of 1..=2"),
+            ("2 2\n2\n1\n", "header says 2 edges, found 2 adjacency entries"),
+            ("2 1 1\n2\n1 1\n", "2: neighbor without edge weight"),
+        ];
+        for (content, msg) in bad.iter() {
+            let path = write(dir, "bad.graph", content);
+            let err = import_metis(path.as_str()).unwrap_err().to_string();
+            assert!(err.starts_with(path.as_str()) && err.contains(msg), "{}", err);
+        }
+        fs::rmr(dir).unwrap();
+    }
+
+    #[test]
+    fn test_to_binary() {
+        let dir = "test_dir_import_binary";
+        let out = "test_dir_import_binary_out";
+        let path = write(dir, "to_binary.mtx", "%%MatrixMarket matrix coordinate integer symmetric\n4 4 3\n2 1 1\n3 2 1\n4 1 7\n");
+        let graph = import_matrix_market(path.as_str()).unwrap();
+        build_binary_graph(&graph, out.to_owned()).unwrap();
+        assert_eq!(load_binary_graph(out.to_owned()).unwrap(), graph);
+        fs::rmr(out).unwrap();
+        fs::rmr(dir).unwrap();
+    }
+}
@@ -1,51 +1,216 @@
 use crate::structure::{Graph, AdjacentList};
+use crate::structure::mapped_csr::{MappedCsrGraph, CSR_FILE};
+use super::load_source_text::LoadError;
 use crate::common::io::*;
+use crate::common::io::checksum::{crc32, Crc32};
+use super::binary_format::*;
 use std::time::Instant;
 
-pub fn load_binary_graph(dir: String) -> Graph {
+pub fn load_binary_graph(dir: String) -> Result<Graph, LoadError> {
     println!("start to load binary graph");
     let now = Instant::now();
-    let adj_path = fs::create_path(&vec![dir.as_str(), "adj"]);
-    let rev_adj_path = fs::create_path(&vec![dir.as_str(), "rev_adj"]);
+    let adj_path = fs::create_path(&[dir.as_str(), "adj"]);
+    let rev_adj_path = fs::create_path(&[dir.as_str(), "rev_adj"]);
 
-    let adj = read_data(adj_path);
-    let rev_adj = read_data(rev_adj_path);
+    let adj = read_data(adj_path)?;
+    let rev_adj = read_data(rev_adj_path)?;
     println!("finish to load binary graph, cost {:?}", now.elapsed());
-    Graph::new(adj, rev_adj)
+    Ok(Graph::new(adj, rev_adj))
 }
 
-fn read_data(path: String) -> AdjacentList {
+/// 直接mmap build_binary_graph写出的<dir>/csr，不解析内容
+pub fn load_mapped_graph(dir: String) -> Result<MappedCsrGraph, LoadError> {
+    let path = fs::create_path(&[dir.as_str(), CSR_FILE]);
+    let graph = MappedCsrGraph::open(path)?;
+    println!("map csr graph with {} nodes, {} edges", graph.node_count(), graph.edge_count());
+    Ok(graph)
+}
+
+fn read_data(path: String) -> Result<AdjacentList, LoadError> {
+    let mut fc = FileChannel::open(path.as_str())?;
+    let file_len = fc.len()?;
     let mut buf = ByteBuffer::new(128<<20);
-    let mut fc = FileChannel::open(path);
-    let mut ret = AdjacentList::new();
-    fc.read(&mut buf).unwrap();
+    read_blocks(&mut fc, &mut buf, file_len).map_err(|e| e.with_path(&path))
+}
+
+fn read_blocks(fc: &mut FileChannel, buf: &mut ByteBuffer, file_len: u64) -> Result<AdjacentList, LoadError> {
     buf.flip();
-    let mut cnt = 0;
-    loop {
-        if buf.remaining() < 12 {
-            buf.compact();
-            let size = fc.read(&mut buf).unwrap();
-            buf.flip();
-            if size == 0 {
-                break;
-            }
+    fill(fc, buf, HEADER_SIZE)?;
+    let header = Header::read(buf)?;
+    let order = header.byte_order;
+    let mut ret = AdjacentList::new();
+    let mut offset = HEADER_SIZE as u64;
+    let mut index = Vec::new();
+    let mut nodes = 0;
+    let mut edges = 0;
+    while nodes < header.node_count {
+        fill(fc, buf, BLOCK_HEADER_SIZE)?;
+        let len = buf.get_with::<u32>(order)? as usize;
+        let crc = buf.get_with::<u32>(order)?;
+        fill(fc, buf, len)?;
+        let start = buf.position();
+        if crc32(&buf.array()[start..start + len]) != crc {
+            return Err(LoadError::format(format!("checksum mismatch in block at offset {}", offset)));
+        }
+        // 只在这个block里解析记录
+        let limit = buf.limit();
+        buf.set_limit(start + len);
+        let mut block_nodes = 0;
+        let mut prev = None;
+        while buf.has_remaining() {
+            let (src_id, tmp) = get_record(buf, &header, prev).map_err(|_| {
+                LoadError::format(format!("bad record in block at offset {}", offset))
+            })?;
+            edges += tmp.len() as u64;
+            prev = Some(src_id);
+            ret.insert(src_id, tmp);
+            block_nodes += 1;
         }
-        let src_id = buf.get::<i64>().unwrap();
-        let count = buf.get::<u32>().unwrap();
-        let mut tmp = Vec::with_capacity(count as usize);
-        for _ in 0..count {
-            if buf.remaining() < 8 {
-                buf.compact();
-                fc.read(&mut buf).unwrap();
-                buf.flip();
-            }
-            tmp.push(buf.get().unwrap());
+        buf.set_limit(limit);
+        index.push((offset, block_nodes));
+        offset += (BLOCK_HEADER_SIZE + len) as u64;
+        nodes += block_nodes;
+        if nodes % 1000000 < block_nodes {
+            println!("load {} nodes", nodes);
+        }
+    }
+    if nodes != header.node_count || edges != header.edge_count {
+        return Err(LoadError::format(format!(
+            "header says {} nodes and {} edges, found {} nodes and {} edges",
+            header.node_count, header.edge_count, nodes, edges)));
+    }
+
+    // 用footer index和trailer核对block
+    let footer_len = index.len() * INDEX_ENTRY_SIZE + TRAILER_SIZE;
+    if offset + footer_len as u64 != file_len {
+        return Err(LoadError::format(format!(
+            "file has {} bytes, expected {}", file_len, offset + footer_len as u64)));
+    }
+    let mut crc = Crc32::new();
+    for (block_offset, block_nodes) in index.iter() {
+        fill(fc, buf, INDEX_ENTRY_SIZE)?;
+        let start = buf.position();
+        crc.update(&buf.array()[start..start + INDEX_ENTRY_SIZE]);
+        if buf.get_with::<u64>(order)? != *block_offset || buf.get_with::<u64>(order)? != *block_nodes {
+            return Err(LoadError::format(format!("footer index does not match block at offset {}", block_offset)));
         }
-        ret.insert(src_id, tmp);
-        cnt += 1;
-        if cnt % 1000000 == 0 {
-            println!("load {} nodes", cnt);
+    }
+    fill(fc, buf, TRAILER_SIZE)?;
+    let index_offset = buf.get_with::<u64>(order)?;
+    let block_count = buf.get_with::<u64>(order)?;
+    let index_crc = buf.get_with::<u32>(order)?;
+    let end_magic = buf.get::<[u8; 4]>()?;
+    if end_magic != END_MAGIC || index_offset != offset || block_count != index.len() as u64 {
+        return Err(LoadError::format("bad trailer"));
+    }
+    if index_crc != crc.finish() {
+        return Err(LoadError::format("checksum mismatch in footer index"));
+    }
+    Ok(ret)
+}
+
+/// 保证buf里至少还有n个字节没读
+fn fill(fc: &mut FileChannel, buf: &mut ByteBuffer, n: usize) -> Result<(), LoadError> {
+    if buf.remaining() >= n {
+        return Ok(());
+    }
+    if n > buf.capacity() {
+        return Err(LoadError::format(format!("block of {} bytes is larger than the read buffer", n)));
+    }
+    buf.compact();
+    while buf.position() < n {
+        let size = fc.read(buf)?;
+        if size == 0 {
+            return Err(LoadError::format("unexpected end of file"));
         }
     }
-    ret
+    buf.flip();
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::build_binary::{build_binary_graph, build_binary_graph_with};
+
+    static TEST_DIR: &str = "test_dir_load_binary";
+    static TEST_DIR_RAW: &str = "test_dir_load_binary_raw";
+
+    fn corrupt(path: &str, f: &dyn Fn(&mut Vec<u8>)) -> Result<AdjacentList, LoadError> {
+        let origin = std::fs::read(path).unwrap();
+        let mut data = origin.clone();
+        f(&mut data);
+        std::fs::write(path, &data).unwrap();
+        let ret = read_data(path.to_owned());
+        std::fs::write(path, &origin).unwrap();
+        ret
+    }
+
+    #[test]
+    fn test_load_binary_graph() {
+        let graph = Graph::test_large();
+        build_binary_graph(&graph, TEST_DIR.to_owned()).unwrap();
+        assert_eq!(load_binary_graph(TEST_DIR.to_owned()).unwrap(), graph);
+
+        let adj_path = fs::create_path(&[TEST_DIR, "adj"]);
+        assert!(corrupt(adj_path.as_str(), &|_| {}).is_ok());
+        let err = corrupt(adj_path.as_str(), &|d| d[0] = b'X').unwrap_err();
+        assert_eq!(err.to_string(), format!("{}: bad magic, not a binary graph file", adj_path));
+        let err = corrupt(adj_path.as_str(), &|d| d[100] ^= 1).unwrap_err();
+        assert!(err.to_string().contains("checksum mismatch"));
+        let err = corrupt(adj_path.as_str(), &|d| d.truncate(d.len() - 30)).unwrap_err();
+        assert!(err.to_string().contains("expected"));
+        let err = corrupt(adj_path.as_str(), &|d| d.truncate(1000)).unwrap_err();
+        assert!(err.to_string().contains("unexpected end of file"));
+        let err = corrupt(adj_path.as_str(), &|d| d.extend_from_slice(&[0; 8])).unwrap_err();
+        assert!(err.to_string().contains("expected"));
+
+        fs::rmr(TEST_DIR).unwrap();
+        let err = load_binary_graph(TEST_DIR.to_owned()).unwrap_err();
+        assert!(err.is_io(std::io::ErrorKind::NotFound));
+        assert!(err.to_string().starts_with(adj_path.as_str()));
+    }
+
+    #[test]
+    fn test_big_endian() {
+        let dir = "test_dir_load_binary_be";
+        let graph = Graph::test_large();
+        let options = BuildOptions { byte_order: ByteOrder::BigEndian, ..Default::default() };
+        build_binary_graph_with(&graph, dir.to_owned(), &options).unwrap();
+        let data = std::fs::read(fs::create_path(&[dir, "adj"])).unwrap();
+        assert_eq!(data[8], ByteOrder::BigEndian.tag());
+        assert_eq!(&data[12..16], &FORMAT_VERSION.to_be_bytes());
+        assert_eq!(load_binary_graph(dir.to_owned()).unwrap(), graph);
+        fs::rmr(dir).unwrap();
+    }
+
+    #[test]
+    fn test_delta_varint() {
+        let dir = "test_dir_load_binary_varint";
+        let mut graph = Graph::test_large();
+        graph.add_directed_edge(i64::MIN, i64::MAX);
+        graph.add_directed_edge(i64::MIN, -5);
+        graph.add_directed_edge(i64::MAX, i64::MIN);
+        let options = BuildOptions { byte_order: ByteOrder::BigEndian, encoding: Encoding::DeltaVarint, mapped: false };
+        build_binary_graph_with(&graph, dir.to_owned(), &options).unwrap();
+        build_binary_graph(&graph, TEST_DIR_RAW.to_owned()).unwrap();
+        let compressed = std::fs::metadata(fs::create_path(&[dir, "adj"])).unwrap().len();
+        let raw = std::fs::metadata(fs::create_path(&[TEST_DIR_RAW, "adj"])).unwrap().len();
+        assert!(compressed * 3 < raw, "{} {}", compressed, raw);
+
+        // 邻居列表按排序后的顺序读回来
+        graph.adj.values_mut().for_each(|x| x.sort_unstable());
+        graph.rev_adj.values_mut().for_each(|x| x.sort_unstable());
+        assert_eq!(load_binary_graph(dir.to_owned()).unwrap(), graph);
+        fs::rmr(dir).unwrap();
+        fs::rmr(TEST_DIR_RAW).unwrap();
+    }
+
+    #[test]
+    fn test_empty_graph() {
+        let dir = "test_dir_load_binary_empty";
+        build_binary_graph(&Graph::empty(), dir.to_owned()).unwrap();
+        assert_eq!(load_binary_graph(dir.to_owned()).unwrap(), Graph::empty());
+        fs::rmr(dir).unwrap();
+    }
 }
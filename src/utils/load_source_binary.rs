@@ -1,125 +1,202 @@
+//! Hadoop作业写出的part文件，大端字节序（Java DataOutput）
+//! 每条记录：src_id(i64) | count(i32) | dst_id(i64) * count | 分隔符(1B)
+
 use std::path::Path;
 use std::collections::HashMap;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::{self, SyncSender};
+use std::thread;
 use std::time::Instant;
 use crate::structure::Graph;
 use crate::common::io::*;
+use super::load_source_text::LoadError;
+
+/// 默认读part文件的线程数
+pub const PART_THREADS: usize = 4;
 
 /// 参数：动态数据目录和静态数据目录
 /// 返回值：正向边邻接表和反向边邻接表
-pub fn load<P: AsRef<Path>>(dynamic_data_path: P, static_data_path: P) -> Graph {
+pub fn load<P: AsRef<Path>>(dynamic_data_path: P, static_data_path: P) -> Result<Graph, LoadError> {
+    load_parallel(dynamic_data_path, static_data_path, PART_THREADS)
+}
+
+/// 和load一样，用threads个线程读part文件，结果和线程数无关
+pub fn load_parallel<P: AsRef<Path>>(dynamic_data_path: P, static_data_path: P, threads: usize) -> Result<Graph, LoadError> {
     let mut adj = HashMap::new();
     let mut rev_adj = HashMap::new();
-    load_dynamic(dynamic_data_path, &mut adj, &mut rev_adj);
-    load_static(static_data_path, &mut adj, &mut rev_adj);
-    Graph::new(adj, rev_adj)
+    load_dynamic(dynamic_data_path, threads, &mut adj, &mut rev_adj)?;
+    load_static(static_data_path, threads, &mut adj, &mut rev_adj)?;
+    Ok(Graph::new(adj, rev_adj))
 }
 
 
 /// 参数：动态数据目录
 /// 返回值(由参数返回)：正向边邻接表和反向边邻接表
-fn load_dynamic<P: AsRef<Path>>(path: P, adj: &mut HashMap<i64, Vec<i64>>, rev_adj: &mut HashMap<i64, Vec<i64>>) {
+fn load_dynamic<P: AsRef<Path>>(path: P, threads: usize, adj: &mut HashMap<i64, Vec<i64>>, rev_adj: &mut HashMap<i64, Vec<i64>>) -> Result<(), LoadError> {
     let now = Instant::now();
-    let mut buf = ByteBuffer::new(128<<20);
-    let files = fs::ls(path).unwrap();
-    let mut cnt = 0;
-    for file in files {
-        buf.clear();
-        let mut fc = FileChannel::open(file);
-        fc.read(&mut buf).unwrap();
-        buf.flip();
-        loop {
-            if buf.remaining() < 4 + 8 {
-                buf.compact();
-                let size = fc.read(&mut buf).unwrap();
-                buf.flip();
-                if size == 0 {
-                    break;
+    let cnt = read_part_dir(path, threads, |src_id, dst_id| {
+        adj.entry(src_id).or_default().push(dst_id);
+        rev_adj.entry(dst_id).or_default().push(src_id);
+    })?;
+    println!("load dynamic success, {} nodes, cost {:?}", cnt, now.elapsed());
+    Ok(())
+}
+
+/// 参数：静态数据目录
+/// 数据说明：这里存的是无向边，因此每个边都得存两遍（原始数据已经特殊处理过了，因此存两遍没有重复）
+/// 返回值（由参数返回）：正向边邻接表和反向边邻接表
+fn load_static<P: AsRef<Path>>(path: P, threads: usize, adj: &mut HashMap<i64, Vec<i64>>, rev_adj: &mut HashMap<i64, Vec<i64>>) -> Result<(), LoadError> {
+    let now = Instant::now();
+    let cnt = read_part_dir(path, threads, |src_id, dst_id| {
+        adj.entry(src_id).or_default().push(dst_id);
+        adj.entry(dst_id).or_default().push(src_id);
+        rev_adj.entry(src_id).or_default().push(dst_id);
+        rev_adj.entry(dst_id).or_default().push(src_id);
+    })?;
+    println!("load static success, {} nodes, cost {:?}", cnt, now.elapsed());
+    Ok(())
+}
+
+/// 目录下的part文件，按文件名排序
+/// 只要part-开头的文件，_SUCCESS、.part-r-00000.crc这类Hadoop附带的文件都跳过
+pub fn list_part_files<P: AsRef<Path>>(path: P) -> Result<Vec<String>, LoadError> {
+    let dir = path.as_ref().to_string_lossy().into_owned();
+    let mut files: Vec<String> = fs::ls(path)
+        .map_err(|e| LoadError::io(&dir, e))?
+        .into_iter()
+        .filter(|file| {
+            let p = Path::new(file);
+            let name = p.file_name().map(|x| x.to_string_lossy().into_owned()).unwrap_or_default();
+            name.starts_with("part-") && !name.ends_with(".crc") && p.is_file()
+        })
+        .collect();
+    files.sort();
+    Ok(files)
+}
+
+/// 一个part文件读出的边和记录数
+struct PartShard {
+    edges: Vec<(i64, i64)>,
+    records: usize,
+}
+
+/// 用threads个线程读目录下的part文件，第i个文件由第i % threads个线程读，
+/// 再按文件名顺序对每条边调用f，所以调用顺序和线程数无关，返回记录数
+/// 每个文件一个容量为0的channel，一个文件和它前面的文件都读完就交给f，用完马上释放，
+/// 同时在内存里的只有每个线程手上的一个文件；某个文件读错以后，排在它后面的文件都不再读
+fn read_part_dir<P: AsRef<Path>, F: FnMut(i64, i64)>(path: P, threads: usize, mut f: F) -> Result<usize, LoadError> {
+    let now = Instant::now();
+    let files = Arc::new(list_part_files(path)?);
+    let threads = threads.max(1);
+    let (senders, receivers): (Vec<_>, Vec<_>) = (0..files.len()).map(|_| mpsc::sync_channel(0)).unzip();
+    let mut senders: Vec<Option<SyncSender<Result<PartShard, LoadError>>>> = senders.into_iter().map(Some).collect();
+    // 出错的文件里最靠前的下标
+    let first_error = Arc::new(AtomicUsize::new(usize::MAX));
+    let mut handlers = vec![];
+    for i in 0..threads {
+        let files = files.clone();
+        let first_error = first_error.clone();
+        let mine: Vec<(usize, SyncSender<Result<PartShard, LoadError>>)> = (i..files.len())
+            .step_by(threads)
+            .map(|idx| (idx, senders[idx].take().unwrap()))
+            .collect();
+        let handler = thread::spawn(move || {
+            let mut buf = ByteBuffer::new(16<<20);
+            for (idx, sender) in mine {
+                if idx > first_error.load(Ordering::SeqCst) {
+                    return;
                 }
-            }
-            let src_id = buf.get::<i64>().unwrap().to_be();
-            let count = buf.get::<i32>().unwrap().to_be();
-            for _ in 0..count {
-                if buf.remaining() < 8 {
-                    buf.compact();
-                    fc.read(&mut buf).unwrap();
-                    buf.flip();
+                let file = &files[idx];
+                let cur = Instant::now();
+                let mut edges = Vec::new();
+                let ret = read_part_file(file.as_str(), &mut buf, &mut |u, v| edges.push((u, v)))
+                    .map(|records| PartShard { edges, records })
+                    .map_err(|e| e.with_path(file));
+                match &ret {
+                    Ok(shard) => println!("load {}: {} nodes, {} edges, cost {:?}", file, shard.records, shard.edges.len(), cur.elapsed()),
+                    Err(_) => { first_error.fetch_min(idx, Ordering::SeqCst); }
+                }
+                // 读的一方已经因为前面的错误退出了
+                if sender.send(ret).is_err() {
+                    return;
                 }
-                let dst_id = buf.get::<i64>().unwrap().to_be();
-                adj.entry(src_id).or_insert(Vec::new()).push(dst_id);
-                rev_adj.entry(dst_id).or_insert(Vec::new()).push(src_id);
             }
-            if !buf.has_remaining() {
-                buf.clear();
-                let size = fc.read(&mut buf).unwrap();
-                buf.flip();
-                if size == 0 {
-                    break;
+        });
+        handlers.push(handler);
+    }
+
+    let mut cnt = 0;
+    let mut ret = Ok(());
+    for receiver in receivers {
+        match receiver.recv() {
+            Ok(Ok(shard)) => {
+                for (u, v) in shard.edges {
+                    f(u, v);
                 }
+                cnt += shard.records;
             }
-            buf.get::<i8>().unwrap();
-            cnt += 1;
-            if cnt % 1000000 == 0{
-                println!("load {} nodes, cost {:?}", cnt, now.elapsed());
+            Ok(Err(e)) => {
+                ret = Err(e);
+                break;
             }
+            // 负责这个文件的线程panic了，下面join时报错
+            Err(_) => break,
         }
     }
-
-    println!("load dynamic success, cost {:?}", now.elapsed());
+    // 剩下的receiver已经释放，阻塞在send上的线程会退出
+    for handler in handlers {
+        handler.join().map_err(|_| LoadError::new("thread reading part files panicked".to_owned()))?;
+    }
+    ret?;
+    println!("load {} files, {} nodes, cost {:?}", files.len(), cnt, now.elapsed());
+    Ok(cnt)
 }
 
-/// 参数：静态数据目录
-/// 数据说明：这里存的是无向边，因此每个边都得存两遍（原始数据已经特殊处理过了，因此存两遍没有重复）
-/// 返回值（由参数返回）：正向边邻接表和反向边邻接表
-fn load_static<P: AsRef<Path>>(path: P, adj: &mut HashMap<i64, Vec<i64>>, rev_adj: &mut HashMap<i64, Vec<i64>>) {
-    let now = Instant::now();
-    let mut buf = ByteBuffer::new(128<<20);
-    let files = fs::ls(path).unwrap();
+/// 读一个part文件，返回记录数
+fn read_part_file<F: FnMut(i64, i64)>(file: &str, buf: &mut ByteBuffer, f: &mut F) -> Result<usize, LoadError> {
+    buf.clear();
+    let mut fc = FileChannel::open(file)?;
+    fc.read(buf)?;
+    buf.flip();
     let mut cnt = 0;
-    for file in files {
-        buf.clear();
-        let mut fc = FileChannel::open(file);
-        fc.read(&mut buf).unwrap();
-        buf.flip();
-        loop {
-            if buf.remaining() < 4 + 8 {
-                buf.compact();
-                let size = fc.read(&mut buf).unwrap();
-                buf.flip();
-                if size == 0 {
-                    break;
-                }
-            }
-            let src_id = buf.get::<i64>().unwrap().to_be();
-            let count = buf.get::<i32>().unwrap().to_be();
-            for _ in 0..count {
-                if buf.remaining() < 8 {
-                    buf.compact();
-                    fc.read(&mut buf).unwrap();
-                    buf.flip();
+    loop {
+        if buf.remaining() < 4 + 8 {
+            buf.compact();
+            let size = fc.read(buf)?;
+            buf.flip();
+            if size == 0 {
+                if buf.has_remaining() {
+                    return Err(LoadError::format(format!("{} trailing bytes after record {}", buf.remaining(), cnt)));
                 }
-                let dst_id = buf.get::<i64>().unwrap().to_be();
-                adj.entry(src_id).or_insert(Vec::new()).push(dst_id);
-                adj.entry(dst_id).or_insert(Vec::new()).push(src_id);
-                rev_adj.entry(src_id).or_insert(Vec::new()).push(dst_id);
-                rev_adj.entry(dst_id).or_insert(Vec::new()).push(src_id);
+                break;
             }
-            if !buf.has_remaining() {
-                buf.clear();
-                let size = fc.read(&mut buf).unwrap();
+        }
+        let src_id = buf.get_be::<i64>()?;
+        let count = buf.get_be::<i32>()?;
+        for _ in 0..count {
+            if buf.remaining() < 8 {
+                buf.compact();
+                fc.read(buf)?;
                 buf.flip();
-                if size == 0 {
-                    break;
-                }
             }
-            buf.get::<i8>().unwrap();
-            cnt += 1;
-            if cnt % 1000000 == 0 {
-                println!("load {} nodes, cost {:?}", cnt, now.elapsed());
+            let dst_id = buf.get_be::<i64>().map_err(|_| {
+                LoadError::format(format!("record {} of {} is truncated", cnt, src_id))
+            })?;
+            f(src_id, dst_id);
+        }
+        if !buf.has_remaining() {
+            buf.clear();
+            let size = fc.read(buf)?;
+            buf.flip();
+            if size == 0 {
+                break;
             }
         }
+        buf.get::<i8>()?;
+        cnt += 1;
     }
-
-    println!("load static success, cost {:?}", now.elapsed());
+    Ok(cnt)
 }
 
 #[cfg(test)]
@@ -133,7 +210,7 @@ mod tests {
 //        load("/Users/wubincen/project/rust/dmt_paper/utils/part-r-00000");
         let mut adj = HashMap::new();
         let mut rev_adj = HashMap::new();
-        load_dynamic(test_dir, &mut adj, &mut rev_adj);
+        load_dynamic(test_dir, PART_THREADS, &mut adj, &mut rev_adj).unwrap();
     }
 
     #[ignore]
@@ -142,6 +219,95 @@ mod tests {
         let test_dir = "test_data";
         let mut adj = HashMap::new();
         let mut rev_adj = HashMap::new();
-        load_static(test_dir, &mut adj, &mut rev_adj);
+        load_static(test_dir, PART_THREADS, &mut adj, &mut rev_adj).unwrap();
+    }
+
+    /// 按part文件格式写records，返回写出的字节
+    fn write_part(file: &str, records: &[(i64, Vec<i64>)]) -> Vec<u8> {
+        let mut buf = ByteBuffer::new(1 << 16);
+        for (src, dsts) in records.iter() {
+            buf.put_be(*src).unwrap();
+            buf.put_be(dsts.len() as i32).unwrap();
+            for d in dsts.iter() {
+                buf.put_be(*d).unwrap();
+            }
+            buf.put(b'\n').unwrap();
+        }
+        let len = buf.position();
+        let data = buf.array()[..len].to_vec();
+        std::fs::write(file, &data).unwrap();
+        data
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_read_part_file() {
+        let dir = "test_dir_part_file";
+        fs::mkdir(dir).unwrap();
+        let file = fs::create_path(&[dir, "part-r-00000"]);
+        let data = write_part(file.as_str(), &[(1, vec![2, 3]), (2, vec![1])]);
+        let len = data.len();
+
+        let mut edges = Vec::new();
+        assert_eq!(read_part_dir(dir, 2, |u, v| edges.push((u, v))).unwrap(), 2);
+        assert_eq!(edges, vec![(1, 2), (1, 3), (2, 1)]);
+
+        std::fs::write(file.as_str(), &data[..len - 5]).unwrap();
+        let err = read_part_dir(dir, 2, |_, _| {}).unwrap_err();
+        assert!(err.to_string().starts_with(file.as_str()), "{}", err);
+
+        fs::rmr(dir).unwrap();
+        assert!(read_part_dir(dir, 2, |_, _| {}).unwrap_err().is_io(std::io::ErrorKind::NotFound));
+    }
+
+    #[test]
+    fn test_read_part_dir_parallel() {
+        let dir = "test_dir_part_dir";
+        let dyn_dir = fs::create_path(&[dir, "dynamic"]);
+        let static_dir = fs::create_path(&[dir, "static"]);
+        fs::mkdir(fs::create_path(&[dyn_dir.as_str(), "part-r-99999"])).unwrap();
+        fs::mkdir(static_dir.as_str()).unwrap();
+        for i in 0..12i64 {
+            let name = format!("part-r-{:05}", i);
+            let records: Vec<(i64, Vec<i64>)> = (0..i * 7).map(|x| (x % 13, vec![i, x])).collect();
+            write_part(fs::create_path(&[dyn_dir.as_str(), name.as_str()]).as_str(), &records);
+            write_part(fs::create_path(&[static_dir.as_str(), name.as_str()]).as_str(), &records[..records.len() / 2]);
+        }
+        fs::touch(fs::create_path(&[dyn_dir.as_str(), "_SUCCESS"])).unwrap();
+        std::fs::write(fs::create_path(&[dyn_dir.as_str(), ".part-r-00000.crc"]), b"xx").unwrap();
+        std::fs::write(fs::create_path(&[dyn_dir.as_str(), "part-r-00001.crc"]), b"xx").unwrap();
+
+        let files = list_part_files(dyn_dir.as_str()).unwrap();
+        assert_eq!(files.len(), 12);
+        assert!(files[0].ends_with("part-r-00000") && files[11].ends_with("part-r-00011"));
+
+        // 边按文件名顺序回调，和线程数无关
+        let expected: Vec<(i64, i64)> = (0..12i64)
+            .flat_map(|i| (0..i * 7).flat_map(move |x| vec![(x % 13, i), (x % 13, x)]))
+            .collect();
+        for threads in [1, 2, 4, 5, 20].iter() {
+            let mut edges = vec![];
+            let cnt = read_part_dir(dyn_dir.as_str(), *threads, |u, v| edges.push((u, v))).unwrap();
+            assert_eq!(cnt, expected.len() / 2);
+            assert_eq!(edges, expected);
+        }
+        assert_eq!(load_parallel(dyn_dir.as_str(), static_dir.as_str(), 1).unwrap(),
+                   load_parallel(dyn_dir.as_str(), static_dir.as_str(), 4).unwrap());
+
+        // 报错的是排在最前面的坏文件
+        for name in ["part-r-00003", "part-r-00007"].iter() {
+            let file = fs::create_path(&[dyn_dir.as_str(), name]);
+            let data = std::fs::read(file.as_str()).unwrap();
+            std::fs::write(file.as_str(), &data[..data.len() - 3]).unwrap();
+        }
+        // 报错之前，坏文件前面的文件已经按顺序交给了f
+        // 前三个文件有0 + 7 + 14条记录，每条两条边
+        let before = &expected[..2 * 21];
+        for threads in [1, 4].iter() {
+            let mut edges = vec![];
+            let err = read_part_dir(dyn_dir.as_str(), *threads, |u, v| edges.push((u, v))).unwrap_err();
+            assert!(err.to_string().contains("part-r-00003"), "{}", err);
+            assert_eq!(edges, before);
+        }
+        fs::rmr(dir).unwrap();
+    }
+}
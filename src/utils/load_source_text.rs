@@ -1,60 +1,92 @@
-use std::fs::File;
-use std::io::BufReader;
-use std::io::prelude::*;
-use crate::structure::{Graph, AdjacentList};
+use crate::structure::{Graph, AdjacentList, EdgeLabels};
+use crate::structure::edge_label::{STATIC_LABEL, DYNAMIC_LABEL};
+use super::text_format::{TextFormat, LoadStats};
 
 pub fn load_data(static_path: String, dynamic_path: String) -> Result<Graph, LoadError> {
+    load_data_with(static_path, dynamic_path, &TextFormat::default())
+}
+
+/// 按format读静态边和动态边
+pub fn load_data_with(static_path: String, dynamic_path: String, format: &TextFormat) -> Result<Graph, LoadError> {
+    load_data_with_stats(static_path, dynamic_path, format).map(|(graph, _)| graph)
+}
+
+/// 和load_data_with一样，另外返回两个文件的统计，宽松模式跳过的坏行都在里面
+pub fn load_data_with_stats(static_path: String, dynamic_path: String, format: &TextFormat) -> Result<(Graph, LoadStats), LoadError> {
     let mut graph = Graph::new(AdjacentList::new(), AdjacentList::new());
-    let f = File::open(static_path)?;
     println!("start to load static data");
-    let reader = BufReader::new(f);
-    for line in reader.lines() {
-        let l = line?;
-        let items: Vec<&str> = l.split_whitespace().collect();
-        let src_id = items[0].parse()?;
-        let dst_id = items[1].parse()?;
-        graph.add_undirected_edge(src_id, dst_id);
-    }
+    let static_file = format.for_each_edge(static_path.as_str(), |e| graph.add_undirected_edge(e.src, e.dst))?;
     println!("start to load dynamic data");
-    let f = File::open(dynamic_path)?;
-    let reader = BufReader::new(f);
-    for line in reader.lines() {
-        let l = line?;
-        let items: Vec<&str> = l.split_whitespace().collect();
-        let src_id = items[0].parse()?;
-        let dst_id = items[1].parse()?;
-        graph.add_directed_edge(src_id, dst_id);
-    }
+    let dynamic_file = format.for_each_edge(dynamic_path.as_str(), |e| graph.add_directed_edge(e.src, e.dst))?;
     println!("load data success");
-    Ok(graph)
+    Ok((graph, LoadStats { static_file, dynamic_file }))
 }
 
-#[derive(Debug, Clone)]
-pub struct LoadError {
-    err_msg: String,
+/// 和load_data一样，另外给静态边打上static标签、动态边打上dynamic标签
+/// 动态边和静态边重复时两条都留着，各自带自己的标签
+pub fn load_labeled_data(static_path: String, dynamic_path: String) -> Result<(Graph, EdgeLabels), LoadError> {
+    let mut graph = Graph::new(AdjacentList::new(), AdjacentList::new());
+    let mut labels = EdgeLabels::new();
+    println!("start to load static data");
+    let format = TextFormat::default();
+    format.for_each_edge(static_path.as_str(), |e| {
+        graph.add_undirected_edge(e.src, e.dst);
+        labels.add_undirected_label(e.src, e.dst, STATIC_LABEL);
+    })?;
+    println!("start to load dynamic data");
+    format.for_each_edge(dynamic_path.as_str(), |e| {
+        graph.add_directed_edge(e.src, e.dst);
+        labels.add_label(e.src, e.dst, DYNAMIC_LABEL);
+    })?;
+    println!("load data success");
+    Ok((graph, labels))
 }
 
-impl LoadError {
-    pub fn new(err_msg: String) -> Self {
-        LoadError {
-            err_msg,
-        }
-    }
-}
+/// 加载出错，和整个crate共用一个错误类型
+pub use crate::common::error::Error as LoadError;
 
-impl From<::std::io::Error> for LoadError {
-    fn from(err: ::std::io::Error) -> Self {
-        LoadError {
-            err_msg: format!("{:?}", err),
-        }
-    }
-}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::io::fs;
+
+    #[test]
+    fn test_load_data() {
+        let dir = "test_dir_load_text";
+        fs::mkdir(dir).unwrap();
+        let static_path = fs::create_path(&[dir, "static"]);
+        let dyn_path = fs::create_path(&[dir, "dynamic"]);
+        std::fs::write(static_path.as_str(), "# static edges\n1 2\n\n").unwrap();
+        std::fs::write(dyn_path.as_str(), "1 3\n").unwrap();
+        assert_eq!(load_data(static_path.clone(), dyn_path.clone()).unwrap(), Graph::test());
 
-impl From<::std::num::ParseIntError> for LoadError {
-    fn from(err: ::std::num::ParseIntError) -> Self {
-        LoadError {
-            err_msg: format!("{:?}", err),
-        }
+        std::fs::write(dyn_path.as_str(), "1 3\n2 x\n").unwrap();
+        let err = load_data(static_path.clone(), dyn_path.clone()).unwrap_err();
+        assert_eq!(err.to_string(), format!("{}:2: invalid id \"x\": invalid digit found in string", dyn_path));
+        std::fs::write(dyn_path.as_str(), "1\n").unwrap();
+        let err = load_labeled_data(static_path.clone(), dyn_path.clone()).unwrap_err();
+        assert!(matches!(err, LoadError::Parse { line: 1, .. }));
+
+        let format = TextFormat::default().lenient();
+        let mut expected = Graph::empty();
+        expected.add_undirected_edge(1, 2);
+        assert_eq!(load_data_with(static_path.clone(), dyn_path.clone(), &format).unwrap(), expected);
+        let (graph, stats) = load_data_with_stats(static_path.clone(), dyn_path.clone(), &format).unwrap();
+        assert_eq!(graph, expected);
+        assert_eq!((stats.static_file.edges, stats.dynamic_file.edges, stats.skipped()), (1, 0, 1));
+        assert!(matches!(stats.dynamic_file.errors[0], LoadError::Parse { line: 1, .. }));
+
+        // 动态边1->2和静态边1-2重复，静态边的标签不会被覆盖
+        std::fs::write(dyn_path.as_str(), "1 2\n").unwrap();
+        let (graph, labels) = load_labeled_data(static_path.clone(), dyn_path.clone()).unwrap();
+        assert_eq!(graph.adj[&1], vec![2, 2]);
+        assert_eq!(labels.label(1, 2), Some(STATIC_LABEL));
+        assert_eq!(labels.label_ids(1, 2).len(), 2);
+        assert_eq!(labels.label(2, 1), Some(STATIC_LABEL));
+        assert_eq!(labels.len(), 3);
+
+        fs::rmr(dir).unwrap();
+        let err = load_data(static_path, dyn_path).unwrap_err();
+        assert!(err.is_io(std::io::ErrorKind::NotFound));
     }
 }
-
@@ -1,6 +1,13 @@
 pub mod build_binary;
 pub mod load_source_text;
+pub mod text_format;
+pub mod parallel_text;
+pub mod import;
+pub mod export;
 pub mod query;
 pub mod load_source_binary;
 pub mod build_source_binary_text;
-pub mod load_binary;
\ No newline at end of file
+pub mod load_binary;
+pub mod binary_format;
+pub mod path_writer;
+pub mod update_log;
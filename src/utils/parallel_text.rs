@@ -0,0 +1,265 @@
+//! 多线程读文本边表：按字节把文件切成若干段，段边界对齐到行首，每个线程解析一段到自己的边缓冲，
+//! 最后按段的顺序合并，所以结果和TextFormat::for_each_edge逐行读出来的完全一样
+
+use std::fs::File;
+use std::io::{BufReader, SeekFrom};
+use std::io::prelude::*;
+use std::sync::Arc;
+use std::thread;
+use std::time::Instant;
+use crate::common::error::{Error, Result};
+use crate::structure::{Graph, AdjacentList, CsrGraph};
+use super::text_format::{TextFormat, ReadStats, LoadStats, MAX_REPORTED_ERRORS};
+
+/// 一段的解析结果，行号从这一段开头算起
+struct Chunk {
+    edges: Vec<(i64, i64)>,
+    stats: ReadStats,
+}
+
+/// 把path跳过skip_lines行表头之后的部分切成最多parts段[start, end)，每段都从行首开始，空段不返回
+pub fn split_ranges(path: &str, skip_lines: usize, parts: usize) -> Result<Vec<(u64, u64)>> {
+    let file = File::open(path).map_err(|e| Error::io(path, e))?;
+    let len = file.metadata().map_err(|e| Error::io(path, e))?.len();
+    let mut reader = BufReader::new(file);
+    let mut line = Vec::new();
+    let mut begin = 0;
+    for _ in 0..skip_lines {
+        line.clear();
+        begin += reader.read_until(b'\n', &mut line).map_err(|e| Error::io(path, e))? as u64;
+    }
+    let parts = parts.max(1) as u64;
+    let mut ranges = Vec::new();
+    let mut start = begin;
+    for i in 1..=parts {
+        let mut end = begin + (len - begin) * i / parts;
+        if end < len && end > start {
+            // 往后找到下一个换行，end落在它后面
+            reader.seek(SeekFrom::Start(end - 1)).map_err(|e| Error::io(path, e))?;
+            line.clear();
+            end = end - 1 + reader.read_until(b'\n', &mut line).map_err(|e| Error::io(path, e))? as u64;
+        }
+        let end = end.max(start);
+        if end > start {
+            ranges.push((start, end));
+        }
+        start = end;
+    }
+    Ok(ranges)
+}
+
+fn read_chunk(path: &str, format: &TextFormat, start: u64, end: u64) -> Result<Chunk> {
+    let mut file = File::open(path).map_err(|e| Error::io(path, e))?;
+    file.seek(SeekFrom::Start(start)).map_err(|e| Error::io(path, e))?;
+    let mut data = vec![0; (end - start) as usize];
+    file.read_exact(&mut data).map_err(|e| Error::io(path, e))?;
+    if data.last() == Some(&b'\n') {
+        data.pop();
+    }
+    let mut chunk = Chunk { edges: Vec::new(), stats: ReadStats::default() };
+    for (idx, l) in data.split(|b| *b == b'\n').enumerate() {
+        chunk.stats.lines += 1;
+        let l = l.strip_suffix(b"\r").unwrap_or(l);
+        let ret = std::str::from_utf8(l)
+            .map_err(|_| Error::parse(idx + 1, "stream did not contain valid UTF-8"))
+            .and_then(|l| format.parse_line(l, idx + 1));
+        match ret {
+            Ok(Some(edge)) => {
+                chunk.stats.edges += 1;
+                chunk.edges.push((edge.src, edge.dst));
+            }
+            Ok(None) => {}
+            Err(e) if format.strict => return Err(e),
+            Err(e) => {
+                chunk.stats.skipped += 1;
+                if chunk.stats.errors.len() < MAX_REPORTED_ERRORS {
+                    chunk.stats.errors.push(e);
+                }
+            }
+        }
+    }
+    Ok(chunk)
+}
+
+/// 段内行号加上前面的行数，变成文件里的行号
+fn shift_line(e: Error, base: usize, path: &str) -> Error {
+    match e {
+        Error::Parse { path: None, line, msg } => Error::parse(line + base, msg).with_path(path),
+        e => e.with_path(path),
+    }
+}
+
+/// 用threads个线程读path里的边，按文件里的顺序返回
+/// 严格模式返回文件里第一行坏数据的错误，宽松模式的坏行记在ReadStats里
+pub fn read_edges_parallel(path: &str, format: &TextFormat, threads: usize) -> Result<(Vec<(i64, i64)>, ReadStats)> {
+    let cur = Instant::now();
+    let ranges = split_ranges(path, format.skip_lines, threads)?;
+    let format = Arc::new(format.clone());
+    let mut handlers = vec![];
+    for (start, end) in ranges {
+        let format = format.clone();
+        let path = path.to_owned();
+        let handler = thread::spawn(move || read_chunk(&path, &format, start, end));
+        handlers.push(handler);
+    }
+
+    let mut edges = Vec::new();
+    let mut stats = ReadStats { lines: format.skip_lines, ..Default::default() };
+    let mut first_err = None;
+    for handler in handlers {
+        let ret = handler.join().map_err(|_| Error::new(format!("thread reading {} panicked", path)))?;
+        match ret {
+            Ok(chunk) if first_err.is_none() => {
+                let base = stats.lines;
+                edges.extend(chunk.edges);
+                stats.lines += chunk.stats.lines;
+                stats.edges += chunk.stats.edges;
+                stats.skipped += chunk.stats.skipped;
+                for e in chunk.stats.errors {
+                    if stats.errors.len() < MAX_REPORTED_ERRORS {
+                        stats.errors.push(shift_line(e, base, path));
+                    }
+                }
+            }
+            Ok(_) => {}
+            Err(e) => if first_err.is_none() {
+                first_err = Some(shift_line(e, stats.lines, path));
+            }
+        }
+    }
+    if let Some(e) = first_err {
+        return Err(e);
+    }
+    if stats.skipped > 0 {
+        println!("skip {} bad lines in {}", stats.skipped, path);
+    }
+    println!("read {} edges from {} with {} threads, cost {:?}", edges.len(), path, threads, cur.elapsed());
+    Ok((edges, stats))
+}
+
+/// load_data_with的多线程版本，结果和它相同
+pub fn load_data_parallel(static_path: String, dynamic_path: String, format: &TextFormat, threads: usize) -> Result<Graph> {
+    load_data_parallel_with_stats(static_path, dynamic_path, format, threads).map(|(graph, _)| graph)
+}
+
+/// load_data_with_stats的多线程版本，结果和统计都和它相同
+pub fn load_data_parallel_with_stats(static_path: String, dynamic_path: String, format: &TextFormat, threads: usize) -> Result<(Graph, LoadStats)> {
+    let mut graph = Graph::new(AdjacentList::new(), AdjacentList::new());
+    println!("start to load static data");
+    let (edges, static_file) = read_edges_parallel(static_path.as_str(), format, threads)?;
+    for (u, v) in edges {
+        graph.add_undirected_edge(u, v);
+    }
+    println!("start to load dynamic data");
+    let (edges, dynamic_file) = read_edges_parallel(dynamic_path.as_str(), format, threads)?;
+    for (u, v) in edges {
+        graph.add_directed_edge(u, v);
+    }
+    println!("load data success");
+    Ok((graph, LoadStats { static_file, dynamic_file }))
+}
+
+/// 多线程读静态边和动态边直接建CSR，不经过Graph，
+/// 结果和CsrGraph::from_graph(&load_data_with(..))相同
+pub fn load_csr_parallel(static_path: String, dynamic_path: String, format: &TextFormat, threads: usize) -> Result<CsrGraph> {
+    load_csr_parallel_with_stats(static_path, dynamic_path, format, threads).map(|(graph, _)| graph)
+}
+
+/// 和load_csr_parallel一样，另外返回两个文件的统计
+pub fn load_csr_parallel_with_stats(static_path: String, dynamic_path: String, format: &TextFormat, threads: usize) -> Result<(CsrGraph, LoadStats)> {
+    println!("start to load static data");
+    let (static_edges, static_file) = read_edges_parallel(static_path.as_str(), format, threads)?;
+    println!("start to load dynamic data");
+    let (dyn_edges, dynamic_file) = read_edges_parallel(dynamic_path.as_str(), format, threads)?;
+    let mut edges = Vec::with_capacity(static_edges.len() * 2 + dyn_edges.len());
+    for (u, v) in static_edges {
+        edges.push((u, v));
+        edges.push((v, u));
+    }
+    edges.extend(dyn_edges);
+    let graph = CsrGraph::from_edges(&edges);
+    println!("load data success");
+    Ok((graph, LoadStats { static_file, dynamic_file }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::io::fs;
+    use crate::utils::load_source_text::{load_data_with, load_data_with_stats};
+
+    fn write_edges(path: &str, n: i64, tail: &str) {
+        let mut s = String::from("# generated\n");
+        for i in 0..n {
+            s.push_str(&format!("{}\t{}\r\n", i % 97, (i * 31) % 101));
+            if i % 10 == 0 {
+                s.push('\n');
+            }
+        }
+        s.push_str(tail);
+        std::fs::write(path, s).unwrap();
+    }
+
+    #[test]
+    fn test_split_ranges() {
+        let dir = "test_dir_parallel_text_split";
+        fs::mkdir(dir).unwrap();
+        let path = fs::create_path(&[dir, "edges"]);
+        std::fs::write(path.as_str(), "a,b\n1,2\n3,4\n55,66\n7,8").unwrap();
+        for parts in 1..30 {
+            let ranges = split_ranges(path.as_str(), 1, parts).unwrap();
+            assert!(ranges.len() <= parts);
+            assert_eq!(ranges[0].0, 4);
+            assert_eq!(ranges.last().unwrap().1, 21);
+            for w in ranges.windows(2) {
+                assert_eq!(w[0].1, w[1].0);
+                assert!(w[0].0 < w[0].1);
+            }
+        }
+        assert_eq!(split_ranges(path.as_str(), 10, 4).unwrap(), vec![]);
+        fs::rmr(dir).unwrap();
+    }
+
+    #[test]
+    fn test_load_parallel() {
+        let dir = "test_dir_parallel_text";
+        fs::mkdir(dir).unwrap();
+        let static_path = fs::create_path(&[dir, "static"]);
+        let dyn_path = fs::create_path(&[dir, "dynamic"]);
+        write_edges(static_path.as_str(), 3000, "");
+        write_edges(dyn_path.as_str(), 2000, "5 6");
+        let format = TextFormat::default();
+        let graph = load_data_with(static_path.clone(), dyn_path.clone(), &format).unwrap();
+        for threads in [1, 2, 4, 7].iter() {
+            assert_eq!(load_data_parallel(static_path.clone(), dyn_path.clone(), &format, *threads).unwrap(), graph);
+            assert_eq!(load_csr_parallel(static_path.clone(), dyn_path.clone(), &format, *threads).unwrap(),
+                       CsrGraph::from_graph(&graph));
+        }
+
+        // 坏行的行号和顺序读的一样
+        write_edges(dyn_path.as_str(), 2000, "5 x\n6\n");
+        let expected = load_data_with(static_path.clone(), dyn_path.clone(), &format).unwrap_err().to_string();
+        let err = load_data_parallel(static_path.clone(), dyn_path.clone(), &format, 4).unwrap_err();
+        assert_eq!(err.to_string(), expected);
+        let format = format.lenient();
+        let mut seq = vec![];
+        let seq_stats = format.for_each_edge(dyn_path.as_str(), |e| seq.push((e.src, e.dst))).unwrap();
+        let (edges, stats) = read_edges_parallel(dyn_path.as_str(), &format, 4).unwrap();
+        assert_eq!(edges, seq);
+        assert_eq!((stats.lines, stats.edges, stats.skipped), (seq_stats.lines, seq_stats.edges, seq_stats.skipped));
+        let lines = |s: &ReadStats| s.errors.iter().map(|e| e.to_string()).collect::<Vec<_>>();
+        assert_eq!(lines(&stats), lines(&seq_stats));
+
+        // 宽松模式整图加载也带回统计
+        let (graph, seq_stats) = load_data_with_stats(static_path.clone(), dyn_path.clone(), &format).unwrap();
+        let (par_graph, stats) = load_data_parallel_with_stats(static_path.clone(), dyn_path.clone(), &format, 4).unwrap();
+        assert_eq!(par_graph, graph);
+        assert_eq!(stats.skipped(), 2);
+        assert_eq!(lines(&stats.dynamic_file), lines(&seq_stats.dynamic_file));
+        assert_eq!(stats.static_file.edges, seq_stats.static_file.edges);
+        let (csr, stats) = load_csr_parallel_with_stats(static_path.clone(), dyn_path.clone(), &format, 3).unwrap();
+        assert_eq!(csr, CsrGraph::from_graph(&graph));
+        assert_eq!(stats.skipped(), 2);
+        fs::rmr(dir).unwrap();
+    }
+}
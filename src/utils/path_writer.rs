@@ -0,0 +1,392 @@
+//! 把结果路径边算边写到文件，不用全部放在内存里
+//!
+//! PathSink写的是路径本身；ResultWriter多带一个查询id，QuerySink把dfs不含起点终点的结果补全后交给ResultWriter
+//! 二进制格式：header(12B): magic "PDFSPTH\0" | version(u32，小端)
+//! 之后每条记录：query_id(varint) | 点数(varint) | 第一个点(zigzag varint) | 之后每个点和前一个点之差(zigzag varint)
+
+use std::fs::File;
+use std::io;
+use std::io::BufWriter;
+use std::io::prelude::*;
+use std::path::Path;
+use crate::common::error::{Error, Result};
+use crate::common::io::{ByteBuffer, FileChannel, MAX_VARINT_LEN};
+use crate::structure::{GraphPath, PathSink};
+
+pub const PATH_MAGIC: [u8; 8] = *b"PDFSPTH\0";
+pub const PATH_FORMAT_VERSION: u32 = 1;
+const PATH_HEADER_SIZE: usize = 12;
+const BUFFER_SIZE: usize = 4 << 20;
+
+/// 带查询id的结果写出，path是完整路径（含起点和终点）
+/// 直接当PathSink用时查询id记为0（文本格式没有id列）
+pub trait ResultWriter: PathSink {
+    fn write_path(&mut self, query_id: u64, path: &[i64]) -> io::Result<()>;
+}
+
+/// 一次查询的PathSink：把dfs给的中间点补上起点和终点，交给writer
+pub struct QuerySink<'a, W: ResultWriter + ?Sized> {
+    writer: &'a mut W,
+    query_id: u64,
+    source: Option<i64>,
+    target: Option<i64>,
+    full: GraphPath,
+}
+
+impl<'a, W: ResultWriter + ?Sized> QuerySink<'a, W> {
+    /// dfs的结果不含起点和终点，写出时补上
+    pub fn new(writer: &'a mut W, query_id: u64, source: i64, target: i64) -> Self {
+        QuerySink { writer, query_id, source: Some(source), target: Some(target), full: GraphPath::new() }
+    }
+
+    /// 结果已经是完整路径（比如短环），原样写出
+    pub fn full(writer: &'a mut W, query_id: u64) -> Self {
+        QuerySink { writer, query_id, source: None, target: None, full: GraphPath::new() }
+    }
+}
+
+impl<'a, W: ResultWriter + ?Sized> PathSink for QuerySink<'a, W> {
+    fn push(&mut self, path: &[i64]) -> io::Result<()> {
+        self.full.clear();
+        self.full.extend(self.source.iter().chain(path.iter()).chain(self.target.iter()));
+        self.writer.write_path(self.query_id, &self.full)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+/// 一行一条路径，点之间用tab分隔；作为ResultWriter时第一列是查询id
+pub struct TextPathSink {
+    writer: BufWriter<File>,
+}
+
+impl TextPathSink {
+    pub fn create<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        Ok(TextPathSink {
+            writer: BufWriter::new(File::create(path)?),
+        })
+    }
+
+    /// 追加写，用于断点续跑
+    pub fn append<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let f = std::fs::OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(TextPathSink {
+            writer: BufWriter::new(f),
+        })
+    }
+}
+
+impl PathSink for TextPathSink {
+    fn push(&mut self, path: &[i64]) -> io::Result<()> {
+        for (i, v) in path.iter().enumerate() {
+            if i > 0 {
+                self.writer.write_all(b"\t")?;
+            }
+            write!(self.writer, "{}", v)?;
+        }
+        self.writer.write_all(b"\n")
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+impl ResultWriter for TextPathSink {
+    fn write_path(&mut self, query_id: u64, path: &[i64]) -> io::Result<()> {
+        write!(self.writer, "{}", query_id)?;
+        for v in path.iter() {
+            write!(self.writer, "\t{}", v)?;
+        }
+        self.writer.write_all(b"\n")
+    }
+}
+
+/// JSON Lines，一行一个{"query":id,"len":边数,"path":[点...]}
+pub struct JsonlPathSink {
+    writer: BufWriter<File>,
+}
+
+impl JsonlPathSink {
+    pub fn create<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        Ok(JsonlPathSink {
+            writer: BufWriter::new(File::create(path)?),
+        })
+    }
+}
+
+impl ResultWriter for JsonlPathSink {
+    fn write_path(&mut self, query_id: u64, path: &[i64]) -> io::Result<()> {
+        write!(self.writer, "{{\"query\":{},\"len\":{},\"path\":[", query_id, path.len().saturating_sub(1))?;
+        for (i, v) in path.iter().enumerate() {
+            if i > 0 {
+                self.writer.write_all(b",")?;
+            }
+            write!(self.writer, "{}", v)?;
+        }
+        self.writer.write_all(b"]}\n")
+    }
+}
+
+impl PathSink for JsonlPathSink {
+    fn push(&mut self, path: &[i64]) -> io::Result<()> {
+        self.write_path(0, path)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+/// 紧凑的二进制结果文件，格式见文件开头，drop时会把缓冲里的写出去
+pub struct BinaryPathSink {
+    fc: FileChannel,
+    buf: ByteBuffer,
+}
+
+impl BinaryPathSink {
+    pub fn create<P: AsRef<Path>>(path: P) -> Result<Self> {
+        Self::with_capacity(path, BUFFER_SIZE)
+    }
+
+    /// 写缓冲是capacity字节，最长的一条记录要放得下
+    pub fn with_capacity<P: AsRef<Path>>(path: P, capacity: usize) -> Result<Self> {
+        let fc = FileChannel::create(path)?;
+        let mut buf = ByteBuffer::new(capacity.max(PATH_HEADER_SIZE));
+        buf.put(PATH_MAGIC)?;
+        buf.put_le(PATH_FORMAT_VERSION)?;
+        Ok(BinaryPathSink { fc, buf })
+    }
+
+    fn write_buf(&mut self) -> Result<()> {
+        self.buf.flip();
+        self.fc.write(&mut self.buf)
+    }
+}
+
+impl ResultWriter for BinaryPathSink {
+    fn write_path(&mut self, query_id: u64, path: &[i64]) -> io::Result<()> {
+        let need = MAX_VARINT_LEN * (path.len() + 2);
+        if need > self.buf.capacity() {
+            return Err(Error::new(format!("path of {} vertices is too long", path.len())).into());
+        }
+        if self.buf.remaining() < need {
+            self.write_buf()?;
+        }
+        self.buf.put_varint(query_id)?;
+        self.buf.put_varint(path.len() as u64)?;
+        let mut prev = 0i64;
+        for v in path.iter() {
+            self.buf.put_signed_varint(v.wrapping_sub(prev))?;
+            prev = *v;
+        }
+        Ok(())
+    }
+}
+
+impl PathSink for BinaryPathSink {
+    fn push(&mut self, path: &[i64]) -> io::Result<()> {
+        self.write_path(0, path)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(self.write_buf()?)
+    }
+}
+
+impl Drop for BinaryPathSink {
+    fn drop(&mut self) {
+        let _ = self.write_buf();
+    }
+}
+
+/// 读BinaryPathSink写的文件，逐条返回(query_id, 完整路径)
+pub struct BinaryPathReader {
+    fc: FileChannel,
+    buf: ByteBuffer,
+    eof: bool,
+    records: usize,
+}
+
+impl BinaryPathReader {
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self> {
+        Self::with_capacity(path, BUFFER_SIZE)
+    }
+
+    /// 读缓冲是capacity字节，比写出时的缓冲小的话长记录可能读不出来
+    pub fn with_capacity<P: AsRef<Path>>(path: P, capacity: usize) -> Result<Self> {
+        let fc = FileChannel::open(path)?;
+        let buf = ByteBuffer::new(capacity.max(PATH_HEADER_SIZE));
+        let mut reader = BinaryPathReader { fc, buf, eof: false, records: 0 };
+        reader.buf.flip();
+        reader.fill().map_err(|e| e.with_path(reader.fc.filename()))?;
+        let path = reader.fc.filename().to_owned();
+        if reader.buf.remaining() < PATH_HEADER_SIZE {
+            return Err(Error::format("unexpected end of file in header").with_path(&path));
+        }
+        if reader.buf.get::<[u8; 8]>()? != PATH_MAGIC {
+            return Err(Error::format("bad magic, not a binary path file").with_path(&path));
+        }
+        let version = reader.buf.get_le::<u32>()?;
+        if version != PATH_FORMAT_VERSION {
+            return Err(Error::format(format!(
+                "unsupported format version {}, expected {}", version, PATH_FORMAT_VERSION)).with_path(&path));
+        }
+        Ok(reader)
+    }
+
+    /// 把buf读满或者读到文件末尾
+    fn fill(&mut self) -> Result<()> {
+        self.buf.compact();
+        while self.buf.position() < self.buf.capacity() {
+            if self.fc.read(&mut self.buf)? == 0 {
+                self.eof = true;
+                break;
+            }
+        }
+        self.buf.flip();
+        Ok(())
+    }
+
+    fn get_record(&mut self) -> Result<(u64, GraphPath)> {
+        let query_id = self.buf.get_varint()?;
+        let count = self.buf.get_varint()? as usize;
+        if count > self.buf.remaining() {
+            return Err(Error::Buffer { need: count, remaining: self.buf.remaining() });
+        }
+        let mut path = Vec::with_capacity(count);
+        let mut prev = 0i64;
+        for _ in 0..count {
+            prev = prev.wrapping_add(self.buf.get_signed_varint()?);
+            path.push(prev);
+        }
+        Ok((query_id, path))
+    }
+
+    /// 下一条记录，读完返回None
+    pub fn read_path(&mut self) -> Result<Option<(u64, GraphPath)>> {
+        loop {
+            if !self.buf.has_remaining() && self.eof {
+                return Ok(None);
+            }
+            let start = self.buf.position();
+            match self.get_record() {
+                Ok(x) => {
+                    self.records += 1;
+                    return Ok(Some(x));
+                }
+                Err(Error::Buffer { .. }) if self.eof => {
+                    return Err(Error::format(format!("record {} is truncated", self.records))
+                        .with_path(self.fc.filename()));
+                }
+                Err(Error::Buffer { .. }) if start == 0 && self.buf.limit() == self.buf.capacity() => {
+                    return Err(Error::format(format!("record {} is larger than the read buffer", self.records))
+                        .with_path(self.fc.filename()));
+                }
+                Err(Error::Buffer { .. }) => {
+                    // 记录跨过了buf的末尾，回到记录开头再多读一些
+                    self.buf.set_position(start);
+                    self.fill().map_err(|e| e.with_path(self.fc.filename()))?;
+                }
+                Err(e) => return Err(e.with_path(self.fc.filename())),
+            }
+        }
+    }
+}
+
+impl Iterator for BinaryPathReader {
+    type Item = Result<(u64, GraphPath)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.read_path().transpose()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::io::fs;
+
+    #[test]
+    fn test_text_path_sink() {
+        let test_dir = "test_dir_path_writer";
+        fs::mkdir(test_dir).unwrap();
+        let file = fs::create_path(&[test_dir, "paths"]);
+        let mut sink = TextPathSink::create(file.as_str()).unwrap();
+        sink.push(&[1, 2, 3]).unwrap();
+        sink.flush().unwrap();
+        let mut sink = TextPathSink::append(file.as_str()).unwrap();
+        sink.push(&[4]).unwrap();
+        sink.flush().unwrap();
+        assert_eq!(std::fs::read_to_string(file.as_str()).unwrap(), "1\t2\t3\n4\n");
+        fs::rmr(test_dir).unwrap();
+    }
+
+    #[test]
+    fn test_query_sinks() {
+        let test_dir = "test_dir_path_writer_query";
+        fs::mkdir(test_dir).unwrap();
+        let text = fs::create_path(&[test_dir, "paths.tsv"]);
+        let jsonl = fs::create_path(&[test_dir, "paths.jsonl"]);
+        let mut text_sink = TextPathSink::create(text.as_str()).unwrap();
+        let mut jsonl_sink = JsonlPathSink::create(jsonl.as_str()).unwrap();
+        {
+            let writers: [&mut dyn ResultWriter; 2] = [&mut text_sink, &mut jsonl_sink];
+            for w in writers {
+                let mut sink = QuerySink::new(w, 7, 1, 4);
+                sink.push(&[2, 3]).unwrap();
+                sink.push(&[]).unwrap();
+                let mut sink = QuerySink::full(sink.writer, 8);
+                sink.push(&[-5, 6, -5]).unwrap();
+                sink.flush().unwrap();
+            }
+        }
+        assert_eq!(std::fs::read_to_string(text.as_str()).unwrap(), "7\t1\t2\t3\t4\n7\t1\t4\n8\t-5\t6\t-5\n");
+        assert_eq!(std::fs::read_to_string(jsonl.as_str()).unwrap(), "\
+{\"query\":7,\"len\":3,\"path\":[1,2,3,4]}
+{\"query\":7,\"len\":1,\"path\":[1,4]}
+{\"query\":8,\"len\":2,\"path\":[-5,6,-5]}
+");
+        fs::rmr(test_dir).unwrap();
+    }
+
+    #[test]
+    fn test_binary_path_sink() {
+        let test_dir = "test_dir_path_writer_binary";
+        fs::mkdir(test_dir).unwrap();
+        let file = fs::create_path(&[test_dir, "paths.bin"]);
+        // 写满好几次缓冲，检查跨缓冲的记录
+        let expected: Vec<(u64, GraphPath)> = (0..300000u64)
+            .map(|i| (i / 7, (0..(i % 9) as i64).map(|x| x * 1000003 - i as i64).collect()))
+            .chain(std::iter::once((u64::MAX, vec![i64::MIN, i64::MAX, 0])))
+            .collect();
+        {
+            let mut sink = BinaryPathSink::create(file.as_str()).unwrap();
+            for (id, path) in expected.iter() {
+                sink.write_path(*id, path).unwrap();
+            }
+        }
+        let len = std::fs::metadata(file.as_str()).unwrap().len() as usize;
+        assert!(len > BUFFER_SIZE, "{}", len);
+        let paths: Vec<(u64, GraphPath)> = BinaryPathReader::open(file.as_str()).unwrap().map(|x| x.unwrap()).collect();
+        assert_eq!(paths, expected);
+
+        let mut sink = BinaryPathSink::create(file.as_str()).unwrap();
+        sink.push(&[1, 2]).unwrap();
+        sink.flush().unwrap();
+        let mut reader = BinaryPathReader::open(file.as_str()).unwrap();
+        assert_eq!(reader.read_path().unwrap(), Some((0, vec![1, 2])));
+        assert_eq!(reader.read_path().unwrap(), None);
+
+        let data = std::fs::read(file.as_str()).unwrap();
+        std::fs::write(file.as_str(), &data[..data.len() - 1]).unwrap();
+        let err = BinaryPathReader::open(file.as_str()).unwrap().next().unwrap().unwrap_err();
+        assert_eq!(err.to_string(), format!("{}: record 0 is truncated", file));
+        std::fs::write(file.as_str(), b"PDFSADJ\0\x01\0\0\0").unwrap();
+        let err = BinaryPathReader::open(file.as_str()).err().unwrap();
+        assert!(err.to_string().contains("bad magic"));
+        fs::rmr(test_dir).unwrap();
+    }
+}
@@ -1,41 +1,35 @@
 use std::fs::File;
 use std::io::BufReader;
 use std::io::prelude::*;
+use super::load_source_text::LoadError;
 
-pub fn load_query(path: &str, limit: u32) -> Vec<(i64, i64)> {
-    let f = File::open(path).unwrap();
-    let reader = BufReader::new(f);
-    let mut ret = Vec::new();
-    let mut cnt = 0;
-    reader.lines().for_each(|line| {
-        if cnt > 0 && cnt >= limit {
-            return
-        }
-        let data = line.unwrap();
-        let tmp: Vec<&str> = data.split(",").collect();
-        let u = tmp[0].parse::<i64>().unwrap();
-        let v = tmp[1].parse::<i64>().unwrap();
-        ret.push((u, v));
-        cnt += 1;
-    });
-    ret
+/// 每行"u,v"，limit为0时不限条数
+pub fn load_query(path: &str, limit: u32) -> Result<Vec<(i64, i64)>, LoadError> {
+    read_pairs(path, limit, |l| l.split(',').map(|x| x.trim()).collect())
 }
 
-pub fn load_query2(path: &str, limit: u32) -> Vec<(i64, i64)> {
-    let f = File::open(path).unwrap();
+/// 每行"u v"，limit为0时不限条数
+pub fn load_query2(path: &str, limit: u32) -> Result<Vec<(i64, i64)>, LoadError> {
+    read_pairs(path, limit, |l| l.split_whitespace().collect())
+}
+
+fn read_pairs<F: Fn(&str) -> Vec<&str>>(path: &str, limit: u32, split: F) -> Result<Vec<(i64, i64)>, LoadError> {
+    let f = File::open(path).map_err(|e| LoadError::io(path, e))?;
     let reader = BufReader::new(f);
     let mut ret = Vec::new();
-    let mut cnt = 0;
-    reader.lines().for_each(|line| {
-        if cnt > 0 && cnt >= limit {
-            return
+    for (idx, line) in reader.lines().enumerate() {
+        if limit > 0 && ret.len() >= limit as usize {
+            break;
+        }
+        let data = line.map_err(|e| LoadError::io(path, e))?;
+        let tmp = split(&data);
+        if tmp.len() < 2 {
+            return Err(LoadError::parse(idx + 1, format!("expected 2 ids, found {}", tmp.len())).with_path(path));
         }
-        let data = line.unwrap();
-        let tmp: Vec<&str> = data.split_whitespace().collect();
-        let u = tmp[0].parse::<i64>().unwrap();
-        let v = tmp[1].parse::<i64>().unwrap();
-        ret.push((u, v));
-        cnt += 1;
-    });
-    ret
+        let parse_id = |x: &str| x.parse::<i64>().map_err(|e| {
+            LoadError::parse(idx + 1, format!("invalid id {:?}: {}", x, e)).with_path(path)
+        });
+        ret.push((parse_id(tmp[0])?, parse_id(tmp[1])?));
+    }
+    Ok(ret)
 }
@@ -0,0 +1,266 @@
+//! 可配置的文本边表读取：分隔符、注释前缀、跳过表头、额外列（权重/时间戳/标签）、严格或宽松模式
+//!
+//! 默认格式是按空白分隔的"src dst"，跳过空行和以#、%开头的注释行（SNAP、Matrix Market的文件头），
+//! 严格模式遇到第一行坏数据就返回带行号的错误，宽松模式跳过坏行并在ReadStats里记下行号和原因
+
+use std::fs::File;
+use std::io::BufReader;
+use std::io::prelude::*;
+use crate::common::error::{Error, Result};
+
+/// 宽松模式最多保留多少条坏行的错误，更多的只计数
+pub const MAX_REPORTED_ERRORS: usize = 100;
+
+/// 列分隔符
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Delimiter {
+    /// 任意个空格或tab
+    Whitespace,
+    /// 单个字符，比如csv的','，字段两边的空白会去掉
+    Char(char),
+}
+
+/// 文本边表的格式，列号从0开始
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TextFormat {
+    pub delimiter: Delimiter,
+    /// 以这些前缀开头的行（去掉前导空白后）是注释
+    pub comment_prefixes: Vec<String>,
+    /// 文件开头跳过的行数，比如csv的表头
+    pub skip_lines: usize,
+    pub src_column: usize,
+    pub dst_column: usize,
+    pub weight_column: Option<usize>,
+    pub timestamp_column: Option<usize>,
+    pub label_column: Option<usize>,
+    /// true时遇到坏行直接返回错误，false时跳过坏行
+    pub strict: bool,
+}
+
+impl Default for TextFormat {
+    fn default() -> Self {
+        TextFormat {
+            delimiter: Delimiter::Whitespace,
+            comment_prefixes: vec!["#".to_owned(), "%".to_owned()],
+            skip_lines: 0,
+            src_column: 0,
+            dst_column: 1,
+            weight_column: None,
+            timestamp_column: None,
+            label_column: None,
+            strict: true,
+        }
+    }
+}
+
+/// 一行解析出的边，label借用这一行的内容
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TextEdge<'a> {
+    pub src: i64,
+    pub dst: i64,
+    pub weight: Option<f64>,
+    pub timestamp: Option<i64>,
+    pub label: Option<&'a str>,
+}
+
+/// 读完一个文件的统计
+#[derive(Debug, Default)]
+pub struct ReadStats {
+    /// 读过的行数，包括表头、注释和空行
+    pub lines: usize,
+    pub edges: usize,
+    /// 宽松模式下跳过的坏行数
+    pub skipped: usize,
+    /// 前MAX_REPORTED_ERRORS条坏行的错误，带文件名和行号
+    pub errors: Vec<Error>,
+}
+
+/// 读静态边和动态边两个文件的统计
+#[derive(Debug, Default)]
+pub struct LoadStats {
+    pub static_file: ReadStats,
+    pub dynamic_file: ReadStats,
+}
+
+impl LoadStats {
+    /// 两个文件一共跳过的坏行数
+    #[inline]
+    pub fn skipped(&self) -> usize {
+        self.static_file.skipped + self.dynamic_file.skipped
+    }
+}
+
+impl TextFormat {
+    /// 逗号分隔，第一行是表头
+    pub fn csv() -> Self {
+        TextFormat {
+            delimiter: Delimiter::Char(','),
+            skip_lines: 1,
+            ..Default::default()
+        }
+    }
+
+    /// tab分隔，没有表头
+    pub fn tsv() -> Self {
+        TextFormat {
+            delimiter: Delimiter::Char('\t'),
+            ..Default::default()
+        }
+    }
+
+    pub fn lenient(mut self) -> Self {
+        self.strict = false;
+        self
+    }
+
+    fn is_comment(&self, l: &str) -> bool {
+        let l = l.trim_start();
+        self.comment_prefixes.iter().any(|p| !p.is_empty() && l.starts_with(p.as_str()))
+    }
+
+    fn split<'a>(&self, l: &'a str) -> Vec<&'a str> {
+        match self.delimiter {
+            Delimiter::Whitespace => l.split_whitespace().collect(),
+            Delimiter::Char(c) => l.split(c).map(|x| x.trim()).collect(),
+        }
+    }
+
+    /// 需要的列数
+    fn columns(&self) -> usize {
+        let extra = [self.weight_column, self.timestamp_column, self.label_column];
+        extra.iter().flatten().chain([self.src_column, self.dst_column].iter())
+            .max().map_or(0, |x| x + 1)
+    }
+
+    /// 解析一行，空行和注释返回None，line从1开始，用于错误信息
+    pub fn parse_line<'a>(&self, l: &'a str, line: usize) -> Result<Option<TextEdge<'a>>> {
+        if l.trim().is_empty() || self.is_comment(l) {
+            return Ok(None);
+        }
+        let items = self.split(l);
+        let columns = self.columns();
+        if items.len() < columns {
+            return Err(Error::parse(line, format!("expected {} fields, found {}", columns, items.len())));
+        }
+        let parse_id = |x: &str| x.parse::<i64>()
+            .map_err(|e| Error::parse(line, format!("invalid id {:?}: {}", x, e)));
+        let weight = match self.weight_column {
+            Some(c) => Some(items[c].parse::<f64>()
+                .map_err(|e| Error::parse(line, format!("invalid weight {:?}: {}", items[c], e)))?),
+            None => None,
+        };
+        let timestamp = match self.timestamp_column {
+            Some(c) => Some(items[c].parse::<i64>()
+                .map_err(|e| Error::parse(line, format!("invalid timestamp {:?}: {}", items[c], e)))?),
+            None => None,
+        };
+        Ok(Some(TextEdge {
+            src: parse_id(items[self.src_column])?,
+            dst: parse_id(items[self.dst_column])?,
+            weight,
+            timestamp,
+            label: self.label_column.map(|c| items[c]),
+        }))
+    }
+
+    /// 按行读path，对每条边调用f
+    /// 严格模式返回第一行坏数据的错误，宽松模式跳过坏行，记在返回的ReadStats里
+    pub fn for_each_edge<F: FnMut(&TextEdge)>(&self, path: &str, mut f: F) -> Result<ReadStats> {
+        let file = File::open(path).map_err(|e| Error::io(path, e))?;
+        let reader = BufReader::new(file);
+        let mut stats = ReadStats::default();
+        for (idx, line) in reader.lines().enumerate() {
+            let l = line.map_err(|e| Error::io(path, e))?;
+            stats.lines += 1;
+            if idx < self.skip_lines {
+                continue;
+            }
+            match self.parse_line(&l, idx + 1) {
+                Ok(Some(edge)) => {
+                    stats.edges += 1;
+                    f(&edge);
+                }
+                Ok(None) => {}
+                Err(e) if self.strict => return Err(e.with_path(path)),
+                Err(e) => {
+                    stats.skipped += 1;
+                    if stats.errors.len() < MAX_REPORTED_ERRORS {
+                        stats.errors.push(e.with_path(path));
+                    }
+                }
+            }
+        }
+        if stats.skipped > 0 {
+            println!("skip {} bad lines in {}", stats.skipped, path);
+        }
+        Ok(stats)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::io::fs;
+
+    #[test]
+    fn test_parse_line() {
+        let format = TextFormat::default();
+        assert_eq!(format.parse_line("", 1).unwrap(), None);
+        assert_eq!(format.parse_line("  \t", 1).unwrap(), None);
+        assert_eq!(format.parse_line("# FromNodeId ToNodeId", 1).unwrap(), None);
+        assert_eq!(format.parse_line("%%MatrixMarket", 1).unwrap(), None);
+        let edge = format.parse_line(" 1\t-2  3", 1).unwrap().unwrap();
+        assert_eq!((edge.src, edge.dst, edge.weight), (1, -2, None));
+        let err = format.parse_line("1", 4).unwrap_err();
+        assert_eq!(err.to_string(), "line 4: expected 2 fields, found 1");
+
+        let format = TextFormat {
+            delimiter: Delimiter::Char(','),
+            src_column: 1,
+            dst_column: 0,
+            weight_column: Some(2),
+            timestamp_column: Some(3),
+            label_column: Some(4),
+            ..Default::default()
+        };
+        let edge = format.parse_line("2, 1 ,0.5,100,knows", 1).unwrap().unwrap();
+        assert_eq!(edge, TextEdge { src: 1, dst: 2, weight: Some(0.5), timestamp: Some(100), label: Some("knows") });
+        let edge = format.parse_line("2,1,1e3,-7,", 1).unwrap().unwrap();
+        assert_eq!((edge.weight, edge.timestamp, edge.label), (Some(1000.0), Some(-7), Some("")));
+        assert!(format.parse_line("2,1,x,100,a", 1).unwrap_err().to_string().contains("invalid weight"));
+        assert!(format.parse_line("2,1,1,1.5,a", 1).unwrap_err().to_string().contains("invalid timestamp"));
+        assert!(format.parse_line("2,1,1,1", 1).unwrap_err().to_string().contains("expected 5 fields"));
+    }
+
+    #[test]
+    fn test_for_each_edge() {
+        let dir = "test_dir_text_format";
+        fs::mkdir(dir).unwrap();
+        let path = fs::create_path(&[dir, "edges.csv"]);
+        std::fs::write(path.as_str(), "src,dst\n1,2\n\n# comment\n1,x\n3\n2,3\n").unwrap();
+
+        let mut edges = vec![];
+        let err = TextFormat::csv().for_each_edge(path.as_str(), |e| edges.push((e.src, e.dst))).unwrap_err();
+        assert_eq!(err.to_string(), format!("{}:5: invalid id \"x\": invalid digit found in string", path));
+        assert_eq!(edges, vec![(1, 2)]);
+
+        edges.clear();
+        let stats = TextFormat::csv().lenient().for_each_edge(path.as_str(), |e| edges.push((e.src, e.dst))).unwrap();
+        assert_eq!(edges, vec![(1, 2), (2, 3)]);
+        assert_eq!((stats.lines, stats.edges, stats.skipped), (7, 2, 2));
+        let lines: Vec<usize> = stats.errors.iter().map(|e| match e {
+            Error::Parse { line, .. } => *line,
+            _ => 0,
+        }).collect();
+        assert_eq!(lines, vec![5, 6]);
+
+        // 不跳过表头时表头就是坏行
+        let format = TextFormat { skip_lines: 0, ..TextFormat::csv() };
+        let err = format.for_each_edge(path.as_str(), |_| {}).unwrap_err();
+        assert!(matches!(err, Error::Parse { line: 1, .. }));
+
+        fs::rmr(dir).unwrap();
+        let err = TextFormat::default().for_each_edge(path.as_str(), |_| {}).unwrap_err();
+        assert!(err.is_io(std::io::ErrorKind::NotFound));
+    }
+}
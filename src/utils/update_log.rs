@@ -0,0 +1,516 @@
+//! 动态边更新的预写日志（WAL）和检查点，进程挂掉后从最后一个检查点加日志恢复
+//!
+//! 目录结构：
+//!   wal-<seq>         从第seq条更新开始的日志
+//!   checkpoint-<seq>  前seq条更新之后的完整图，build_binary_graph的格式
+//! 日志格式：header(20B): magic "PDFSWAL\0" | version(u32) | 第一条记录的seq(u64)
+//! 之后每条记录定长29B：crc32(u32) | seq(u64) | op(u8，1插入2删除) | src(i64) | dst(i64)，全部小端，crc覆盖crc之后的25B
+//! 写到一半挂掉时日志末尾可能是半条或者crc不对的记录，恢复时从那里截断
+
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use crate::common::error::{Error, Result};
+use crate::common::io::{fs, ByteBuffer, ByteOrder, FileChannel};
+use crate::common::io::checksum::crc32;
+use crate::structure::{EdgeUpdate, Graph, Snapshot, VersionedGraph};
+use super::build_binary::build_binary_graph;
+use super::load_binary::load_binary_graph;
+
+pub const LOG_MAGIC: [u8; 8] = *b"PDFSWAL\0";
+pub const LOG_FORMAT_VERSION: u32 = 1;
+const LOG_HEADER_SIZE: usize = 20;
+const RECORD_SIZE: usize = 29;
+const BUFFER_SIZE: usize = 1 << 20;
+const OP_INSERT: u8 = 1;
+const OP_DELETE: u8 = 2;
+const LOG_PREFIX: &str = "wal-";
+const CHECKPOINT_PREFIX: &str = "checkpoint-";
+const TMP_SUFFIX: &str = ".tmp";
+
+/// 扫描一个日志文件的结果
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LogScan {
+    /// header里第一条记录的seq，header不完整时为0
+    pub start_seq: u64,
+    /// 完整的记录数
+    pub records: u64,
+    /// 完整记录（含header）的字节数
+    pub valid_len: u64,
+    /// 末尾有没有被丢掉的半条或者坏记录
+    pub torn: bool,
+}
+
+/// 把buf读满或者读到文件末尾，返回是否读到了末尾
+fn fill(fc: &mut FileChannel, buf: &mut ByteBuffer) -> Result<bool> {
+    buf.compact();
+    while buf.position() < buf.capacity() {
+        if fc.read(buf)? == 0 {
+            buf.flip();
+            return Ok(true);
+        }
+    }
+    buf.flip();
+    Ok(false)
+}
+
+/// 按顺序把日志里完整的记录交给f(seq, update)，遇到半条或者crc不对的记录就停下
+/// header写了一半的文件当作没有记录
+pub fn scan_log<F: FnMut(u64, EdgeUpdate)>(path: &str, mut f: F) -> Result<LogScan> {
+    let mut fc = FileChannel::open(path)?;
+    let file_len = fc.len()?;
+    let mut buf = ByteBuffer::new(BUFFER_SIZE);
+    buf.flip();
+    let mut eof = fill(&mut fc, &mut buf).map_err(|e| e.with_path(path))?;
+    let mut scan = LogScan { start_seq: 0, records: 0, valid_len: 0, torn: false };
+    if buf.remaining() < LOG_HEADER_SIZE {
+        scan.torn = file_len > 0;
+        return Ok(scan);
+    }
+    if buf.get::<[u8; 8]>()? != LOG_MAGIC {
+        return Err(Error::format("bad magic, not an update log").with_path(path));
+    }
+    let version = buf.get_le::<u32>()?;
+    if version != LOG_FORMAT_VERSION {
+        return Err(Error::format(format!(
+            "unsupported format version {}, expected {}", version, LOG_FORMAT_VERSION)).with_path(path));
+    }
+    let mut expected = buf.get_le::<u64>()?;
+    scan.start_seq = expected;
+    scan.valid_len = LOG_HEADER_SIZE as u64;
+    loop {
+        if buf.remaining() < RECORD_SIZE {
+            if eof {
+                scan.torn = buf.has_remaining();
+                break;
+            }
+            eof = fill(&mut fc, &mut buf).map_err(|e| e.with_path(path))?;
+            continue;
+        }
+        let start = buf.position();
+        let stored = buf.get_le::<u32>()?;
+        if crc32(&buf.array()[start + 4..start + RECORD_SIZE]) != stored {
+            scan.torn = true;
+            break;
+        }
+        let seq = buf.get_le::<u64>()?;
+        let op = buf.get::<u8>()?;
+        let src = buf.get_le::<i64>()?;
+        let dst = buf.get_le::<i64>()?;
+        let update = match op {
+            OP_INSERT => EdgeUpdate::Insert(src, dst),
+            OP_DELETE => EdgeUpdate::Delete(src, dst),
+            _ => return Err(Error::format(format!("record {} has unknown op {}", seq, op)).with_path(path)),
+        };
+        if seq != expected {
+            return Err(Error::format(format!("expected record {}, found {}", expected, seq)).with_path(path));
+        }
+        f(seq, update);
+        expected += 1;
+        scan.records += 1;
+        scan.valid_len += RECORD_SIZE as u64;
+    }
+    Ok(scan)
+}
+
+/// 只追加的更新日志，每次append写完都落盘
+/// append失败时截回上一次成功落盘的长度，截不回去的话日志作废，之后的append都返回错误
+pub struct UpdateLog {
+    fc: FileChannel,
+    buf: ByteBuffer,
+    next_seq: u64,
+    /// 最后一次成功落盘后的文件长度
+    valid_len: u64,
+    poisoned: bool,
+}
+
+impl UpdateLog {
+    /// 新建（已存在就清空）一个从start_seq开始的日志
+    pub fn create<P: AsRef<Path>>(path: P, start_seq: u64) -> Result<Self> {
+        let mut fc = FileChannel::create(path)?;
+        let mut buf = ByteBuffer::new(BUFFER_SIZE);
+        buf.put(LOG_MAGIC)?;
+        buf.put_le(LOG_FORMAT_VERSION)?;
+        buf.put_le(start_seq)?;
+        buf.flip();
+        fc.write(&mut buf)?;
+        fc.sync()?;
+        Ok(UpdateLog { fc, buf, next_seq: start_seq, valid_len: LOG_HEADER_SIZE as u64, poisoned: false })
+    }
+
+    /// 接着已有的日志写：截掉末尾的半条或者坏记录，以追加模式打开
+    pub fn open(path: &str) -> Result<Self> {
+        let scan = scan_log(path, |_, _| {})?;
+        if scan.valid_len == 0 {
+            return Err(Error::format("header is torn").with_path(path));
+        }
+        let mut fc = FileChannel::append(path)?;
+        if scan.torn {
+            fc.truncate(scan.valid_len)?;
+            fc.sync()?;
+        }
+        Ok(UpdateLog {
+            fc,
+            buf: ByteBuffer::new(BUFFER_SIZE),
+            next_seq: scan.start_seq + scan.records,
+            valid_len: scan.valid_len,
+            poisoned: false,
+        })
+    }
+
+    /// 下一条记录的seq，即到目前为止写过的更新总数
+    #[inline]
+    pub fn next_seq(&self) -> u64 {
+        self.next_seq
+    }
+
+    #[inline]
+    pub fn filename(&self) -> &str {
+        self.fc.filename()
+    }
+
+    /// 追加一批更新并落盘，返回之后的next_seq
+    /// 失败时这一批一条都不算：已经写进文件的部分截掉，next_seq不变
+    pub fn append(&mut self, updates: &[EdgeUpdate]) -> Result<u64> {
+        if self.poisoned {
+            return Err(Error::new("update log is unusable after a failed rollback".to_owned()).with_path(self.fc.filename()));
+        }
+        match self.write_records(updates) {
+            Ok(seq) => {
+                self.next_seq = seq;
+                self.valid_len += (updates.len() * RECORD_SIZE) as u64;
+                Ok(seq)
+            }
+            Err(e) => {
+                self.rollback();
+                Err(e)
+            }
+        }
+    }
+
+    /// 丢掉缓冲里没写出的记录，文件截回valid_len，截不回去就作废
+    fn rollback(&mut self) {
+        self.buf.clear();
+        let ret = self.fc.truncate(self.valid_len).and_then(|_| self.fc.sync());
+        if ret.is_err() {
+            self.poisoned = true;
+        }
+    }
+
+    fn write_records(&mut self, updates: &[EdgeUpdate]) -> Result<u64> {
+        let mut seq = self.next_seq;
+        for update in updates.iter() {
+            if self.buf.remaining() < RECORD_SIZE {
+                self.buf.flip();
+                self.fc.write(&mut self.buf)?;
+            }
+            let (op, src, dst) = match *update {
+                EdgeUpdate::Insert(u, v) => (OP_INSERT, u, v),
+                EdgeUpdate::Delete(u, v) => (OP_DELETE, u, v),
+            };
+            let start = self.buf.position();
+            self.buf.put_le(0u32)?;
+            self.buf.put_le(seq)?;
+            self.buf.put(op)?;
+            self.buf.put_le(src)?;
+            self.buf.put_le(dst)?;
+            let crc = crc32(&self.buf.array()[start + 4..start + RECORD_SIZE]);
+            self.buf.put_at_with(start, crc, ByteOrder::LittleEndian)?;
+            seq += 1;
+        }
+        self.buf.flip();
+        self.fc.write(&mut self.buf)?;
+        self.fc.sync()?;
+        Ok(seq)
+    }
+}
+
+/// 目录里名字是prefix<seq>的项，按seq排序
+fn list_seq(dir: &str, prefix: &str) -> Result<Vec<(u64, String)>> {
+    let mut ret = Vec::new();
+    for path in fs::ls(dir).map_err(|e| Error::io(dir, e))? {
+        let name = match Path::new(&path).file_name() {
+            Some(name) => name.to_string_lossy().into_owned(),
+            None => continue,
+        };
+        if let Some(seq) = name.strip_prefix(prefix).and_then(|x| x.parse::<u64>().ok()) {
+            ret.push((seq, path));
+        }
+    }
+    ret.sort_unstable();
+    Ok(ret)
+}
+
+/// 从dir里最后一个检查点（没有就是base()）开始重放日志，返回图和恢复到的seq
+/// 日志中间缺了记录时报错
+pub fn recover<F: FnOnce() -> Result<Graph>>(dir: &str, base: F) -> Result<(Graph, u64)> {
+    let (mut graph, start) = match list_seq(dir, CHECKPOINT_PREFIX)?.pop() {
+        Some((seq, path)) => (load_binary_graph(path)?, seq),
+        None => (base()?, 0),
+    };
+    let mut next = start;
+    for (first, path) in list_seq(dir, LOG_PREFIX)? {
+        if first > next {
+            return Err(Error::format(format!("updates {}..{} are missing", next, first)).with_path(&path));
+        }
+        let scan = scan_log(&path, |seq, update| {
+            if seq < next {
+                return;
+            }
+            match update {
+                EdgeUpdate::Insert(u, v) => graph.add_directed_edge(u, v),
+                EdgeUpdate::Delete(u, v) => {
+                    graph.remove_directed_edge(u, v);
+                }
+            }
+            next = seq + 1;
+        })?;
+        if scan.torn {
+            println!("{} is torn after {} records", path, scan.records);
+        }
+    }
+    Ok((graph, next))
+}
+
+/// 写之前先记日志的VersionedGraph
+/// 打开时从检查点和日志恢复，最后一个日志能接着写就接着写，否则另起一个新日志，旧的日志留到下一个检查点再删
+pub struct LoggedGraph {
+    dir: String,
+    graph: VersionedGraph,
+    log: Mutex<UpdateLog>,
+}
+
+impl LoggedGraph {
+    /// 没有检查点时从空图开始
+    pub fn open(dir: &str) -> Result<Self> {
+        LoggedGraph::open_with(dir, || Ok(Graph::empty()))
+    }
+
+    /// 没有检查点时用base()作为初始图，比如加载静态和动态数据
+    pub fn open_with<F: FnOnce() -> Result<Graph>>(dir: &str, base: F) -> Result<Self> {
+        fs::mkdir(dir).map_err(|e| Error::io(dir, e))?;
+        let (graph, next) = recover(dir, base)?;
+        let log = match list_seq(dir, LOG_PREFIX)?.pop() {
+            Some((_, path)) => match UpdateLog::open(&path) {
+                Ok(log) if log.next_seq() == next => Some(log),
+                _ => None,
+            },
+            None => None,
+        };
+        let log = match log {
+            Some(log) => log,
+            None => UpdateLog::create(log_path(dir, next), next)?,
+        };
+        Ok(LoggedGraph {
+            dir: dir.to_owned(),
+            graph: VersionedGraph::new(graph),
+            log: Mutex::new(log),
+        })
+    }
+
+    #[inline]
+    pub fn graph(&self) -> &VersionedGraph {
+        &self.graph
+    }
+
+    #[inline]
+    pub fn pin(&self) -> Arc<Snapshot> {
+        self.graph.pin()
+    }
+
+    /// 写过的更新总数
+    pub fn seq(&self) -> u64 {
+        self.log.lock().unwrap().next_seq()
+    }
+
+    /// 先写日志再应用，返回新版本号；日志写失败时图不变
+    pub fn apply(&self, updates: &[EdgeUpdate]) -> Result<u64> {
+        let mut log = self.log.lock().unwrap();
+        log.append(updates)?;
+        Ok(self.graph.apply(updates))
+    }
+
+    pub fn add_directed_edge(&self, id1: i64, id2: i64) -> Result<u64> {
+        self.apply(&[EdgeUpdate::Insert(id1, id2)])
+    }
+
+    pub fn remove_directed_edge(&self, id1: i64, id2: i64) -> Result<u64> {
+        self.apply(&[EdgeUpdate::Delete(id1, id2)])
+    }
+
+    /// 把当前的图写成检查点，换一个新日志，删掉更早的检查点和日志，返回检查点的seq
+    /// 先写到临时目录，检查点里的文件都fsync之后再改名，改名和新日志在目录上fsync落盘之后才删旧的，
+    /// 任何一步挂掉都还能从旧检查点加日志恢复
+    pub fn checkpoint(&self) -> Result<u64> {
+        let mut log = self.log.lock().unwrap();
+        let seq = log.next_seq();
+        let path = fs::create_path(&[self.dir.as_str(), &format!("{}{}", CHECKPOINT_PREFIX, seq)]);
+        if !fs::exists(&path) {
+            let tmp = format!("{}{}", path, TMP_SUFFIX);
+            if fs::exists(&tmp) {
+                fs::rmr(&tmp).map_err(|e| Error::io(&tmp, e))?;
+            }
+            build_binary_graph(&self.pin().to_graph(), tmp.clone())?;
+            fs::sync_dir_all(&tmp).map_err(|e| Error::io(&tmp, e))?;
+            std::fs::rename(&tmp, &path).map_err(|e| Error::io(&tmp, e))?;
+        }
+        if log.filename() != log_path(&self.dir, seq) {
+            *log = UpdateLog::create(log_path(&self.dir, seq), seq)?;
+        }
+        fs::sync(&self.dir).map_err(|e| Error::io(&self.dir, e))?;
+        for (s, p) in list_seq(&self.dir, CHECKPOINT_PREFIX)? {
+            if s < seq {
+                fs::rmr(&p).map_err(|e| Error::io(&p, e))?;
+            }
+        }
+        for (s, p) in list_seq(&self.dir, LOG_PREFIX)? {
+            if s < seq {
+                fs::rm(&p).map_err(|e| Error::io(&p, e))?;
+            }
+        }
+        Ok(seq)
+    }
+}
+
+fn log_path(dir: &str, seq: u64) -> String {
+    fs::create_path(&[dir, &format!("{}{}", LOG_PREFIX, seq)])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs::OpenOptions;
+    use std::io::Write;
+    use crate::structure::GraphView;
+
+    fn edges<G: GraphView>(graph: &G) -> Vec<(i64, i64)> {
+        let mut ret: Vec<(i64, i64)> = graph.vertices().iter()
+            .flat_map(|u| graph.out_neighbors(*u).iter().map(move |v| (*u, *v)).collect::<Vec<_>>())
+            .collect();
+        ret.sort_unstable();
+        ret
+    }
+
+    #[test]
+    fn test_update_log() {
+        let dir = "test_update_log";
+        fs::mkdir(dir).unwrap();
+        let path = fs::create_path(&[dir, "wal-5"]);
+        let updates: Vec<EdgeUpdate> = (0..1000)
+            .map(|i| if i % 3 == 0 { EdgeUpdate::Delete(i, -i) } else { EdgeUpdate::Insert(i, i + 1) })
+            .collect();
+        let mut log = UpdateLog::create(&path, 5).unwrap();
+        assert_eq!(log.append(&updates[..10]).unwrap(), 15);
+        assert_eq!(log.append(&updates[10..]).unwrap(), 1005);
+        drop(log);
+
+        let mut read = Vec::new();
+        let scan = scan_log(&path, |seq, u| read.push((seq, u))).unwrap();
+        assert_eq!(scan, LogScan { start_seq: 5, records: 1000, valid_len: (LOG_HEADER_SIZE + 1000 * RECORD_SIZE) as u64, torn: false });
+        assert_eq!(read, updates.iter().enumerate().map(|(i, u)| (i as u64 + 5, *u)).collect::<Vec<_>>());
+
+        // 半条记录
+        OpenOptions::new().append(true).open(&path).unwrap().write_all(&[1, 2, 3]).unwrap();
+        let scan = scan_log(&path, |_, _| {}).unwrap();
+        assert_eq!((scan.records, scan.torn), (1000, true));
+
+        // 改坏一条记录，从那里截断
+        let mut data = std::fs::read(&path).unwrap();
+        data[LOG_HEADER_SIZE + 10 * RECORD_SIZE + 20] ^= 1;
+        std::fs::write(&path, &data).unwrap();
+        let scan = scan_log(&path, |_, _| {}).unwrap();
+        assert_eq!((scan.records, scan.torn), (10, true));
+
+        std::fs::write(&path, &LOG_MAGIC[..4]).unwrap();
+        assert_eq!(scan_log(&path, |_, _| {}).unwrap().records, 0);
+        std::fs::write(&path, [0u8; 40]).unwrap();
+        assert!(scan_log(&path, |_, _| {}).is_err());
+        fs::rmr(dir).unwrap();
+    }
+
+    #[test]
+    fn test_update_log_failed_append() {
+        let dir = "test_update_log_failed_append";
+        fs::mkdir(dir).unwrap();
+        let path = fs::create_path(&[dir, "wal-0"]);
+        let updates: Vec<EdgeUpdate> = (0..10).map(|i| EdgeUpdate::Insert(i, i + 1)).collect();
+        let mut log = UpdateLog::create(&path, 0).unwrap();
+        assert_eq!(log.append(&updates[..3]).unwrap(), 3);
+
+        // 一批写出了一部分之后失败：文件里留下seq 3开始的记录，缓冲里还有没写出的
+        let other = fs::create_path(&[dir, "wal-3"]);
+        UpdateLog::create(&other, 3).unwrap().append(&updates[3..10]).unwrap();
+        let mut f = OpenOptions::new().append(true).open(&path).unwrap();
+        f.write_all(&std::fs::read(&other).unwrap()[LOG_HEADER_SIZE..]).unwrap();
+        log.buf.put_le(7u64).unwrap();
+        log.rollback();
+        assert!(!log.poisoned);
+        assert_eq!(log.next_seq(), 3);
+        assert_eq!(std::fs::metadata(&path).unwrap().len(), (LOG_HEADER_SIZE + 3 * RECORD_SIZE) as u64);
+        // 截回去之后seq接得上
+        assert_eq!(log.append(&updates[3..5]).unwrap(), 5);
+        let mut seqs = Vec::new();
+        let scan = scan_log(&path, |seq, _| seqs.push(seq)).unwrap();
+        assert_eq!((scan.records, scan.torn), (5, false));
+        assert_eq!(seqs, (0..5).collect::<Vec<_>>());
+
+        // 只读打开，写不进去也截不回去，日志作废
+        let mut log = UpdateLog { fc: FileChannel::open(&path).unwrap(), ..UpdateLog::open(&path).unwrap() };
+        assert!(log.append(&updates[5..6]).is_err());
+        assert!(log.poisoned);
+        assert_eq!(log.next_seq(), 5);
+        assert!(log.append(&updates[5..6]).unwrap_err().to_string().contains("unusable"));
+        assert_eq!(scan_log(&path, |_, _| {}).unwrap().records, 5);
+        fs::rmr(dir).unwrap();
+    }
+
+    #[test]
+    fn test_logged_graph_recovery() {
+        let dir = "test_logged_graph_recovery";
+        let _ = fs::rmr(dir);
+        let expected = {
+            let g = LoggedGraph::open_with(dir, || Ok(Graph::test())).unwrap();
+            g.add_directed_edge(3, 4).unwrap();
+            g.apply(&[EdgeUpdate::Insert(2, 4), EdgeUpdate::Delete(1, 2)]).unwrap();
+            assert_eq!(g.seq(), 3);
+            edges(g.pin().as_ref())
+        };
+
+        // 重启：没有检查点，从base重放日志
+        let g = LoggedGraph::open_with(dir, || Ok(Graph::test())).unwrap();
+        assert_eq!(g.seq(), 3);
+        assert_eq!(edges(g.pin().as_ref()), expected);
+
+        assert_eq!(g.checkpoint().unwrap(), 3);
+        assert_eq!(g.checkpoint().unwrap(), 3);
+        g.add_directed_edge(7, 8).unwrap();
+        g.remove_directed_edge(3, 4).unwrap();
+        let expected = edges(g.pin().as_ref());
+        let log = g.log.lock().unwrap().filename().to_owned();
+        drop(g);
+        assert_eq!(list_seq(dir, LOG_PREFIX).unwrap().len(), 1);
+        assert_eq!(list_seq(dir, CHECKPOINT_PREFIX).unwrap().len(), 1);
+
+        // 写最后一条时挂掉，留下半条记录；有检查点时不再调用base
+        OpenOptions::new().append(true).open(&log).unwrap().write_all(&[0u8; RECORD_SIZE - 1]).unwrap();
+        let g = LoggedGraph::open_with(dir, || panic!("should load the checkpoint")).unwrap();
+        assert_eq!(g.seq(), 5);
+        // 截掉半条记录后接着写原来的日志
+        assert_eq!(g.log.lock().unwrap().filename(), log);
+        assert_eq!(std::fs::metadata(&log).unwrap().len(), (LOG_HEADER_SIZE + 2 * RECORD_SIZE) as u64);
+        assert_eq!(edges(g.pin().as_ref()), expected);
+        assert!(g.pin().out_neighbors(7).contains(&8));
+
+        g.add_directed_edge(8, 9).unwrap();
+        assert_eq!(g.checkpoint().unwrap(), 6);
+        drop(g);
+        let (graph, seq) = recover(dir, || Ok(Graph::empty())).unwrap();
+        assert_eq!(seq, 6);
+        assert!(graph.has_edge(8, 9));
+        assert_eq!(list_seq(dir, CHECKPOINT_PREFIX).unwrap().len(), 1);
+
+        // 日志中间缺了记录
+        UpdateLog::create(log_path(dir, 10), 10).unwrap().append(&[EdgeUpdate::Insert(1, 1)]).unwrap();
+        assert!(recover(dir, || Ok(Graph::empty())).is_err());
+        fs::rmr(dir).unwrap();
+    }
+}